@@ -0,0 +1,158 @@
+//! Report of problems found in the markup
+//!
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{descendants, element_attrs, get_attr, is_element, Document};
+
+/// Tags which are obsolete in HTML living standard
+const OBSOLETE: &[&str] = &[
+    "acronym",
+    "applet",
+    "basefont",
+    "bgsound",
+    "big",
+    "blink",
+    "center",
+    "dir",
+    "font",
+    "frame",
+    "frameset",
+    "isindex",
+    "keygen",
+    "listing",
+    "marquee",
+    "menuitem",
+    "multicol",
+    "nextid",
+    "nobr",
+    "noembed",
+    "noframes",
+    "plaintext",
+    "rb",
+    "rtc",
+    "spacer",
+    "strike",
+    "tt",
+    "xmp",
+];
+
+/// Parser error messages meaning an open element was closed implicitly,
+/// detailed messages of `DocumentBuilder::exact_errors` start the same way
+const UNCLOSED: &[&str] = &["Unexpected open element", "Unexpected open tag"];
+
+/// Parser error messages meaning elements were closed in wrong order
+const MISNESTED: &[&str] = &[
+    "Formatting element not",
+    "Found special tag while closing generic tag",
+    "No matching tag to close",
+    "Closing wrong heading tag",
+];
+
+/// Problem in the markup found by `Document::audit`
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum Warning {
+    /// Element was left open and parser closed it implicitly
+    UnclosedTag {
+        /// Line parser was at when it closed the element
+        line: u64,
+        /// Description of the problem given by the parser
+        message: String,
+    },
+    /// End tags did not match open elements and parser had to rearrange the tree
+    MisnestedTag {
+        /// Line parser was at when it found the end tag
+        line: u64,
+        /// Description of the problem given by the parser
+        message: String,
+    },
+    /// Several elements share the same id
+    DuplicateId {
+        /// Shared id
+        id: String,
+        /// Css paths of the elements in document order
+        paths: Vec<String>,
+    },
+    /// Element is obsolete and should not be used
+    ObsoleteTag {
+        /// Tag name
+        tag: String,
+        /// Css path of the element
+        path: String,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnclosedTag { line, message } => {
+                write!(f, "line {}: unclosed tag: {}", line, message)
+            }
+            Warning::MisnestedTag { line, message } => {
+                write!(f, "line {}: misnested tag: {}", line, message)
+            }
+            Warning::DuplicateId { id, paths } => {
+                write!(f, "duplicate id {:?}: {}", id, paths.join(", "))
+            }
+            Warning::ObsoleteTag { tag, path } => write!(f, "obsolete tag <{}>: {}", tag, path),
+        }
+    }
+}
+
+pub(crate) fn audit(doc: &Document) -> Vec<Warning> {
+    let mut warnings = vec![];
+
+    for error in doc.context.errors.iter() {
+        let starts = |prefixes: &[&str]| prefixes.iter().any(|p| error.message.starts_with(p));
+        if starts(UNCLOSED) {
+            warnings.push(Warning::UnclosedTag {
+                line: error.line,
+                message: error.message.clone(),
+            });
+        } else if starts(MISNESTED) {
+            warnings.push(Warning::MisnestedTag {
+                line: error.line,
+                message: error.message.clone(),
+            });
+        }
+    }
+
+    let elements: Vec<_> = descendants(&doc.doc.document)
+        .into_iter()
+        .filter(is_element)
+        .map(|node| doc.element(&node))
+        .collect();
+
+    // ids in order of their first occurrence
+    let mut ids: Vec<String> = vec![];
+    let mut paths: HashMap<String, Vec<String>> = HashMap::new();
+    for el in elements.iter() {
+        let id = match element_attrs(&el.handle).and_then(|attrs| get_attr(&attrs, "id")) {
+            Some(id) => id,
+            None => continue,
+        };
+        let entry = paths.entry(id.clone()).or_insert_with(|| {
+            ids.push(id);
+            vec![]
+        });
+        entry.push(el.css_path());
+    }
+    for id in ids {
+        if paths[&id].len() > 1 {
+            let paths = paths.remove(&id).unwrap_or_default();
+            warnings.push(Warning::DuplicateId { id, paths });
+        }
+    }
+
+    for el in elements.iter() {
+        if let Some(tag) = el.tag() {
+            if OBSOLETE.contains(&tag.as_str()) {
+                let path = el.css_path();
+                warnings.push(Warning::ObsoleteTag { tag, path });
+            }
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,359 @@
+//! Compact binary format of parsed documents, available with `binary` feature
+//!
+//! Layout is a header followed by nodes in document order, each node is written
+//! as its kind, data, source information and number of children followed by the children.
+//! Content of `<template>` elements is written before their children.
+//!
+use html5ever::tree_builder::QuirksMode;
+use markup5ever::{Attribute, LocalName, Namespace, Prefix, QualName};
+use markup5ever_arcdom::{ArcDom, Handle, Node, NodeData};
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::sync::Arc;
+use url::Url;
+
+use super::{append_child, Context, Document, Error};
+
+const MAGIC: &[u8] = b"CRAB";
+const VERSION: u8 = 2;
+
+const DOCUMENT: u8 = 0;
+const DOCTYPE: u8 = 1;
+const TEXT: u8 = 2;
+const COMMENT: u8 = 3;
+const ELEMENT: u8 = 4;
+const PROCESSING_INSTRUCTION: u8 = 5;
+
+pub(crate) fn encode(doc: &Document) -> Vec<u8> {
+    let mut writer = Writer(MAGIC.to_vec());
+    writer.u8(VERSION);
+    writer.u8(match doc.doc.quirks_mode {
+        QuirksMode::NoQuirks => 0,
+        QuirksMode::LimitedQuirks => 1,
+        QuirksMode::Quirks => 2,
+    });
+    writer.opt_str(doc.context.base_url.as_ref().map(|url| url.as_str()));
+
+    let mut stack = vec![Arc::clone(&doc.doc.document)];
+    while let Some(node) = stack.pop() {
+        writer.node(&node, &doc.context);
+
+        let children = node.children.borrow();
+        stack.extend(children.iter().rev().cloned());
+        if let NodeData::Element {
+            template_contents: Some(ref contents),
+            ..
+        } = node.data
+        {
+            stack.extend(contents.children.borrow().iter().rev().cloned());
+        }
+    }
+
+    writer.0
+}
+
+pub(crate) fn decode(bytes: &[u8]) -> Result<Document, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC || reader.u8()? != VERSION {
+        return Err(Error::InvalidBinary);
+    }
+
+    let quirks_mode = match reader.u8()? {
+        0 => QuirksMode::NoQuirks,
+        1 => QuirksMode::LimitedQuirks,
+        2 => QuirksMode::Quirks,
+        _ => return Err(Error::InvalidBinary),
+    };
+    let mut context = Context::default();
+    if let Some(url) = reader.opt_str()? {
+        context.base_url = Some(Url::parse(&url).map_err(|_| Error::InvalidBinary)?);
+    }
+
+    let (document, children, _) = reader.node(&mut context)?;
+    if !matches!(document.data, NodeData::Document) {
+        return Err(Error::InvalidBinary);
+    }
+
+    // nodes waiting for their children with number of children left to read
+    let mut stack = vec![(Arc::clone(&document), children)];
+    while let Some((parent, left)) = stack.last_mut() {
+        if *left == 0 {
+            stack.pop();
+            continue;
+        }
+        *left -= 1;
+        let parent = Arc::clone(parent);
+
+        let (node, children, template) = reader.node(&mut context)?;
+        append_child(&parent, Arc::clone(&node));
+        stack.push((node, children));
+        if let Some((contents, children)) = template {
+            stack.push((contents, children));
+        }
+    }
+
+    if reader.pos != bytes.len() {
+        return Err(Error::InvalidBinary);
+    }
+
+    Ok(Document {
+        doc: ArcDom {
+            document,
+            errors: vec![],
+            quirks_mode,
+        },
+        context: Rc::new(context),
+    })
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    /// LEB128 encoded unsigned integer
+    fn uint(&mut self, mut value: u64) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                self.0.push(byte);
+                return;
+            }
+            self.0.push(byte | 0x80);
+        }
+    }
+
+    fn str(&mut self, value: &str) {
+        self.uint(value.len() as u64);
+        self.0.extend_from_slice(value.as_bytes());
+    }
+
+    fn opt_str(&mut self, value: Option<&str>) {
+        match value {
+            Some(value) => {
+                self.u8(1);
+                self.str(value);
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn name(&mut self, name: &QualName) {
+        self.opt_str(name.prefix.as_deref());
+        self.str(&name.ns);
+        self.str(&name.local);
+    }
+
+    fn node(&mut self, handle: &Handle, context: &Context) {
+        match handle.data {
+            NodeData::Document => self.u8(DOCUMENT),
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => {
+                self.u8(DOCTYPE);
+                self.str(name);
+                self.str(public_id);
+                self.str(system_id);
+            }
+            NodeData::Text { ref contents } => {
+                self.u8(TEXT);
+                self.str(&contents.borrow());
+            }
+            NodeData::Comment { ref contents } => {
+                self.u8(COMMENT);
+                self.str(contents);
+            }
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ref template_contents,
+                mathml_annotation_xml_integration_point,
+            } => {
+                self.u8(ELEMENT);
+                self.name(name);
+                let attrs = attrs.borrow();
+                self.uint(attrs.len() as u64);
+                for attr in attrs.iter() {
+                    self.name(&attr.name);
+                    self.str(&attr.value);
+                }
+                self.u8(mathml_annotation_xml_integration_point as u8);
+                match template_contents {
+                    Some(contents) => {
+                        self.u8(1);
+                        self.uint(contents.children.borrow().len() as u64);
+                    }
+                    None => self.u8(0),
+                }
+            }
+            NodeData::ProcessingInstruction {
+                ref target,
+                ref contents,
+            } => {
+                self.u8(PROCESSING_INSTRUCTION);
+                self.str(target);
+                self.str(contents);
+            }
+        }
+
+        match context.source(handle) {
+            Some(source) => {
+                self.u8(1);
+                self.uint(source.line);
+                self.uint(source.column);
+                self.uint(source.range.start as u64);
+                self.uint(source.range.end as u64);
+                match source.raw_attrs {
+                    Some(ref attrs) => {
+                        self.u8(1);
+                        self.uint(attrs.len() as u64);
+                        for (name, value) in attrs.iter() {
+                            self.str(name);
+                            self.str(value);
+                        }
+                    }
+                    None => self.u8(0),
+                }
+            }
+            None => self.u8(0),
+        }
+
+        self.uint(handle.children.borrow().len() as u64);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], Error> {
+        let end = self.pos.checked_add(len).ok_or(Error::InvalidBinary)?;
+        let res = self.bytes.get(self.pos..end).ok_or(Error::InvalidBinary)?;
+        self.pos = end;
+        Ok(res)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::InvalidBinary),
+        }
+    }
+
+    fn uint(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::InvalidBinary)
+    }
+
+    fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.uint()?).map_err(|_| Error::InvalidBinary)
+    }
+
+    fn str(&mut self) -> Result<String, Error> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| Error::InvalidBinary)
+    }
+
+    fn opt_str(&mut self) -> Result<Option<String>, Error> {
+        match self.bool()? {
+            true => Ok(Some(self.str()?)),
+            false => Ok(None),
+        }
+    }
+
+    fn name(&mut self) -> Result<QualName, Error> {
+        let prefix = self.opt_str()?.map(Prefix::from);
+        let ns = Namespace::from(self.str()?);
+        let local = LocalName::from(self.str()?);
+        Ok(QualName::new(prefix, ns, local))
+    }
+
+    /// Read node with its source information, returns the node, number of its children
+    /// and content of the template with number of its children
+    #[allow(clippy::type_complexity)]
+    fn node(
+        &mut self,
+        context: &mut Context,
+    ) -> Result<(Handle, usize, Option<(Handle, usize)>), Error> {
+        let mut template = None;
+
+        let data = match self.u8()? {
+            DOCUMENT => NodeData::Document,
+            DOCTYPE => NodeData::Doctype {
+                name: self.str()?.into(),
+                public_id: self.str()?.into(),
+                system_id: self.str()?.into(),
+            },
+            TEXT => NodeData::Text {
+                contents: RefCell::new(self.str()?.into()),
+            },
+            COMMENT => NodeData::Comment {
+                contents: self.str()?.into(),
+            },
+            ELEMENT => {
+                let name = self.name()?;
+                let mut attrs = vec![];
+                for _ in 0..self.usize()? {
+                    attrs.push(Attribute {
+                        name: self.name()?,
+                        value: self.str()?.into(),
+                    });
+                }
+                let mathml_annotation_xml_integration_point = self.bool()?;
+                if self.bool()? {
+                    template = Some((Node::new(NodeData::Document), self.usize()?));
+                }
+
+                NodeData::Element {
+                    name,
+                    attrs: RefCell::new(attrs),
+                    template_contents: template.as_ref().map(|(contents, _)| Arc::clone(contents)),
+                    mathml_annotation_xml_integration_point,
+                }
+            }
+            PROCESSING_INSTRUCTION => NodeData::ProcessingInstruction {
+                target: self.str()?.into(),
+                contents: self.str()?.into(),
+            },
+            _ => return Err(Error::InvalidBinary),
+        };
+        let node = Node::new(data);
+
+        if self.bool()? {
+            let line = self.uint()?;
+            let column = self.uint()?;
+            let range = self.usize()?..self.usize()?;
+            context.set_source(&node, line, column, range);
+            if self.bool()? {
+                let mut attrs = vec![];
+                for _ in 0..self.usize()? {
+                    attrs.push((self.str()?, self.str()?));
+                }
+                context.set_raw_attrs(&node, attrs);
+            }
+        }
+
+        Ok((node, self.usize()?, template))
+    }
+}
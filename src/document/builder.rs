@@ -0,0 +1,467 @@
+//! Configurable construction of documents
+//!
+use html5ever::driver::ParseOpts;
+use html5ever::tree_builder::TreeBuilderOpts;
+use markup5ever::{namespace_url, ns, LocalName, QualName};
+use markup5ever_arcdom::{ArcDom, NodeData};
+use std::io::{self, Read};
+use std::rc::Rc;
+use std::sync::Arc;
+use url::Url;
+
+use super::sink::{self, Limits};
+use super::{
+    append_child, element_attrs, get_attr, Context, Document, Error, ParseError, Selector,
+};
+
+/// Builder of documents with custom parser options
+///
+/// # Example
+/// ```
+/// use crabquery::DocumentBuilder;
+///
+/// let html = "<div><noscript><img src='/pixel.gif'></noscript></div>";
+/// let doc = DocumentBuilder::new().scripting_enabled(false).parse(html);
+///
+/// assert_eq!(doc.select("noscript > img").len(), 1);
+/// ```
+#[derive(Clone)]
+pub struct DocumentBuilder {
+    //{{{
+    opts: ParseOpts,
+    url: Option<Url>,
+    limits: Limits,
+    keep: Vec<String>,
+    inline_templates: bool,
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentBuilder {
+    /// Create builder with default options, same ones `Document::from` uses
+    pub fn new() -> Self {
+        Self {
+            opts: ParseOpts {
+                tree_builder: TreeBuilderOpts {
+                    drop_doctype: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            url: None,
+            limits: Limits::default(),
+            keep: vec![],
+            inline_templates: false,
+        }
+    }
+
+    /// Make content of `<template>` elements their children so it can be selected
+    /// like the rest of the document, disabled by default.
+    /// Otherwise it is available with `Element::template_contents`.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::DocumentBuilder;
+    ///
+    /// let html = "<template><li class='item'>one</li></template>";
+    /// let doc = DocumentBuilder::new().inline_templates(true).parse(html);
+    ///
+    /// assert_eq!(doc.select("template > .item").len(), 1);
+    /// ```
+    pub fn inline_templates(mut self, value: bool) -> Self {
+        self.inline_templates = value;
+        self
+    }
+
+    /// Drop doctype node instead of adding it to the document, enabled by default
+    pub fn drop_doctype(mut self, value: bool) -> Self {
+        self.opts.tree_builder.drop_doctype = value;
+        self
+    }
+
+    /// Parse as if scripting was enabled, enabled by default.
+    /// When disabled `<noscript>` content is parsed into elements instead of a text,
+    /// otherwise it can be parsed later with `Element::noscript_contents`.
+    pub fn scripting_enabled(mut self, value: bool) -> Self {
+        self.opts.tree_builder.scripting_enabled = value;
+        self
+    }
+
+    /// Report detailed descriptions of parse errors, disabled by default as it is slower
+    pub fn exact_errors(mut self, value: bool) -> Self {
+        self.opts.tokenizer.exact_errors = value;
+        self.opts.tree_builder.exact_errors = value;
+        self
+    }
+
+    /// Parse input as `<iframe srcdoc>` document, disabled by default
+    pub fn iframe_srcdoc(mut self, value: bool) -> Self {
+        self.opts.tree_builder.iframe_srcdoc = value;
+        self
+    }
+
+    /// Discard byte order mark at the start of the input, enabled by default
+    pub fn discard_bom(mut self, value: bool) -> Self {
+        self.opts.tokenizer.discard_bom = value;
+        self
+    }
+
+    /// Set url the document was loaded from, relative urls are resolved against it
+    /// or against `<base href>` of the document which itself is resolved against the url
+    ///
+    /// # Arguments
+    /// * `url` - absolute url
+    pub fn base_url(mut self, url: &str) -> Result<Self, Error> {
+        self.url = Some(Url::parse(url).map_err(Error::InvalidUrl)?);
+        Ok(self)
+    }
+
+    /// Limit size of the input in bytes, unlimited by default.
+    /// Parsing stops once the limit is exceeded, see `try_parse` for details.
+    ///
+    /// # Arguments
+    /// * `max` - maximum number of bytes
+    pub fn max_input_size(mut self, max: usize) -> Self {
+        self.limits.input_size = Some(max);
+        self
+    }
+
+    /// Limit nesting depth of elements, unlimited by default.
+    /// Parsing stops once the limit is exceeded, see `try_parse` for details.
+    ///
+    /// # Arguments
+    /// * `max` - maximum number of ancestors of an element including itself
+    pub fn max_depth(mut self, max: usize) -> Self {
+        self.limits.depth = Some(max);
+        self
+    }
+
+    /// Limit number of elements, comments and processing instructions, unlimited by default.
+    /// Parsing stops once the limit is exceeded, see `try_parse` for details.
+    ///
+    /// # Arguments
+    /// * `max` - maximum number of nodes
+    pub fn max_nodes(mut self, max: usize) -> Self {
+        self.limits.nodes = Some(max);
+        self
+    }
+
+    /// Build only subtrees of elements matching any of given selectors and their ancestors,
+    /// everything else is dropped while parsing to save memory.
+    /// Selectors are checked when elements are added so they should only depend on ancestors
+    /// and attributes of the element, positions do not count dropped siblings.
+    ///
+    /// # Arguments
+    /// * `selectors` - css selectors of subtrees to keep
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::DocumentBuilder;
+    ///
+    /// let html = "<div id='nav'><a href='/'>home</a></div><div id='content'><a href='/x'>x</a></div>";
+    /// let doc = DocumentBuilder::new().keep_only(&["#content"]).parse(html);
+    ///
+    /// assert_eq!(doc.select("a").len(), 1);
+    /// assert!(doc.select_first("#nav").is_none());
+    /// ```
+    pub fn keep_only(mut self, selectors: &[&str]) -> Self {
+        self.keep = selectors.iter().map(|sel| sel.to_string()).collect();
+        self
+    }
+
+    /// Parse html string, when a limit is exceeded document parsed up to that point is returned
+    ///
+    /// # Arguments
+    /// * `input` - html
+    pub fn parse(&self, input: &str) -> Document {
+        let mut feeder = self.feeder();
+        feeder.feed(input);
+        self.build(feeder.finish())
+    }
+
+    /// Parse html string collecting errors found in the input,
+    /// fails with `Error::LimitExceeded` when the input exceeds one of the limits
+    ///
+    /// # Arguments
+    /// * `input` - html
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{DocumentBuilder, Error, Limit};
+    ///
+    /// let html = "<div><div><div>deep</div></div></div>";
+    /// let res = DocumentBuilder::new().max_depth(4).try_parse(html);
+    ///
+    /// assert!(matches!(res, Err(Error::LimitExceeded(Limit::Depth(4)))));
+    /// ```
+    pub fn try_parse(&self, input: &str) -> Result<(Document, Vec<ParseError>), Error> {
+        let mut feeder = self.feeder();
+        feeder.feed(input);
+        let (doc, context) = feeder.finish();
+        if let Some(limit) = context.exceeded {
+            return Err(Error::LimitExceeded(limit));
+        }
+        let errors = context.errors.clone();
+
+        Ok((self.build((doc, context)), errors))
+    }
+
+    /// Create push based parser fed with chunks of input as they arrive
+    pub fn sink(&self) -> DocumentSink {
+        DocumentSink {
+            feeder: self.feeder(),
+            builder: self.clone(),
+        }
+    }
+
+    /// Parse html read from any reader chunk by chunk, invalid utf-8 sequences are replaced.
+    /// Reading stops once a limit is exceeded and `io::ErrorKind::InvalidData` error is returned.
+    ///
+    /// # Arguments
+    /// * `reader` - source of utf-8 encoded html, e.g. file or response body
+    pub fn parse_reader<R: Read>(&self, reader: R) -> io::Result<Document> {
+        self.try_parse_reader(reader).map_err(|err| match err {
+            Error::Io(err) => err,
+            err => io::Error::new(io::ErrorKind::InvalidData, err),
+        })
+    }
+
+    /// Parse html read from any reader chunk by chunk, invalid utf-8 sequences are replaced.
+    /// Fails with `Error::Io` when reading fails and with `Error::LimitExceeded`
+    /// when the input exceeds one of the limits, reading stops at that point.
+    ///
+    /// # Arguments
+    /// * `reader` - source of utf-8 encoded html, e.g. file or response body
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{DocumentBuilder, Error, Limit};
+    ///
+    /// let html = "<p>one</p><p>two</p>";
+    /// let doc = DocumentBuilder::new().try_parse_reader(html.as_bytes()).unwrap();
+    /// assert_eq!(doc.select("p").len(), 2);
+    ///
+    /// let res = DocumentBuilder::new().max_input_size(8).try_parse_reader(html.as_bytes());
+    /// assert!(matches!(res, Err(Error::LimitExceeded(Limit::InputSize(8)))));
+    /// ```
+    pub fn try_parse_reader<R: Read>(&self, mut reader: R) -> Result<Document, Error> {
+        let mut sink = self.sink();
+        let mut buf = [0; 8192];
+
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => sink.feed_bytes(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(Error::Io(err)),
+            }
+            if sink.feeder.exceeded().is_some() {
+                break;
+            }
+        }
+
+        sink.try_finish()
+    }
+
+    /// Parse html read from async reader chunk by chunk, available with `tokio` feature.
+    /// Reading stops once a limit is exceeded and `io::ErrorKind::InvalidData` error is returned.
+    ///
+    /// # Arguments
+    /// * `reader` - source of utf-8 encoded html, e.g. response body stream
+    #[cfg(feature = "tokio")]
+    pub async fn parse_async_reader<R: tokio::io::AsyncRead + Unpin>(
+        &self,
+        mut reader: R,
+    ) -> io::Result<Document> {
+        use tokio::io::AsyncReadExt;
+
+        let mut sink = self.sink();
+        let mut buf = vec![0; 8192];
+
+        loop {
+            match reader.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(n) => sink.feed_bytes(&buf[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+            if sink.feeder.exceeded().is_some() {
+                break;
+            }
+        }
+
+        sink.try_finish()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Parse html bytes in given encoding, available with `encoding_rs` feature
+    ///
+    /// # Arguments
+    /// * `bytes` - encoded html
+    /// * `encoding` - encoding of the bytes, byte order mark takes precedence over it
+    #[cfg(feature = "encoding_rs")]
+    pub fn parse_bytes_with_encoding(
+        &self,
+        bytes: &[u8],
+        encoding: &'static encoding_rs::Encoding,
+    ) -> Document {
+        let (input, _, _) = encoding.decode(bytes);
+        self.parse(&input)
+    }
+
+    /// Parse html bytes detecting their encoding, available with `encoding_rs` feature
+    ///
+    /// # Arguments
+    /// * `bytes` - encoded html
+    #[cfg(feature = "encoding_rs")]
+    pub fn parse_bytes(&self, bytes: &[u8]) -> Document {
+        self.parse_bytes_with_encoding(bytes, super::encoding::detect(bytes))
+    }
+
+    /// Parse html fragment as content of given context element
+    ///
+    /// # Arguments
+    /// * `html` - html fragment
+    /// * `context` - tag name of the element fragment belongs to, e.g. `tr` or `ul`
+    pub fn parse_fragment(&self, html: &str, context: &str) -> Document {
+        let name = QualName::new(
+            None,
+            ns!(html),
+            LocalName::from(context.to_ascii_lowercase()),
+        );
+        let mut feeder = sink::Feeder::fragment(self.opts.clone(), self.limits, name);
+        feeder.keep_only(&self.keep);
+        feeder.feed(html);
+        let (doc, context) = feeder.finish();
+
+        // fragment is parsed into <html> element wrapping it, replace it with its content
+        let wrapper = doc.document.children.borrow_mut().pop();
+        if let Some(wrapper) = wrapper {
+            let children = std::mem::take(&mut *wrapper.children.borrow_mut());
+            for child in children.iter() {
+                child.parent.set(Some(Arc::downgrade(&doc.document)));
+            }
+            doc.document.children.borrow_mut().extend(children);
+        }
+
+        self.build((doc, context))
+    }
+
+    fn feeder(&self) -> sink::Feeder {
+        let mut feeder = sink::Feeder::new(self.opts.clone(), self.limits);
+        feeder.keep_only(&self.keep);
+        feeder
+    }
+
+    fn build(&self, (doc, mut context): (ArcDom, Context)) -> Document {
+        if self.inline_templates {
+            let mut stack = vec![Arc::clone(&doc.document)];
+            while let Some(node) = stack.pop() {
+                if let NodeData::Element {
+                    template_contents: Some(ref contents),
+                    ..
+                } = node.data
+                {
+                    let children = std::mem::take(&mut *contents.children.borrow_mut());
+                    for child in children {
+                        append_child(&node, child);
+                    }
+                }
+                stack.extend(node.children.borrow().iter().cloned());
+            }
+        }
+
+        let base = Selector::from("base[href]")
+            .find_first(&doc.document)
+            .and_then(|base| get_attr(&element_attrs(&base)?, "href"));
+        context.base_url = match (base, &self.url) {
+            (Some(href), Some(url)) => url.join(href.trim()).ok().or_else(|| Some(url.clone())),
+            (Some(href), None) => Url::parse(href.trim()).ok(),
+            (None, url) => url.clone(),
+        };
+
+        Document {
+            doc,
+            context: Rc::new(context),
+        }
+    }
+} //}}}
+
+/// Push based parser building document from chunks of input,
+/// processed input is not kept around so the whole html is never in memory at once
+///
+/// # Example
+/// ```
+/// use crabquery::DocumentSink;
+///
+/// let mut sink = DocumentSink::new();
+/// sink.feed("<ul><li>one</li><l");
+/// sink.feed("i>two</li></ul>");
+/// let doc = sink.finish();
+///
+/// assert_eq!(doc.select("li").len(), 2);
+/// ```
+pub struct DocumentSink {
+    //{{{
+    feeder: sink::Feeder,
+    builder: DocumentBuilder,
+}
+
+impl Default for DocumentSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocumentSink {
+    /// Create parser with default options, use `DocumentBuilder::sink` for custom ones
+    pub fn new() -> Self {
+        DocumentBuilder::new().sink()
+    }
+
+    /// Parse next chunk of html
+    ///
+    /// # Arguments
+    /// * `chunk` - part of html, can end anywhere including the middle of a tag
+    pub fn feed(&mut self, chunk: &str) {
+        self.feeder.feed(chunk);
+    }
+
+    /// Parse next chunk of utf-8 encoded html, invalid sequences are replaced
+    ///
+    /// # Arguments
+    /// * `chunk` - part of html, can end anywhere including the middle of a character
+    pub fn feed_bytes(&mut self, chunk: &[u8]) {
+        self.feeder.feed_utf8(chunk);
+    }
+
+    /// Finish parsing and get the document,
+    /// when a limit is exceeded document parsed up to that point is returned
+    pub fn finish(self) -> Document {
+        self.builder.build(self.feeder.finish())
+    }
+
+    /// Finish parsing and get the document,
+    /// fails with `Error::LimitExceeded` when the input exceeded one of the limits
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{DocumentBuilder, Error, Limit};
+    ///
+    /// let mut sink = DocumentBuilder::new().max_input_size(16).sink();
+    /// sink.feed("<ul><li>one</li>");
+    /// sink.feed("<li>two</li></ul>");
+    ///
+    /// assert!(matches!(sink.try_finish(), Err(Error::LimitExceeded(Limit::InputSize(16)))));
+    /// ```
+    pub fn try_finish(self) -> Result<Document, Error> {
+        match self.feeder.exceeded() {
+            Some(limit) => Err(Error::LimitExceeded(limit)),
+            None => Ok(self.finish()),
+        }
+    }
+} //}}}
@@ -0,0 +1,120 @@
+//! Nodes inserted into documents by mutation methods
+//!
+use markup5ever_arcdom::{Handle, Node, NodeData};
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use super::{detach, parent_handle, Document, Element};
+
+/// Content inserted into a document by methods like `Element::replace_with`
+///
+/// # Example
+/// ```
+/// use crabquery::{Content, Document};
+///
+/// let doc = Document::from("<p>one</p><p>two</p><p>three</p>");
+/// let p = doc.select("p");
+/// p[0].replace_with(Content::text("1"));
+/// p[1].replace_with(Content::html("<b>2</b>"));
+/// p[2].replace_with(&doc.select_first("b").unwrap());
+///
+/// assert_eq!(doc.select_first("body").unwrap().text().unwrap(), "1");
+/// assert_eq!(doc.select("b").len(), 1);
+/// assert!(doc.select("p").is_empty());
+/// ```
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum Content {
+    /// Element moved from its current position, elements of other documents are moved too
+    Element(Element),
+    /// Html fragment parsed in context of the element the content is inserted into
+    Html(String),
+    /// Text inserted as is, without parsing
+    Text(String),
+}
+
+impl Content {
+    /// Create content parsed from html fragment
+    ///
+    /// # Arguments
+    /// * `html` - html fragment
+    pub fn html(html: &str) -> Self {
+        Content::Html(html.to_string())
+    }
+
+    /// Create text content
+    ///
+    /// # Arguments
+    /// * `text` - text, inserted as is
+    pub fn text(text: &str) -> Self {
+        Content::Text(text.to_string())
+    }
+
+    /// Convert into nodes detached from any tree, `None` when the content is an element
+    /// which is the parent itself or one of its ancestors
+    ///
+    /// # Arguments
+    /// * `parent` - node the content is going to be inserted into
+    pub(crate) fn into_nodes(self, parent: &Handle) -> Option<Vec<Handle>> {
+        match self {
+            Content::Element(el) => {
+                if is_inclusive_ancestor(&el.handle, parent) {
+                    return None;
+                }
+                detach(&el.handle);
+                Some(vec![el.handle])
+            }
+            Content::Html(html) => {
+                let context = match parent.data {
+                    NodeData::Element { ref name, .. } => name.local.to_string(),
+                    _ => "body".to_string(),
+                };
+                let fragment = Document::parse_fragment(&html, &context);
+                let nodes = std::mem::take(&mut *fragment.doc.document.children.borrow_mut());
+                for node in nodes.iter() {
+                    node.parent.set(None);
+                }
+                Some(nodes)
+            }
+            Content::Text(text) => Some(vec![Node::new(NodeData::Text {
+                contents: RefCell::new(text.into()),
+            })]),
+        }
+    }
+}
+
+impl From<Element> for Content {
+    fn from(el: Element) -> Self {
+        Content::Element(el)
+    }
+}
+
+impl From<&Element> for Content {
+    fn from(el: &Element) -> Self {
+        Content::Element(el.clone())
+    }
+}
+
+/// Insert nodes into children of the parent starting at given position
+pub(crate) fn insert_nodes(parent: &Handle, index: usize, nodes: Vec<Handle>) {
+    for node in nodes.iter() {
+        node.parent.set(Some(Arc::downgrade(parent)));
+    }
+
+    let mut children = parent.children.borrow_mut();
+    let index = index.min(children.len());
+    children.splice(index..index, nodes);
+}
+
+fn is_inclusive_ancestor(ancestor: &Handle, node: &Handle) -> bool {
+    let mut current = Some(Arc::clone(node));
+
+    while let Some(node) = current {
+        if Arc::ptr_eq(&node, ancestor) {
+            return true;
+        }
+        current = parent_handle(&node);
+    }
+
+    false
+}
@@ -0,0 +1,255 @@
+//! Structural comparison of documents
+//!
+use markup5ever_arcdom::{Handle, NodeData};
+use std::sync::Arc;
+
+use super::{element_attrs, get_attr, is_element, text_contents, Document};
+
+/// Difference between two documents found by `Document::diff`
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[non_exhaustive]
+pub enum Change {
+    /// Element present only in the new document, css path is the one in the new document
+    Added(String),
+    /// Element present only in the old document, css path is the one in the old document
+    Removed(String),
+    /// Element present in both documents with different attributes or own text,
+    /// css path is the one in the new document
+    Changed(String),
+}
+
+/// Compare element trees of documents, subtrees of added and removed elements
+/// are reported only by their root element
+pub(crate) fn diff(old: &Document, new: &Document) -> Vec<Change> {
+    enum Item {
+        Change(Change),
+        Compare(Handle, Handle),
+    }
+
+    let mut changes = vec![];
+    let mut stack = vec![Item::Compare(
+        Arc::clone(&old.doc.document),
+        Arc::clone(&new.doc.document),
+    )];
+
+    while let Some(item) = stack.pop() {
+        let (old_parent, new_parent) = match item {
+            Item::Change(change) => {
+                changes.push(change);
+                continue;
+            }
+            Item::Compare(old_parent, new_parent) => (old_parent, new_parent),
+        };
+        let old_children = element_children(&old_parent);
+        let new_children = element_children(&new_parent);
+        let mut items = vec![];
+
+        for step in align(&old_children, &new_children) {
+            match step {
+                Step::Removed(i) => items.push(Item::Change(Change::Removed(
+                    old.element(&old_children[i]).css_path(),
+                ))),
+                Step::Added(j) => items.push(Item::Change(Change::Added(
+                    new.element(&new_children[j]).css_path(),
+                ))),
+                Step::Kept(i, j) => {
+                    let (old_el, new_el) = (&old_children[i], &new_children[j]);
+                    if attrs(old_el) != attrs(new_el) || own_text(old_el) != own_text(new_el) {
+                        let path = new.element(new_el).css_path();
+                        items.push(Item::Change(Change::Changed(path)));
+                    }
+                    items.push(Item::Compare(Arc::clone(old_el), Arc::clone(new_el)));
+                }
+            }
+        }
+
+        // stack is popped from the end, keep document order
+        stack.extend(items.into_iter().rev());
+    }
+
+    changes
+}
+
+enum Step {
+    Removed(usize),
+    Added(usize),
+    Kept(usize, usize),
+}
+
+/// Maximal number of removed and added children the alignment looks for,
+/// children lists which differ more are reported as replaced
+const MAX_EDITS: usize = 1000;
+
+/// Align children lists by their tag and id with the shortest edit script,
+/// common prefix and suffix are matched directly
+fn align(old: &[Handle], new: &[Handle]) -> Vec<Step> {
+    let old_keys: Vec<_> = old.iter().map(key).collect();
+    let new_keys: Vec<_> = new.iter().map(key).collect();
+
+    let prefix = old_keys
+        .iter()
+        .zip(new_keys.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_keys[prefix..]
+        .iter()
+        .rev()
+        .zip(new_keys[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old_keys[prefix..old_keys.len() - suffix];
+    let new_mid = &new_keys[prefix..new_keys.len() - suffix];
+    let (n, m) = (old_mid.len(), new_mid.len());
+
+    let mut steps: Vec<_> = (0..prefix).map(|i| Step::Kept(i, i)).collect();
+    match edit_script(old_mid, new_mid) {
+        Some(script) => steps.extend(script.into_iter().map(|step| match step {
+            Step::Removed(i) => Step::Removed(prefix + i),
+            Step::Added(j) => Step::Added(prefix + j),
+            Step::Kept(i, j) => Step::Kept(prefix + i, prefix + j),
+        })),
+        None => {
+            steps.extend((prefix..prefix + n).map(Step::Removed));
+            steps.extend((prefix..prefix + m).map(Step::Added));
+        }
+    }
+    for k in 0..suffix {
+        steps.push(Step::Kept(prefix + n + k, prefix + m + k));
+    }
+
+    steps
+}
+
+/// Find the shortest edit script turning `old` into `new` with Myers' algorithm,
+/// it takes O((n + m) * d) time and O(d * d) memory for d edits.
+/// Returns `None` when more than `MAX_EDITS` edits are needed.
+fn edit_script<T: PartialEq>(old: &[T], new: &[T]) -> Option<Vec<Step>> {
+    let (n, m) = (old.len(), new.len());
+    // furthest x reached on each diagonal k = x - y after d edits, stored at k + d
+    let mut trace: Vec<Vec<usize>> = vec![];
+
+    for d in 0..=MAX_EDITS.min(n + m) {
+        let furthest = |k: isize| {
+            trace
+                .last()
+                .map_or(0, |v: &Vec<usize>| v[(k + d as isize - 1) as usize])
+        };
+        let mut v = vec![0; 2 * d + 1];
+        let mut done = false;
+
+        for k in (-(d as isize)..=d as isize).step_by(2) {
+            let mut x = if d == 0 {
+                0
+            } else if k == -(d as isize) || (k != d as isize && furthest(k - 1) < furthest(k + 1)) {
+                furthest(k + 1)
+            } else {
+                furthest(k - 1) + 1
+            };
+            let mut y = (x as isize - k) as usize;
+            while x < n && y < m && old[x] == new[y] {
+                x += 1;
+                y += 1;
+            }
+            v[(k + d as isize) as usize] = x;
+            if x >= n && y >= m {
+                done = true;
+                break;
+            }
+        }
+
+        trace.push(v);
+        if done {
+            return Some(backtrack(&trace, n, m));
+        }
+    }
+
+    None
+}
+
+/// Walk the edits found by `edit_script` back from the end of both lists
+fn backtrack(trace: &[Vec<usize>], n: usize, m: usize) -> Vec<Step> {
+    let mut steps = vec![];
+    let (mut x, mut y) = (n, m);
+
+    for d in (1..trace.len()).rev() {
+        let prev = &trace[d - 1];
+        let d = d as isize;
+        let furthest = |k: isize| prev[(k + d - 1) as usize];
+        let k = x as isize - y as isize;
+        let prev_k = if k == -d || (k != d && furthest(k - 1) < furthest(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = furthest(prev_k);
+        let prev_y = (prev_x as isize - prev_k) as usize;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            steps.push(Step::Kept(x, y));
+        }
+        if prev_k == k + 1 {
+            steps.push(Step::Added(prev_y));
+        } else {
+            steps.push(Step::Removed(prev_x));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        steps.push(Step::Kept(x, y));
+    }
+
+    steps.reverse();
+    steps
+}
+
+fn element_children(handle: &Handle) -> Vec<Handle> {
+    handle
+        .children
+        .borrow()
+        .iter()
+        .filter(|n| is_element(n))
+        .cloned()
+        .collect()
+}
+
+fn key(handle: &Handle) -> (String, Option<String>) {
+    let name = match handle.data {
+        NodeData::Element { ref name, .. } => name.local.to_string(),
+        _ => String::new(),
+    };
+    let id = element_attrs(handle).and_then(|attrs| get_attr(&attrs, "id"));
+
+    (name, id)
+}
+
+fn attrs(handle: &Handle) -> Vec<(String, String)> {
+    let mut attrs: Vec<_> = element_attrs(handle)
+        .map(|attrs| {
+            attrs
+                .iter()
+                .map(|a| (a.name.local.to_string(), a.value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    attrs.sort();
+
+    attrs
+}
+
+/// Text of direct text children with collapsed whitespace
+fn own_text(handle: &Handle) -> String {
+    let text: String = handle
+        .children
+        .borrow()
+        .iter()
+        .filter_map(text_contents)
+        .collect();
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,209 @@
+//! Detection of html input encoding following WHATWG encoding sniffing algorithm
+//!
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// How many bytes are checked for `<meta>` tag declaring encoding, same as browsers do
+const PRESCAN_LEN: usize = 1024;
+
+/// Detect encoding of html bytes looking at byte order mark, charset declared in
+/// `<meta>` tags, falling back to utf-8 for valid utf-8 input and windows-1252 otherwise
+pub(crate) fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    if let Some(encoding) = prescan(&bytes[..bytes.len().min(PRESCAN_LEN)]) {
+        return encoding;
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        UTF_8
+    } else {
+        WINDOWS_1252
+    }
+}
+
+/// Look for encoding declared in `<meta charset>` or `<meta http-equiv content>` tags,
+/// skipping comments and attributes of other tags
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+
+        if rest.starts_with(b"<!--") {
+            i += find(&rest[2..], b"-->").map_or(rest.len(), |pos| pos + 5);
+        } else if starts_with_ignore_case(rest, b"<meta")
+            && matches!(rest.get(5), Some(&c) if is_space(c) || c == b'/')
+        {
+            i += 6;
+            if let Some(encoding) = meta_encoding(bytes, &mut i) {
+                return Some(encoding);
+            }
+        } else if rest.len() > 2
+            && rest[0] == b'<'
+            && (rest[1].is_ascii_alphabetic() || rest[1] == b'/' && rest[2].is_ascii_alphabetic())
+        {
+            // skip tag name and attributes, so their values are not mistaken for tags
+            i += 2;
+            while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'>' {
+                i += 1;
+            }
+            while attribute(bytes, &mut i).is_some() {}
+            i += 1;
+        } else if rest.starts_with(b"<!") || rest.starts_with(b"</") || rest.starts_with(b"<?") {
+            i += find(rest, b">").map_or(rest.len(), |pos| pos + 1);
+        } else {
+            i += 1;
+        }
+    }
+
+    None
+}
+
+/// Process attributes of `<meta>` tag, returns encoding if tag declares one
+fn meta_encoding(bytes: &[u8], i: &mut usize) -> Option<&'static Encoding> {
+    let mut seen = vec![];
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+
+    while let Some((name, value)) = attribute(bytes, i) {
+        if seen.contains(&name) {
+            continue;
+        }
+
+        match name.as_str() {
+            "http-equiv" if value.eq_ignore_ascii_case("content-type") => got_pragma = true,
+            "content" if charset.is_none() => {
+                if let Some(encoding) = content_charset(&value) {
+                    charset = Some(encoding);
+                    need_pragma = Some(true);
+                }
+            }
+            "charset" => {
+                charset = Encoding::for_label(value.as_bytes());
+                need_pragma = Some(false);
+            }
+            _ => {}
+        }
+        seen.push(name);
+    }
+
+    match need_pragma {
+        None => return None,
+        Some(true) if !got_pragma => return None,
+        _ => {}
+    }
+
+    match charset? {
+        encoding if encoding == UTF_16BE || encoding == UTF_16LE => Some(UTF_8),
+        encoding if encoding == X_USER_DEFINED => Some(WINDOWS_1252),
+        encoding => Some(encoding),
+    }
+}
+
+/// Read next attribute of a tag lowercasing its name, returns None at the end of the tag
+fn attribute(bytes: &[u8], i: &mut usize) -> Option<(String, String)> {
+    while *i < bytes.len() && (is_space(bytes[*i]) || bytes[*i] == b'/') {
+        *i += 1;
+    }
+    if *i >= bytes.len() || bytes[*i] == b'>' {
+        return None;
+    }
+
+    let mut name = vec![];
+    loop {
+        match bytes.get(*i) {
+            None => return None,
+            Some(b'=') if !name.is_empty() => break,
+            Some(&c) if is_space(c) || c == b'/' || c == b'>' => {
+                let mut j = *i;
+                while j < bytes.len() && is_space(bytes[j]) {
+                    j += 1;
+                }
+                if bytes.get(j) == Some(&b'=') {
+                    *i = j;
+                    break;
+                }
+                return Some((String::from_utf8_lossy(&name).into_owned(), String::new()));
+            }
+            Some(&c) => {
+                name.push(c.to_ascii_lowercase());
+                *i += 1;
+            }
+        }
+    }
+    let name = String::from_utf8_lossy(&name).into_owned();
+
+    // skip `=` and whitespace before the value
+    *i += 1;
+    while *i < bytes.len() && is_space(bytes[*i]) {
+        *i += 1;
+    }
+
+    let mut value = vec![];
+    match bytes.get(*i) {
+        None => return None,
+        Some(&quote) if quote == b'"' || quote == b'\'' => {
+            *i += 1;
+            while *i < bytes.len() && bytes[*i] != quote {
+                value.push(bytes[*i].to_ascii_lowercase());
+                *i += 1;
+            }
+            *i += 1;
+        }
+        Some(b'>') => {}
+        Some(_) => {
+            while *i < bytes.len() && !is_space(bytes[*i]) && bytes[*i] != b'>' {
+                value.push(bytes[*i].to_ascii_lowercase());
+                *i += 1;
+            }
+        }
+    }
+
+    Some((name, String::from_utf8_lossy(&value).into_owned()))
+}
+
+/// Extract encoding from `content` attribute like `text/html; charset=utf-8`
+fn content_charset(content: &str) -> Option<&'static Encoding> {
+    let mut rest = content;
+
+    loop {
+        let pos = rest.find("charset")?;
+        rest = rest[pos + "charset".len()..].trim_start_matches(is_space_char);
+        if let Some(value) = rest.strip_prefix('=') {
+            rest = value.trim_start_matches(is_space_char);
+            break;
+        }
+    }
+
+    let label = match rest.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let value = &rest[1..];
+            &value[..value.find(quote)?]
+        }
+        _ => rest
+            .split(|c| is_space_char(c) || c == ';')
+            .next()
+            .unwrap_or_default(),
+    };
+
+    Encoding::for_label(label.as_bytes())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn starts_with_ignore_case(bytes: &[u8], prefix: &[u8]) -> bool {
+    bytes.len() >= prefix.len() && bytes[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+fn is_space(c: u8) -> bool {
+    matches!(c, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+fn is_space_char(c: char) -> bool {
+    c.is_ascii() && is_space(c as u8)
+}
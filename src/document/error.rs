@@ -0,0 +1,88 @@
+//! Errors returned by fallible operations
+//!
+use std::fmt;
+use std::io;
+use std::str::Utf8Error;
+
+/// Error of a fallible operation
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Given url could not be parsed
+    InvalidUrl(url::ParseError),
+    /// Input exceeded one of the limits set on `DocumentBuilder`, parsing was aborted
+    LimitExceeded(Limit),
+    /// CSS selector could not be parsed, with the part which is not understood
+    InvalidSelector(String),
+    /// Reading the input failed
+    Io(io::Error),
+    /// Input bytes are not valid utf-8
+    InvalidUtf8(Utf8Error),
+    /// Bytes are not a document in binary format, available with `binary` feature
+    #[cfg(feature = "binary")]
+    InvalidBinary,
+    /// JSON value is not a valid node tree, with path to the invalid node,
+    /// available with `serde_json` feature
+    #[cfg(feature = "serde_json")]
+    InvalidJson(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(err) => write!(f, "invalid url: {}", err),
+            Error::LimitExceeded(limit) => write!(f, "{} exceeded", limit),
+            Error::InvalidSelector(part) => write!(f, "invalid selector \"{}\"", part),
+            Error::Io(err) => write!(f, "io error: {}", err),
+            Error::InvalidUtf8(err) => write!(f, "invalid utf-8: {}", err),
+            #[cfg(feature = "binary")]
+            Error::InvalidBinary => write!(f, "invalid binary document"),
+            #[cfg(feature = "serde_json")]
+            Error::InvalidJson(msg) => write!(f, "invalid json document: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUrl(err) => Some(err),
+            Error::LimitExceeded(_) => None,
+            Error::InvalidSelector(_) => None,
+            Error::Io(err) => Some(err),
+            Error::InvalidUtf8(err) => Some(err),
+            #[cfg(feature = "binary")]
+            Error::InvalidBinary => None,
+            #[cfg(feature = "serde_json")]
+            Error::InvalidJson(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// Parse limit with its configured maximum
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub enum Limit {
+    /// Maximum size of the input in bytes
+    InputSize(usize),
+    /// Maximum nesting depth of elements
+    Depth(usize),
+    /// Maximum number of elements, comments and processing instructions
+    Nodes(usize),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::InputSize(max) => write!(f, "input size limit of {} bytes", max),
+            Limit::Depth(max) => write!(f, "depth limit of {}", max),
+            Limit::Nodes(max) => write!(f, "node count limit of {}", max),
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Lookup of elements by id and class without walking the whole tree
+//!
+use markup5ever_arcdom::Handle;
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use super::{element_attrs, get_attr, Context, Selector};
+
+thread_local! {
+    /// Number of tree changes made on this thread, nodes never leave the thread
+    /// so indexes built at the same count are up to date
+    static CHANGES: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Note that some tree is about to change, built indexes become stale
+pub(crate) fn touch() {
+    CHANGES.with(|changes| changes.set(changes.get().wrapping_add(1)));
+}
+
+fn changes() -> u64 {
+    CHANGES.with(Cell::get)
+}
+
+enum Key {
+    Id,
+    Class,
+}
+
+/// Elements by their trimmed id and words of their class attribute, in document order
+pub(crate) struct Index {
+    /// Value of the change counter the index was built at
+    built_at: u64,
+    ids: HashMap<String, Vec<Handle>>,
+    classes: HashMap<String, Vec<Handle>>,
+}
+
+impl Index {
+    fn build(root: &Handle) -> Self {
+        let mut index = Index {
+            built_at: changes(),
+            ids: HashMap::new(),
+            classes: HashMap::new(),
+        };
+
+        let mut stack: Vec<_> = root.children.borrow().iter().rev().cloned().collect();
+        while let Some(node) = stack.pop() {
+            if let Some(attrs) = element_attrs(&node) {
+                if let Some(id) = get_attr(&attrs, "id") {
+                    index
+                        .ids
+                        .entry(id.trim().to_string())
+                        .or_default()
+                        .push(Arc::clone(&node));
+                }
+                if let Some(value) = get_attr(&attrs, "class") {
+                    let words: HashSet<_> = value.split_whitespace().collect();
+                    for word in words {
+                        index
+                            .classes
+                            .entry(word.to_string())
+                            .or_default()
+                            .push(Arc::clone(&node));
+                    }
+                }
+            }
+            stack.extend(node.children.borrow().iter().rev().cloned());
+        }
+
+        index
+    }
+}
+
+impl Context {
+    /// Get elements under the root which can match the selector when its last part
+    /// requires an id or a class, `None` when all elements have to be checked.
+    /// The index is built on first use and rebuilt after the tree changes.
+    pub(super) fn candidates(&self, root: &Handle, selector: &Selector) -> Option<Vec<Handle>> {
+        let last = match selector.matchers.last() {
+            Some(last) if !last.direct_match => last,
+            _ => return None,
+        };
+
+        let (map, key) = match (last.id.first(), last.class.first()) {
+            (Some(id), _) => (Key::Id, id),
+            (None, Some(class)) => (Key::Class, class),
+            (None, None) => return None,
+        };
+
+        let mut index = self.index.borrow_mut();
+        if !matches!(*index, Some(ref index) if index.built_at == changes()) {
+            *index = Some(Index::build(root));
+        }
+        let index = index.as_ref()?;
+        let found = match map {
+            Key::Id => index.ids.get(key),
+            Key::Class => index.classes.get(key),
+        };
+
+        Some(found.cloned().unwrap_or_default())
+    }
+}
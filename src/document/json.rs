@@ -0,0 +1,136 @@
+//! Building documents from JSON node trees, available with `serde_json` feature
+//!
+use html5ever::tree_builder::QuirksMode;
+use markup5ever::{namespace_url, ns, Attribute, LocalName, QualName};
+use markup5ever_arcdom::{ArcDom, Handle, Node, NodeData};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::{append_child, Context, Document, Error};
+
+/// Build document out of a node or an array of nodes, node is either a string
+/// for a text node or an object with `tag`, optional `attrs` object and optional
+/// `children` array
+pub(crate) fn decode(value: &Value) -> Result<Document, Error> {
+    let document = Node::new(NodeData::Document);
+
+    let roots = match value {
+        Value::Array(nodes) => nodes.iter().enumerate().map(path_item("")).collect(),
+        node => vec![(String::new(), node)],
+    };
+    // nodes waiting to be converted with their parent and path used in errors
+    let mut stack: Vec<_> = roots
+        .into_iter()
+        .rev()
+        .map(|(path, node)| (Arc::clone(&document), path, node))
+        .collect();
+
+    while let Some((parent, path, value)) = stack.pop() {
+        let (node, children) = node(&path, value)?;
+        append_child(&parent, Arc::clone(&node));
+
+        let base = format!("{}/children", path);
+        let children: Vec<_> = children.iter().enumerate().map(path_item(&base)).collect();
+        stack.extend(
+            children
+                .into_iter()
+                .rev()
+                .map(|(path, child)| (Arc::clone(&node), path, child)),
+        );
+    }
+
+    Ok(Document {
+        doc: ArcDom {
+            document,
+            errors: vec![],
+            quirks_mode: QuirksMode::NoQuirks,
+        },
+        context: Rc::new(Context::default()),
+    })
+}
+
+fn path_item(base: &str) -> impl Fn((usize, &Value)) -> (String, &Value) + '_ {
+    move |(i, value)| (format!("{}/{}", base, i), value)
+}
+
+/// Convert single JSON node, returns the node and its children
+fn node<'a>(path: &str, value: &'a Value) -> Result<(Handle, &'a [Value]), Error> {
+    let invalid =
+        |message: &str| Error::InvalidJson(format!("{}: {}", path_or_root(path), message));
+
+    let object = match value {
+        Value::String(text) => {
+            let data = NodeData::Text {
+                contents: RefCell::new(text.as_str().into()),
+            };
+            return Ok((Node::new(data), &[]));
+        }
+        Value::Object(object) => object,
+        _ => return Err(invalid("expected object or string")),
+    };
+
+    let tag = match object.get("tag") {
+        Some(Value::String(tag)) if !tag.is_empty() => tag,
+        _ => return Err(invalid("expected non empty string tag")),
+    };
+    if !valid_name(tag) {
+        return Err(invalid(&format!("invalid tag name {:?}", tag)));
+    }
+
+    let mut attrs = vec![];
+    match object.get("attrs") {
+        Some(Value::Object(map)) => {
+            for (name, value) in map.iter() {
+                if !valid_name(name) {
+                    return Err(invalid(&format!("invalid attribute name {:?}", name)));
+                }
+                let value = match value {
+                    Value::String(value) => value,
+                    _ => return Err(invalid(&format!("expected string value of {}", name))),
+                };
+                attrs.push(Attribute {
+                    name: QualName::new(None, ns!(), LocalName::from(name.to_ascii_lowercase())),
+                    value: value.as_str().into(),
+                });
+            }
+        }
+        Some(Value::Null) | None => {}
+        _ => return Err(invalid("expected object attrs")),
+    }
+
+    let children = match object.get("children") {
+        Some(Value::Array(children)) => children.as_slice(),
+        Some(Value::Null) | None => &[],
+        _ => return Err(invalid("expected array children")),
+    };
+
+    let data = NodeData::Element {
+        name: QualName::new(None, ns!(html), LocalName::from(tag.to_ascii_lowercase())),
+        attrs: RefCell::new(attrs),
+        template_contents: None,
+        mathml_annotation_xml_integration_point: false,
+    };
+
+    Ok((Node::new(data), children))
+}
+
+/// Check that the name can be written into a tag without changing its meaning,
+/// html parser ends names at whitespace, `/`, `>` and `=`
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.chars().any(|c| {
+            c.is_whitespace()
+                || c.is_control()
+                || matches!(c, '/' | '>' | '<' | '=' | '"' | '\'' | '`')
+        })
+}
+
+fn path_or_root(path: &str) -> &str {
+    if path.is_empty() {
+        "/"
+    } else {
+        path
+    }
+}
@@ -0,0 +1,343 @@
+//! Compact serialization dropping everything which does not change the parsed document
+//!
+use html5ever::serialize::TraversalScope;
+use markup5ever_arcdom::{Handle, Node, NodeData};
+use std::cell::RefCell;
+use std::sync::Arc;
+
+use super::serialize::{children, end_tag, escape_text, to_html, BLOCK, VERBATIM, VOID};
+use super::{deep_clone, parent_handle};
+
+/// Elements never rendered, whitespace around them still separates the text
+const HIDDEN: &[&str] = &[
+    "base", "link", "meta", "script", "style", "template", "title",
+];
+
+/// Elements which close `<p>` when they start
+const CLOSES_P: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+enum Op {
+    Open(Handle),
+    Close(Handle),
+}
+
+/// Serialize children of the node without comments, insignificant whitespace,
+/// unnecessary attribute quotes and optional tags
+pub(crate) fn to_minified_html(handle: &Handle) -> String {
+    let root = deep_clone(handle);
+    minify_tree(&root);
+
+    let mut out = String::new();
+    let mut stack: Vec<_> = children(&root).into_iter().rev().map(Op::Open).collect();
+
+    while let Some(op) = stack.pop() {
+        let handle = match op {
+            Op::Open(handle) => handle,
+            Op::Close(handle) => {
+                if !omit_end_tag(&handle) {
+                    end_tag(&handle, &mut out);
+                }
+                continue;
+            }
+        };
+
+        match handle.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } if !VERBATIM.contains(&&*name.local) => {
+                let attrs = attrs.borrow();
+                if !(attrs.is_empty() && omit_start_tag(&handle)) {
+                    out.push('<');
+                    out.push_str(&name.local);
+                    for attr in attrs.iter() {
+                        out.push(' ');
+                        if let Some(ref prefix) = attr.name.prefix {
+                            out.push_str(prefix);
+                            out.push(':');
+                        }
+                        out.push_str(&attr.name.local);
+                        write_attr_value(&attr.value, &mut out);
+                    }
+                    out.push('>');
+                }
+                if !VOID.contains(&&*name.local) {
+                    stack.push(Op::Close(Arc::clone(&handle)));
+                    stack.extend(children(&handle).into_iter().rev().map(Op::Open));
+                }
+            }
+            NodeData::Text { ref contents } => escape_text(&contents.borrow(), &mut out),
+            _ => out.push_str(&to_html(&handle, TraversalScope::IncludeNode)),
+        }
+    }
+
+    out
+}
+
+/// Write attribute value without quotes when possible, empty value is left out
+fn write_attr_value(value: &str, out: &mut String) {
+    if value.is_empty() {
+        return;
+    }
+
+    out.push('=');
+    let unquoted = !value
+        .chars()
+        .any(|c| c.is_ascii_whitespace() || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`'));
+    if !unquoted {
+        out.push('"');
+    }
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    if !unquoted {
+        out.push('"');
+    }
+}
+
+/// Remove comments, collapse whitespace of text and drop it next to block elements
+fn minify_tree(root: &Handle) {
+    let mut stack = vec![Arc::clone(root)];
+
+    while let Some(parent) = stack.pop() {
+        let mut nodes: Vec<Handle> = vec![];
+        for node in children(&parent) {
+            match node.data {
+                NodeData::Comment { .. } => {}
+                NodeData::Text { ref contents } => {
+                    let text = collapse(&contents.borrow());
+                    // text nodes separated by a comment before
+                    let merged = match nodes.last().map(|last| &last.data) {
+                        Some(NodeData::Text { contents }) => {
+                            let mut contents = contents.borrow_mut();
+                            let joined = collapse(&format!("{}{}", contents, text));
+                            *contents = joined.as_str().into();
+                            true
+                        }
+                        _ => false,
+                    };
+                    if !merged {
+                        nodes.push(text_node(&text));
+                    }
+                }
+                NodeData::Element { ref name, .. } => {
+                    if !VERBATIM.contains(&&*name.local) {
+                        stack.push(Arc::clone(&node));
+                    }
+                    nodes.push(node);
+                }
+                _ => nodes.push(node),
+            }
+        }
+
+        // whitespace at edges of blocks does not render
+        let edge = is_block_container(&parent);
+        let mut kept = vec![];
+        for (i, node) in nodes.iter().enumerate() {
+            if let NodeData::Text { ref contents } = node.data {
+                let mut text = contents.borrow().to_string();
+                let before = if i == 0 {
+                    edge
+                } else {
+                    is_block(&nodes[i - 1])
+                };
+                let after = match nodes.get(i + 1) {
+                    Some(next) => is_block(next),
+                    None => edge,
+                };
+                if before {
+                    text = text.trim_start_matches(' ').to_string();
+                }
+                if after {
+                    text = text.trim_end_matches(' ').to_string();
+                }
+                if text.is_empty() {
+                    continue;
+                }
+                *contents.borrow_mut() = text.as_str().into();
+            }
+            kept.push(Arc::clone(node));
+        }
+
+        let target = match parent.data {
+            NodeData::Element {
+                template_contents: Some(ref contents),
+                ..
+            } => Arc::clone(contents),
+            _ => Arc::clone(&parent),
+        };
+        for node in kept.iter() {
+            node.parent.set(Some(Arc::downgrade(&target)));
+        }
+        *target.children.borrow_mut() = kept;
+    }
+}
+
+fn collapse(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut space = false;
+
+    for c in text.chars() {
+        if c.is_ascii_whitespace() {
+            space = true;
+            continue;
+        }
+        if space {
+            res.push(' ');
+            space = false;
+        }
+        res.push(c);
+    }
+    if space {
+        res.push(' ');
+    }
+
+    res
+}
+
+fn text_node(text: &str) -> Handle {
+    Node::new(NodeData::Text {
+        contents: RefCell::new(text.into()),
+    })
+}
+
+fn tag(handle: &Handle) -> Option<&str> {
+    match handle.data {
+        NodeData::Element { ref name, .. } => Some(&name.local),
+        _ => None,
+    }
+}
+
+fn is_block(handle: &Handle) -> bool {
+    match tag(handle) {
+        Some(tag) => BLOCK.contains(&tag) && !HIDDEN.contains(&tag),
+        None => matches!(handle.data, NodeData::Doctype { .. }),
+    }
+}
+
+/// Node whose edges do not render whitespace
+fn is_block_container(handle: &Handle) -> bool {
+    match tag(handle) {
+        Some(tag) => BLOCK.contains(&tag) || tag == "template",
+        None => true,
+    }
+}
+
+fn next_sibling(handle: &Handle) -> Option<Handle> {
+    let parent = parent_handle(handle)?;
+    let children = parent.children.borrow();
+    let i = children.iter().position(|n| Arc::ptr_eq(n, handle))?;
+    children.get(i + 1).cloned()
+}
+
+/// Check if start tag of element without attributes can be left out
+fn omit_start_tag(handle: &Handle) -> bool {
+    let first = handle.children.borrow().first().cloned();
+    match tag(handle) {
+        Some("html") => true,
+        Some("head") => match first {
+            Some(ref n) => tag(n).is_some(),
+            None => true,
+        },
+        Some("body") => match first {
+            None => true,
+            Some(n) => match n.data {
+                NodeData::Text { ref contents } => !contents
+                    .borrow()
+                    .starts_with(|c: char| c.is_ascii_whitespace()),
+                NodeData::Element { ref name, .. } => !matches!(
+                    &*name.local,
+                    "base"
+                        | "link"
+                        | "meta"
+                        | "noscript"
+                        | "script"
+                        | "style"
+                        | "template"
+                        | "title"
+                ),
+                _ => false,
+            },
+        },
+        _ => false,
+    }
+}
+
+/// Check if end tag can be left out as the parser implies it from what follows
+fn omit_end_tag(handle: &Handle) -> bool {
+    let name = match tag(handle) {
+        Some(name) => name,
+        None => return false,
+    };
+    let next = next_sibling(handle);
+    let next_tag = next.as_ref().and_then(tag);
+    let followed_by = |names: &[&str]| matches!(next_tag, Some(t) if names.contains(&t));
+    let last = next.is_none();
+
+    match name {
+        "html" | "body" => true,
+        "head" => match next.as_ref().map(|n| &n.data) {
+            Some(NodeData::Text { contents }) => !contents
+                .borrow()
+                .starts_with(|c: char| c.is_ascii_whitespace()),
+            _ => true,
+        },
+        "li" => last || followed_by(&["li"]),
+        "dt" => followed_by(&["dt", "dd"]),
+        "dd" => last || followed_by(&["dt", "dd"]),
+        "rt" | "rp" => last || followed_by(&["rt", "rp"]),
+        "optgroup" => last || followed_by(&["optgroup", "hr"]),
+        "option" => last || followed_by(&["option", "optgroup", "hr"]),
+        "thead" => followed_by(&["tbody", "tfoot"]),
+        "tbody" => last || followed_by(&["tbody", "tfoot"]),
+        "tfoot" => last,
+        "tr" => last || followed_by(&["tr"]),
+        "td" | "th" => last || followed_by(&["td", "th"]),
+        "p" => {
+            let parent = parent_handle(handle);
+            let transparent = matches!(
+                parent.as_ref().and_then(tag),
+                Some("a" | "audio" | "del" | "ins" | "map" | "noscript" | "video")
+            );
+            followed_by(CLOSES_P) || (last && !transparent)
+        }
+        _ => false,
+    }
+}
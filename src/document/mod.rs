@@ -6,635 +6,7836 @@
 //! * id based `#mainbutton`
 //! * direct child `>`
 //! * attribute selectors `[href]`, `[href="specific-value"]`, `[href*="contains-str"]`,
-//! `[href^="begins-with"]`,, `[href$="ends-with"]`
+//!   `[href^="begins-with"]`, `[href$="ends-with"]`
+//! * position based `:nth-child(3)`
 //! * all combinations of above like `div.container > form#feedback input.button`
 //!
-use html5ever::driver::ParseOpts;
-use html5ever::parse_document;
-use html5ever::tendril::TendrilSink;
-use html5ever::tree_builder::TreeBuilderOpts;
-use markup5ever::{Attribute, QualName};
-use markup5ever_arcdom::{ArcDom, Handle, NodeData};
-use std::cell::Ref;
-use std::collections::HashMap;
+mod audit;
+#[cfg(feature = "binary")]
+mod binary;
+mod builder;
+mod content;
+mod diff;
+#[cfg(feature = "encoding_rs")]
+mod encoding;
+mod error;
+mod index;
+#[cfg(feature = "serde_json")]
+mod json;
+mod minify;
+mod pretty;
+mod sanitize;
+mod selection;
+mod serialize;
+mod sink;
+mod stream;
+mod transaction;
+
+pub use audit::Warning;
+pub use builder::{DocumentBuilder, DocumentSink};
+pub use content::Content;
+pub use diff::Change;
+pub use error::{Error, Limit};
+pub use sanitize::Sanitizer;
+pub use selection::Selection;
+pub use serialize::Escaping;
+pub use stream::{SelectStream, StreamElement};
+pub use transaction::Transaction;
+
+use html5ever::serialize::TraversalScope;
+use html5ever::tendril::StrTendril;
+use markup5ever::{local_name, namespace_url, ns, Attribute, LocalName, QualName};
+use markup5ever_arcdom::{ArcDom, Handle, Node, NodeData, WeakHandle};
+use smallvec::SmallVec;
+use std::cell::{Ref, RefCell};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::default::Default;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::ops::Range;
+use std::rc::Rc;
+use std::str::FromStr;
 use std::sync::Arc;
+use url::Url;
 
+/// Elements which text is not parsed as html, so elements can not be inserted into it
+const RAW_TEXT: &[&str] = &[
+    "iframe",
+    "noembed",
+    "noframes",
+    "noscript",
+    "plaintext",
+    "script",
+    "style",
+    "textarea",
+    "title",
+    "xmp",
+];
+
+/// Parsed html document, elements selected from it share its nodes.
+///
+/// Documents and elements stay on the thread which parsed them, nodes are reference
+/// counted and changed in place through `RefCell`s, so they are neither `Send` nor `Sync`.
+/// To process pages on other threads send the html there, or bytes of `to_binary` with
+/// `binary` feature, and parse it on that thread.
+///
+/// # Example
+/// ```
+/// use crabquery::Document;
+/// use std::thread;
+///
+/// let pages = vec!["<h1>one</h1>".to_string(), "<h1>two</h1>".to_string()];
+/// let workers: Vec<_> = pages
+///     .into_iter()
+///     .map(|html| thread::spawn(move || Document::from(html).select("h1").texts()))
+///     .collect();
+/// let titles: Vec<_> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+///
+/// assert_eq!(titles, vec![vec!["one"], vec!["two"]]);
+/// ```
+///
+/// ```compile_fail
+/// fn send<T: Send>(_: T) {}
+///
+/// send(crabquery::Document::from("<p>not shared</p>"));
+/// ```
 pub struct Document {
     //{{{
     doc: ArcDom,
+    context: Rc<Context>,
 }
 
-fn default_parse_opts() -> ParseOpts {
-    ParseOpts {
-        tree_builder: TreeBuilderOpts {
-            drop_doctype: true,
-            ..Default::default()
-        },
-        ..Default::default()
-    }
+/// Document wide information shared by all elements of the document
+#[derive(Default)]
+pub(crate) struct Context {
+    /// Source information keyed by node address
+    sources: HashMap<usize, Source>,
+    /// Url relative links are resolved against
+    base_url: Option<Url>,
+    /// Errors reported by the parser
+    errors: Vec<ParseError>,
+    /// Limit which made the parser stop
+    exceeded: Option<Limit>,
+    /// Changes recorded by open transactions
+    journal: RefCell<transaction::Journal>,
+    /// Elements by id and class, built by queries which can use it
+    index: RefCell<Option<index::Index>>,
 }
 
-impl From<&str> for Document {
-    /// Create document from a string slice
-    fn from(input: &str) -> Self {
-        let doc = parse_document(ArcDom::default(), default_parse_opts())
-            .from_utf8()
-            .read_from(&mut input.as_bytes())
-            .expect("could not parse html input");
-
-        Self { doc }
-    }
+/// Where the node came from in the original input
+struct Source {
+    /// Keeps node address from being reused while the entry exists
+    _handle: WeakHandle,
+    line: u64,
+    /// Column of the start of the markup in characters, starting from 1
+    column: u64,
+    range: Range<usize>,
+    /// Attributes as written in the start tag, kept only when parser dropped duplicates
+    raw_attrs: Option<Vec<(String, String)>>,
 }
 
-impl From<String> for Document {
-    /// Create document from String
-    fn from(input: String) -> Self {
-        Self::from(input.as_str())
+impl Context {
+    fn set_source(&mut self, handle: &Handle, line: u64, column: u64, range: Range<usize>) {
+        let source = Source {
+            _handle: Arc::downgrade(handle),
+            line,
+            column,
+            range,
+            raw_attrs: None,
+        };
+        self.sources.insert(Arc::as_ptr(handle) as usize, source);
     }
-}
 
-impl Document {
-    /// Select elements using given css selector
-    ///
-    /// # Example
-    /// ```
-    /// use crabquery::Document;
-    ///
-    /// let doc = Document::from("<span>hi there</span>");
-    /// let sel = doc.select("span");
-    /// let el = sel.first().unwrap();
-    ///
-    /// assert_eq!(el.text().unwrap(), "hi there");
-    /// ```
-    pub fn select(&self, selector: &str) -> Vec<Element> {
-        let sel = Selector::from(selector);
-        sel.find(self.doc.document.children.borrow())
+    fn set_source_end(&mut self, handle: &Handle, end: usize) {
+        if let Some(source) = self.sources.get_mut(&(Arc::as_ptr(handle) as usize)) {
+            source.range.end = end.max(source.range.end);
+        }
     }
-} //}}}
-
-#[derive(Debug, PartialEq, Clone)]
-enum AttributeSpec {
-    //{{{
-    /// Implementation of [attribute] selector
-    Present,
-    /// Implementation of [attribute="value"] selector
-    Exact(String),
-    // Implementation of [attribute~="value"] selector
-    // ContainsWord(String, String),
-    // Implementation of [attribute|="value"] selector
-    // StartsWord(String, String),
-    /// Implementation of [attribute^="value"] selector
-    Starts(String),
-    /// Implementation of [attribute$="value"] selector
-    Ends(String),
-    /// Implementation of [attribute*="value"] selector
-    Contains(String),
-}
 
-impl AttributeSpec {
-    fn matches(&self, other: String) -> bool {
-        use AttributeSpec::*;
+    fn remove_source(&mut self, handle: &Handle) {
+        self.sources.remove(&(Arc::as_ptr(handle) as usize));
+    }
 
-        match self {
-            Present => true,
-            Exact(v) => &other == v,
-            Starts(v) => other.starts_with(v),
-            Ends(v) => other.ends_with(v),
-            Contains(v) => other.contains(v),
+    fn set_raw_attrs(&mut self, handle: &Handle, attrs: Vec<(String, String)>) {
+        if let Some(source) = self.sources.get_mut(&(Arc::as_ptr(handle) as usize)) {
+            source.raw_attrs = Some(attrs);
         }
     }
-} //}}}
-
-#[derive(Debug, PartialEq, Clone)]
-struct Matcher {
-    //{{{
-    tag: Vec<String>,
-    class: Vec<String>,
-    id: Vec<String>,
-    attribute: HashMap<String, AttributeSpec>,
-    direct_match: bool,
-}
 
-impl From<String> for Matcher {
-    fn from(input: String) -> Self {
-        Self::from(input.as_str())
+    fn source(&self, handle: &Handle) -> Option<&Source> {
+        self.sources.get(&(Arc::as_ptr(handle) as usize))
     }
 }
 
-impl From<&str> for Matcher {
-    fn from(input: &str) -> Self {
-        let mut segments = vec![];
-        let mut buf = "".to_string();
-
-        for c in input.chars() {
-            match c {
-                '>' => {
-                    return Self {
-                        tag: vec![],
-                        class: vec![],
-                        id: vec![],
-                        attribute: HashMap::new(),
-                        direct_match: true,
-                    };
-                }
-                '#' | '.' | '[' => {
-                    segments.push(buf);
-                    buf = "".to_string();
-                }
-                ']' => {
-                    segments.push(buf);
-                    buf = "".to_string();
-                    continue;
-                }
-                _ => {}
-            };
-
-            buf.push(c);
-        }
-        segments.push(buf);
-
-        let mut res = Self {
-            tag: vec![],
-            class: vec![],
-            id: vec![],
-            attribute: HashMap::new(),
-            direct_match: false,
-        };
+/// Position of an element in the original html input
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[non_exhaustive]
+pub struct SourceLocation {
+    /// Line number (starting from 1) on which the start tag of the element ends
+    pub line: u64,
+    /// Column (starting from 1, counted in characters) at which the start tag begins,
+    /// for tags spanning several lines it is the column on the first of them.
+    /// Not known for elements found by `SelectStream`.
+    pub column: Option<u64>,
+}
 
-        for segment in segments {
-            match segment.chars().next() {
-                Some('#') => res.id.push(segment[1..].to_string()),
-                Some('.') => res.class.push(segment[1..].to_string()),
-                Some('[') => res.add_data_attribute(segment[1..].to_string()),
-                None => {}
-                _ => res.tag.push(segment),
-            }
-        }
+/// Error in the html input reported by the parser, input is still parsed recovering from it
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseError {
+    /// Line number (starting from 1) parser was at when error was found
+    pub line: u64,
+    /// Description of the error
+    pub message: String,
+}
 
-        res
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
     }
 }
 
-impl Matcher {
-    fn add_data_attribute(&mut self, spec: String) {
-        use AttributeSpec::*;
-
-        let parts = spec.split('=').collect::<Vec<_>>();
-
-        if parts.len() == 1 {
-            let k = parts[0];
-            self.attribute.insert(k.to_string(), Present);
-            return;
-        }
-
-        let v = parts[1].trim_matches('"').to_string();
-        let k = parts[0];
-        let k = k[..k.len() - 1].to_string();
-
-        match parts[0].chars().last() {
-            Some('^') => {
-                self.attribute.insert(k, Starts(v));
-            }
-            Some('$') => {
-                self.attribute.insert(k, Ends(v));
-            }
-            Some('*') => {
-                self.attribute.insert(k, Contains(v));
-            }
-            Some(_) => {
-                let k = parts[0].to_string();
-                self.attribute.insert(k, Exact(v));
-            }
-            None => {
-                panic!("Colud not parse attribute spec \"{}\"", spec);
-            }
-        }
-    }
+/// Rendering mode browsers would use for the document, determined by its doctype
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum QuirksMode {
+    /// Standards mode
+    NoQuirks,
+    /// Almost standards mode
+    LimitedQuirks,
+    /// Full quirks mode, e.g. for documents without doctype
+    Quirks,
+}
 
-    fn matches(&self, name: &QualName, attrs: Ref<'_, Vec<Attribute>>) -> bool {
-        let mut id_match = self.id.is_empty();
-        if let Some(el_id) = get_attr(&attrs, "id") {
-            let el_ids: Vec<_> = el_id.split_whitespace().collect();
-            id_match = self.id.iter().all(|id| el_ids.iter().any(|eid| eid == id))
-        }
+/// Document type declaration like `<!DOCTYPE html>`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Doctype {
+    /// Name, `html` for html documents
+    pub name: String,
+    /// Public identifier of legacy doctypes, empty if missing
+    pub public_id: String,
+    /// System identifier of legacy doctypes, empty if missing
+    pub system_id: String,
+}
 
-        let mut class_match = self.class.is_empty();
-        if let Some(el_class) = get_attr(&attrs, "class") {
-            let el_classes: Vec<_> = el_class.split_whitespace().collect();
+impl Clone for Document {
+    /// Deep copy of the document, changes to the copy do not affect the original.
+    ///
+    /// Every node keeps a single parent pointer, so a subtree can not be shared by two
+    /// trees and the copy takes time proportional to the size of the document.
+    /// To try out a few changes without copying a large document change it in place
+    /// inside of a transaction started with `Document::begin` and roll it back afterwards.
+    fn clone(&self) -> Self {
+        let document = deep_clone(&self.doc.document);
 
-            class_match = self
-                .class
-                .iter()
-                .all(|class| el_classes.iter().any(|eclass| eclass == class))
-        }
+        let mut context = Context {
+            base_url: self.context.base_url.clone(),
+            errors: self.context.errors.clone(),
+            exceeded: self.context.exceeded,
+            ..Default::default()
+        };
+        copy_sources(&self.doc.document, &document, &self.context, &mut context);
 
-        let mut attr_match = true;
-        for (k, v) in &self.attribute {
-            if let Some(value) = get_attr(&attrs, k.as_str()) {
-                if !v.matches(value) {
-                    attr_match = false;
-                    break;
-                }
-            } else {
-                attr_match = false;
-                break;
-            }
+        Self {
+            doc: ArcDom {
+                document,
+                errors: vec![],
+                quirks_mode: self.doc.quirks_mode,
+            },
+            context: Rc::new(context),
         }
-
-        let name = name.local.to_string();
-        let tag_match = self.tag.is_empty() || self.tag.iter().any(|tag| &name == tag);
-        // println!(
-        //     "for: {:?} \n {:?} \n {:?} \n tag_match: {}, id_match: {}, class_match: {}, attr_match: {} \n",
-        //     &self, name, attrs,
-        //     tag_match, id_match, class_match, attr_match
-        // );
-
-        tag_match && id_match && class_match && attr_match
     }
 }
-//}}}
-
-#[derive(Debug, PartialEq)]
-struct Selector {
-    //{{{
-    matchers: Vec<Matcher>,
-}
 
-impl From<&str> for Selector {
+impl From<&str> for Document {
+    /// Create document from a string slice
     fn from(input: &str) -> Self {
-        let matchers: Vec<_> = input.split_whitespace().map(Matcher::from).collect();
-
-        Selector { matchers }
+        DocumentBuilder::new().parse(input)
     }
 }
 
-fn get_attr(attrs: &Ref<'_, Vec<Attribute>>, name: &str) -> Option<String> {
-    attrs
-        .iter()
-        .filter(|attr| &attr.name.local == name)
-        .take(1)
-        .map(|attr| attr.value.to_string())
-        .collect::<Vec<_>>()
-        .pop()
+impl From<String> for Document {
+    /// Create document from String
+    fn from(input: String) -> Self {
+        Self::from(input.as_str())
+    }
 }
 
-impl Selector {
-    fn find_nodes(
-        &self,
-        matcher: &Matcher,
-        elements: Vec<Handle>,
-        direct_match: bool,
-    ) -> Vec<Handle> {
-        let mut acc = vec![];
-
-        for el in elements.iter() {
-            if !direct_match {
-                let children: Vec<_> = el.children.borrow().iter().map(Arc::clone).collect();
-                acc.append(&mut self.find_nodes(matcher, children, false));
-            }
-
-            match el.data {
-                NodeData::Element {
-                    ref name,
-                    ref attrs,
-                    ..
-                } if matcher.matches(name, attrs.borrow()) => {
-                    acc.push(Arc::clone(&el));
-                }
-                _ => {}
-            };
-        }
+/// `TryFrom<&str>` comes with `From<&str>` and never fails,
+/// bytes can fail to be valid utf-8
+impl TryFrom<&[u8]> for Document {
+    type Error = Error;
 
-        acc
+    /// Create document from utf-8 encoded bytes, same as `Document::try_from_bytes`
+    fn try_from(bytes: &[u8]) -> Result<Self, Error> {
+        Self::try_from_bytes(bytes)
     }
+}
 
-    fn find(&self, elements: Ref<'_, Vec<Handle>>) -> Vec<Element> {
-        let mut elements: Vec<_> = elements.iter().map(Arc::clone).collect();
-        let mut direct_match = false;
-
-        for matcher in &self.matchers {
-            if matcher.direct_match {
-                direct_match = true;
-                elements = elements
-                    .iter()
-                    .flat_map(|el| {
-                        el.children
-                            .borrow()
-                            .iter()
-                            .map(Arc::clone)
-                            .collect::<Vec<_>>()
-                    })
-                    .collect();
-                continue;
-            }
-            elements = self.find_nodes(matcher, elements, direct_match);
-            direct_match = false;
-        }
-
-        elements.iter().map(Element::from).collect()
+impl Document {
+    /// Create document from utf-8 encoded bytes,
+    /// fails with `Error::InvalidUtf8` instead of replacing invalid sequences
+    ///
+    /// # Arguments
+    /// * `bytes` - utf-8 encoded html
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Error};
+    /// use std::convert::TryFrom;
+    ///
+    /// let doc = Document::try_from_bytes(b"<p>caf\xc3\xa9</p>").unwrap();
+    /// assert_eq!(doc.select_first("p").unwrap().text().unwrap(), "café");
+    ///
+    /// let res = Document::try_from(&b"<p>caf\xe9</p>"[..]);
+    /// assert!(matches!(res, Err(Error::InvalidUtf8(_))));
+    /// ```
+    pub fn try_from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let input = std::str::from_utf8(bytes).map_err(Error::InvalidUtf8)?;
+        Ok(Self::from(input))
     }
-} //}}}
-
-pub struct Element {
-    //{{{
-    handle: Handle,
-}
 
-impl From<Handle> for Element {
-    fn from(e: Handle) -> Self {
-        Self::from(&e)
+    /// Parse document collecting errors found in the input with detailed descriptions,
+    /// useful for validation of the markup. Parser recovers from the errors
+    /// so the document is the same one `Document::from` would return.
+    ///
+    /// # Arguments
+    /// * `input` - html
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let (doc, errors) = Document::try_parse("<div><p>one</div>\n</span>").unwrap();
+    ///
+    /// assert_eq!(doc.select("p").len(), 1);
+    /// assert!(!errors.is_empty());
+    /// assert_eq!(errors.last().unwrap().line, 2);
+    /// ```
+    pub fn try_parse(input: &str) -> Result<(Self, Vec<ParseError>), Error> {
+        DocumentBuilder::new().exact_errors(true).try_parse(input)
     }
-}
 
-impl From<&Handle> for Element {
-    fn from(e: &Handle) -> Self {
-        Element {
-            handle: Arc::clone(e),
-        }
+    /// Create document reading html from any reader, input is passed to the parser
+    /// chunk by chunk as it is read. Invalid utf-8 sequences are replaced.
+    ///
+    /// # Arguments
+    /// * `reader` - source of utf-8 encoded html, e.g. file or response body
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let html: &[u8] = b"<ul><li>one</li><li>two</li></ul>";
+    /// let doc = Document::from_reader(html).unwrap();
+    ///
+    /// assert_eq!(doc.select("li").len(), 2);
+    /// ```
+    pub fn from_reader<R: Read>(reader: R) -> io::Result<Self> {
+        DocumentBuilder::new().parse_reader(reader)
     }
-}
 
-impl Element {
-    /// Get value of an attribue
+    /// Create document reading html from async reader, available with `tokio` feature.
+    /// Input is passed to the parser chunk by chunk as it arrives,
+    /// the parser is not `Send` so returned future should be awaited on the current task.
     ///
     /// # Arguments
-    /// * `name` - attribute name
+    /// * `reader` - source of utf-8 encoded html, e.g. response body stream
     ///
     /// # Example
     /// ```
     /// use crabquery::Document;
     ///
-    /// let doc = Document::from("<a class='link'>hi there</a>");
-    /// let sel = doc.select("a");
-    /// let el = sel.first().unwrap();
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() {
+    /// let html: &[u8] = b"<ul><li>one</li><li>two</li></ul>";
+    /// let doc = Document::from_async_reader(html).await.unwrap();
     ///
-    /// assert_eq!(el.attr("class").unwrap(), "link");
+    /// assert_eq!(doc.select("li").len(), 2);
+    /// # }
     /// ```
-    pub fn attr(&self, name: &str) -> Option<String> {
-        match self.handle.data {
-            NodeData::Element { ref attrs, .. } => get_attr(&attrs.borrow(), name),
-            _ => None,
-        }
+    #[cfg(feature = "tokio")]
+    pub async fn from_async_reader<R: tokio::io::AsyncRead + Unpin>(reader: R) -> io::Result<Self> {
+        DocumentBuilder::new().parse_async_reader(reader).await
     }
 
-    /// Get tag value
+    /// Create document from html bytes in given encoding, available with `encoding_rs` feature.
+    /// Byte order mark takes precedence over given encoding, invalid sequences are replaced.
+    /// Source ranges of elements point to the decoded input.
+    ///
+    /// # Arguments
+    /// * `bytes` - encoded html
+    /// * `encoding` - encoding of the bytes
     ///
     /// # Example
     /// ```
     /// use crabquery::Document;
+    /// use crabquery::encoding_rs::WINDOWS_1251;
     ///
-    /// let doc = Document::from("<a class='link'>hi there</a>");
-    /// let sel = doc.select("a");
-    /// let el = sel.first().unwrap();
+    /// let doc = Document::from_bytes_with_encoding(b"<p>\xcf\xf0\xe8\xe2\xe5\xf2</p>", WINDOWS_1251);
     ///
-    /// assert_eq!(el.tag().unwrap(), "a");
+    /// assert_eq!(doc.select_first("p").unwrap().text().unwrap(), "Привет");
     /// ```
-    pub fn tag(&self) -> Option<String> {
-        match self.handle.data {
-            NodeData::Element { ref name, .. } => Some(name.local.to_string()),
-            _ => None,
+    #[cfg(feature = "encoding_rs")]
+    pub fn from_bytes_with_encoding(
+        bytes: &[u8],
+        encoding: &'static encoding_rs::Encoding,
+    ) -> Self {
+        DocumentBuilder::new().parse_bytes_with_encoding(bytes, encoding)
+    }
+
+    /// Create document from html bytes detecting their encoding, available with `encoding_rs`
+    /// feature. Encoding is taken from byte order mark or `<meta>` charset declaration,
+    /// input without them is decoded as utf-8 if it is valid and as windows-1252 otherwise.
+    ///
+    /// # Arguments
+    /// * `bytes` - encoded html
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from_bytes(b"<meta charset='iso-8859-1'><p>caf\xe9</p>");
+    ///
+    /// assert_eq!(doc.select_first("p").unwrap().text().unwrap(), "café");
+    /// ```
+    #[cfg(feature = "encoding_rs")]
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        DocumentBuilder::new().parse_bytes(bytes)
+    }
+
+    /// Restore document saved with `to_binary`, available with `binary` feature
+    ///
+    /// # Arguments
+    /// * `bytes` - document in binary format
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li>two</li></ul>");
+    /// let restored = Document::from_binary(&doc.to_binary()).unwrap();
+    ///
+    /// assert_eq!(restored.select("li").len(), 2);
+    /// assert!(Document::from_binary(b"<ul></ul>").is_err());
+    /// ```
+    #[cfg(feature = "binary")]
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, Error> {
+        binary::decode(bytes)
+    }
+
+    /// Save document in compact binary format which is faster to load than parsing html,
+    /// source locations and base url are kept. Available with `binary` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let html = "<p>hi <a href='/x'>there</a></p>";
+    /// let doc = Document::from(html);
+    /// let restored = Document::from_binary(&doc.to_binary()).unwrap();
+    ///
+    /// assert_eq!(restored.html(), doc.html());
+    /// let a = restored.select_first("a").unwrap();
+    /// assert_eq!(&html[a.source_range().unwrap()], "<a href='/x'>there</a>");
+    /// ```
+    #[cfg(feature = "binary")]
+    pub fn to_binary(&self) -> Vec<u8> {
+        binary::encode(self)
+    }
+
+    /// Build document from JSON tree of nodes without going through html,
+    /// available with `serde_json` feature. Node is either a string for a text
+    /// or an object with `tag`, optional `attrs` object and optional `children` array.
+    /// Nodes are added as given, parser does not add `<html>` or `<body>`.
+    ///
+    /// # Arguments
+    /// * `value` - root node or array of root nodes
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    /// use serde_json::json;
+    ///
+    /// let doc = Document::from_json(&json!({
+    ///     "tag": "ul",
+    ///     "attrs": {"class": "menu"},
+    ///     "children": [
+    ///         {"tag": "li", "children": ["one"]},
+    ///         {"tag": "li", "children": ["two"]},
+    ///     ],
+    /// }))
+    /// .unwrap();
+    ///
+    /// assert_eq!(doc.select("ul.menu > li")[1].text().unwrap(), "two");
+    /// assert!(Document::from_json(&json!({"attrs": {}})).is_err());
+    /// ```
+    #[cfg(feature = "serde_json")]
+    pub fn from_json(value: &serde_json::Value) -> Result<Self, Error> {
+        json::decode(value)
+    }
+
+    /// Create document from html fragment parsed as content of given context element,
+    /// that way snippets like table rows or list items are kept as is.
+    /// Top level nodes of the fragment become children of the document.
+    ///
+    /// # Arguments
+    /// * `html` - html fragment
+    /// * `context` - tag name of the element fragment belongs to, e.g. `tr` or `ul`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::parse_fragment("<td>one</td><td>two</td>", "tr");
+    ///
+    /// assert_eq!(doc.select("td").len(), 2);
+    /// assert!(doc.select("table").is_empty());
+    /// ```
+    pub fn parse_fragment(html: &str, context: &str) -> Self {
+        DocumentBuilder::new().parse_fragment(html, context)
+    }
+
+    /// Create document from several html fragments (e.g. pages of paginated response),
+    /// fragments are parsed as content of `<body>` and their top level nodes become
+    /// children of the document in the given order. Source locations refer to the fragment
+    /// the element came from.
+    ///
+    /// # Arguments
+    /// * `fragments` - html fragments
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from_fragments(&[
+    ///     "<li class='item'>one</li><li class='item'>two</li>",
+    ///     "<li class='item'>three</li>",
+    /// ]);
+    ///
+    /// assert_eq!(doc.select(".item").len(), 3);
+    /// assert_eq!(doc.select(".item")[2].text().unwrap(), "three");
+    /// ```
+    pub fn from_fragments(fragments: &[&str]) -> Self {
+        let doc = ArcDom::default();
+        let mut context = Context::default();
+
+        for html in fragments {
+            let fragment = Document::parse_fragment(html, "body");
+            let children = std::mem::take(&mut *fragment.doc.document.children.borrow_mut());
+            for child in children {
+                append_child(&doc.document, child);
+            }
+            if let Ok(fragment_context) = Rc::try_unwrap(fragment.context) {
+                context.sources.extend(fragment_context.sources);
+                context.errors.extend(fragment_context.errors);
+            }
+        }
+
+        Document {
+            doc,
+            context: Rc::new(context),
+        }
+    }
+
+    /// Create document with given url, relative urls are resolved against it
+    /// or against `<base href>` of the document which itself is resolved against the url
+    ///
+    /// # Arguments
+    /// * `html` - html
+    /// * `url` - absolute url the document was loaded from
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let html = "<base href='/docs/'><a href='intro.html'>intro</a>";
+    /// let doc = Document::from_with_base(html, "https://example.com/index.html").unwrap();
+    ///
+    /// assert_eq!(doc.base_url().unwrap(), "https://example.com/docs/");
+    /// assert_eq!(
+    ///     doc.select_first("a").unwrap().abs_attr("href").unwrap(),
+    ///     "https://example.com/docs/intro.html"
+    /// );
+    /// ```
+    pub fn from_with_base(html: &str, url: &str) -> Result<Self, Error> {
+        Ok(DocumentBuilder::new().base_url(url)?.parse(html))
+    }
+
+    /// Get url relative urls of the document are resolved against
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<base href='https://example.com/docs/'><p>hi there</p>");
+    /// assert_eq!(doc.base_url().unwrap(), "https://example.com/docs/");
+    ///
+    /// let doc = Document::from("<p>hi there</p>");
+    /// assert!(doc.base_url().is_none());
+    /// ```
+    pub fn base_url(&self) -> Option<String> {
+        self.context.base_url.as_ref().map(|url| url.to_string())
+    }
+
+    /// Select elements using given css selector, matches are in document order
+    /// and every element is included once. Selector which can not be parsed matches nothing,
+    /// use `try_select` to tell it apart from no matches.
+    ///
+    /// When the last part of the selector has an id or a class, like `#main a` or `li.item`,
+    /// only elements having it are checked. They are looked up in an index built
+    /// by the first such query and rebuilt after the document changes.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<span>hi there</span>");
+    /// let sel = doc.select("span");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.text().unwrap(), "hi there");
+    /// ```
+    pub fn select(&self, selector: &str) -> Selection {
+        let sel = Selector::compile(selector);
+        self.find_handles(&sel)
+            .iter()
+            .map(|handle| self.element(handle))
+            .collect()
+    }
+
+    /// Select elements using given css selector, fails with `Error::InvalidSelector`
+    /// when the selector can not be parsed
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Error};
+    ///
+    /// let doc = Document::from("<li>one</li><li>two</li>");
+    ///
+    /// assert_eq!(doc.try_select("li:nth-child(2)").unwrap().texts(), vec!["two"]);
+    /// assert!(matches!(doc.try_select("li:hover"), Err(Error::InvalidSelector(_))));
+    /// ```
+    pub fn try_select(&self, selector: &str) -> Result<Selection, Error> {
+        let sel = Selector::cached(selector)?;
+        Ok(self
+            .find_handles(&sel)
+            .iter()
+            .map(|handle| self.element(handle))
+            .collect())
+    }
+
+    /// Lazily select elements matching given css selector in document order,
+    /// the document is traversed only as far as the iterator is consumed
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li>two</li><li>three</li></ul>");
+    /// let items: Vec<_> = doc
+    ///     .select_iter("ul li")
+    ///     .take(2)
+    ///     .map(|li| li.text().unwrap())
+    ///     .collect();
+    ///
+    /// assert_eq!(items, vec!["one", "two"]);
+    /// ```
+    pub fn select_iter(&self, selector: &str) -> impl Iterator<Item = Element> {
+        let context = Rc::clone(&self.context);
+        Selector::compile(selector)
+            .iter(Arc::clone(&self.doc.document))
+            .map(move |handle| Element {
+                handle,
+                context: Rc::clone(&context),
+            })
+    }
+
+    /// Select first element in document order matching given css selector,
+    /// traversal stops as soon as the match is found
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<h1>one</h1><h1>two</h1>");
+    /// let el = doc.select_first("h1").unwrap();
+    ///
+    /// assert_eq!(el.text().unwrap(), "one");
+    /// ```
+    pub fn select_first(&self, selector: &str) -> Option<Element> {
+        let sel = Selector::compile(selector);
+        let root = &self.doc.document;
+        let first = match self.context.candidates(root, &sel) {
+            Some(nodes) => nodes.into_iter().find(|n| sel.matches_within(n, root)),
+            None => sel.find_first(root),
+        };
+
+        first.map(|handle| self.element(&handle))
+    }
+
+    /// Select at most given number of elements matching css selector in document order,
+    /// traversal stops once enough matches are found
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    /// * `limit` - maximum number of elements
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div class='card'>1</div><div class='card'>2</div><div class='card'>3</div>");
+    /// let cards = doc.select_with_limit(".card", 2);
+    ///
+    /// assert_eq!(cards.texts(), vec!["1", "2"]);
+    /// assert_eq!(doc.select_with_limit(".card", 10).len(), 3);
+    /// ```
+    pub fn select_with_limit(&self, selector: &str, limit: usize) -> Selection {
+        self.select_iter(selector).take(limit).collect()
+    }
+
+    /// Count elements matching given css selector without collecting them
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>1</li><li>2</li></ul><ol><li>3</li></ol>");
+    ///
+    /// assert_eq!(doc.select_count("li"), 3);
+    /// assert_eq!(doc.select_count("ul > li"), 2);
+    /// ```
+    pub fn select_count(&self, selector: &str) -> usize {
+        let sel = Selector::compile(selector);
+        let root = &self.doc.document;
+        match self.context.candidates(root, &sel) {
+            Some(nodes) => nodes.iter().filter(|n| sel.matches_within(n, root)).count(),
+            None => sel.iter(Arc::clone(root)).count(),
+        }
+    }
+
+    /// Get first element in document order matching given css selector,
+    /// same as `select_first`
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<h1>Title</h1><h1>Other</h1>");
+    ///
+    /// assert_eq!(doc.first("h1").unwrap().text().unwrap(), "Title");
+    /// ```
+    pub fn first(&self, selector: &str) -> Option<Element> {
+        self.select_first(selector)
+    }
+
+    /// Get last element in document order matching given css selector,
+    /// the document is walked from the end and traversal stops at the match
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li>two</li></ul><ul><li>three</li></ul><p>end</p>");
+    ///
+    /// assert_eq!(doc.last("li").unwrap().text().unwrap(), "three");
+    /// assert!(doc.last("table").is_none());
+    /// ```
+    pub fn last(&self, selector: &str) -> Option<Element> {
+        Selector::compile(selector)
+            .find_last(&self.doc.document)
+            .map(|handle| self.element(&handle))
+    }
+
+    /// Get element matching given css selector at given position in document order,
+    /// traversal stops once it is found
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    /// * `n` - position counting from zero
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<li>one</li><li>two</li><li>three</li>");
+    ///
+    /// assert_eq!(doc.nth("li", 1).unwrap().text().unwrap(), "two");
+    /// assert!(doc.nth("li", 3).is_none());
+    /// ```
+    pub fn nth(&self, selector: &str, n: usize) -> Option<Element> {
+        self.select_iter(selector).nth(n)
+    }
+
+    /// Check if any element matches given css selector,
+    /// traversal stops at the first match
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<form><div class='captcha'></div></form>");
+    ///
+    /// assert!(doc.exists(".captcha"));
+    /// assert!(!doc.exists("form .login"));
+    /// ```
+    pub fn exists(&self, selector: &str) -> bool {
+        self.select_first(selector).is_some()
+    }
+
+    /// Select elements using multiple named css selectors in a single traversal of the document,
+    /// matches of each selector are in document order without duplicates
+    ///
+    /// # Arguments
+    /// * `selectors` - pairs of names and selectors, results are keyed by the names
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<h1>Title</h1><a href='/one'>one</a><a href='/two'>two</a>");
+    /// let res = doc.select_map(&[("title", "h1"), ("links", "a[href]"), ("images", "img")]);
+    ///
+    /// assert_eq!(res["title"][0].text().unwrap(), "Title");
+    /// assert_eq!(res["links"].len(), 2);
+    /// assert!(res["images"].is_empty());
+    /// ```
+    pub fn select_map<'a>(&self, selectors: &[(&'a str, &str)]) -> HashMap<&'a str, Vec<Element>> {
+        let compiled: Vec<_> = selectors
+            .iter()
+            .map(|(name, selector)| (*name, Selector::compile(selector)))
+            .collect();
+        let mut res: HashMap<_, Vec<_>> =
+            selectors.iter().map(|(name, _)| (*name, vec![])).collect();
+
+        let mut stack: Vec<_> = self
+            .doc
+            .document
+            .children
+            .borrow()
+            .iter()
+            .rev()
+            .cloned()
+            .collect();
+        while let Some(node) = stack.pop() {
+            if is_element(&node) {
+                for (name, sel) in compiled.iter() {
+                    if sel.matches(&node) {
+                        if let Some(matched) = res.get_mut(name) {
+                            matched.push(self.element(&node));
+                        }
+                    }
+                }
+            }
+            stack.extend(node.children.borrow().iter().rev().cloned());
+        }
+
+        res
+    }
+
+    /// Extract repeated records, for every element matching the container selector
+    /// first descendant matching each field selector is looked up. Fields missing
+    /// from a record are `None` so values never shift between records.
+    ///
+    /// # Arguments
+    /// * `container` - css selector of elements holding single record each
+    /// * `fields` - pairs of names and selectors of fields relative to the container
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(
+    ///     "<ul><li class='product'><b class='title'>Tea</b><i class='price'>3</i></li>\
+    ///      <li class='product'><b class='title'>Cup</b></li></ul>",
+    /// );
+    /// let records = doc.select_records("li.product", &[("name", ".title"), ("price", ".price")]);
+    ///
+    /// assert_eq!(records.len(), 2);
+    /// assert_eq!(records[0]["name"].as_ref().unwrap().text().unwrap(), "Tea");
+    /// assert_eq!(records[0]["price"].as_ref().unwrap().text().unwrap(), "3");
+    /// assert_eq!(records[1]["name"].as_ref().unwrap().text().unwrap(), "Cup");
+    /// assert!(records[1]["price"].is_none());
+    /// ```
+    pub fn select_records<'a>(
+        &self,
+        container: &str,
+        fields: &[(&'a str, &str)],
+    ) -> Vec<HashMap<&'a str, Option<Element>>> {
+        let compiled: Vec<_> = fields
+            .iter()
+            .map(|(name, selector)| (*name, Selector::compile(selector)))
+            .collect();
+
+        self.select(container)
+            .iter()
+            .map(|record| {
+                compiled
+                    .iter()
+                    .map(|(name, sel)| {
+                        let field = sel.find_first(&record.handle);
+                        (*name, field.map(|handle| self.element(&handle)))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Get quirks mode determined by the parser from the doctype of the document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, QuirksMode};
+    ///
+    /// let doc = Document::from("<!DOCTYPE html><p>hi there</p>");
+    /// assert_eq!(doc.quirks_mode(), QuirksMode::NoQuirks);
+    ///
+    /// let doc = Document::from("<p>hi there</p>");
+    /// assert_eq!(doc.quirks_mode(), QuirksMode::Quirks);
+    /// ```
+    pub fn quirks_mode(&self) -> QuirksMode {
+        match self.doc.quirks_mode {
+            html5ever::tree_builder::QuirksMode::NoQuirks => QuirksMode::NoQuirks,
+            html5ever::tree_builder::QuirksMode::LimitedQuirks => QuirksMode::LimitedQuirks,
+            html5ever::tree_builder::QuirksMode::Quirks => QuirksMode::Quirks,
+        }
+    }
+
+    /// Get doctype of the document, available only when it is kept
+    /// with `DocumentBuilder::drop_doctype(false)`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::DocumentBuilder;
+    ///
+    /// let doc = DocumentBuilder::new()
+    ///     .drop_doctype(false)
+    ///     .parse("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01//EN\"><p>hi there</p>");
+    /// let doctype = doc.doctype().unwrap();
+    ///
+    /// assert_eq!(doctype.name, "html");
+    /// assert_eq!(doctype.public_id, "-//W3C//DTD HTML 4.01//EN");
+    /// ```
+    pub fn doctype(&self) -> Option<Doctype> {
+        self.doc
+            .document
+            .children
+            .borrow()
+            .iter()
+            .find_map(|n| match n.data {
+                NodeData::Doctype {
+                    ref name,
+                    ref public_id,
+                    ref system_id,
+                } => Some(Doctype {
+                    name: name.to_string(),
+                    public_id: public_id.to_string(),
+                    system_id: system_id.to_string(),
+                }),
+                _ => None,
+            })
+    }
+
+    /// Get the root element of the document, usually `<html>`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>hi there</p>");
+    ///
+    /// assert_eq!(doc.root().unwrap().tag().unwrap(), "html");
+    /// ```
+    pub fn root(&self) -> Option<Element> {
+        self.doc
+            .document
+            .children
+            .borrow()
+            .iter()
+            .find(|n| is_element(n))
+            .map(|n| self.element(n))
+    }
+
+    /// Get `<head>` element of the document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<title>Crabs</title><p>hi there</p>");
+    ///
+    /// assert_eq!(doc.head().unwrap().children()[0].tag().unwrap(), "title");
+    /// ```
+    pub fn head(&self) -> Option<Element> {
+        self.root_child(&["head"])
+    }
+
+    /// Get `<body>` element of the document, or `<frameset>` for documents using frames
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<title>Crabs</title><p>hi there</p>");
+    ///
+    /// assert_eq!(doc.body().unwrap().children()[0].tag().unwrap(), "p");
+    /// ```
+    pub fn body(&self) -> Option<Element> {
+        self.root_child(&["body", "frameset"])
+    }
+
+    /// Get text of the document `<title>` with stripped and collapsed whitespace
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<title>\n  Crabs &amp;\n  Lobsters </title>");
+    ///
+    /// assert_eq!(doc.title().unwrap(), "Crabs & Lobsters");
+    /// ```
+    pub fn title(&self) -> Option<String> {
+        let title = self.select_first("title")?;
+        let text = title.descendant_text_nodes().concat();
+
+        Some(text.split_ascii_whitespace().collect::<Vec<_>>().join(" "))
+    }
+
+    /// First child element of the root with one of given tag names
+    fn root_child(&self, tags: &[&str]) -> Option<Element> {
+        self.root()?
+            .children()
+            .into_iter()
+            .find(|el| tags.contains(&el.tag().unwrap_or_default().as_str()))
+    }
+
+    /// Serialize the whole document back to html
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<title>Crabs</title><p class=x>hi &amp; bye");
+    ///
+    /// assert_eq!(
+    ///     doc.html(),
+    ///     "<html><head><title>Crabs</title></head><body><p class=\"x\">hi &amp; bye</p></body></html>"
+    /// );
+    /// ```
+    pub fn html(&self) -> String {
+        serialize::to_html(&self.doc.document, TraversalScope::ChildrenOnly(None))
+    }
+
+    /// Serialize the whole document back to html choosing how text and attribute values
+    /// are escaped, e.g. for systems which can not handle anything but ascii
+    ///
+    /// # Arguments
+    /// * `escaping` - escaping of text and attribute values
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Escaping};
+    ///
+    /// let doc = Document::from("<p title='café'>Grüße &amp; ✓</p>");
+    ///
+    /// assert_eq!(doc.html_with(Escaping::Minimal), doc.html());
+    /// assert!(doc
+    ///     .html_with(Escaping::Ascii)
+    ///     .contains("<p title=\"caf&#xE9;\">Gr&#xFC;&#xDF;e &amp; &#x2713;</p>"));
+    /// ```
+    pub fn html_with(&self, escaping: Escaping) -> String {
+        serialize::to_html_with(
+            &self.doc.document,
+            TraversalScope::ChildrenOnly(None),
+            escaping,
+        )
+    }
+
+    /// Serialize the document into bytes of given encoding, available with `encoding_rs`
+    /// feature. Charset declared by `<meta>` tags is updated to the encoding, or a
+    /// `<meta charset>` is added to the head when there is none, the document itself is not
+    /// changed. Characters the encoding can not represent are written as numeric character
+    /// references. UTF-16 encodings are written as UTF-8, like browsers do when submitting forms.
+    ///
+    /// # Arguments
+    /// * `encoding` - output encoding
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    /// use crabquery::encoding_rs::WINDOWS_1251;
+    ///
+    /// let doc = Document::from("<meta charset=utf-8><p>Привет ✓</p>");
+    ///
+    /// assert_eq!(
+    ///     doc.to_bytes(WINDOWS_1251),
+    ///     b"<html><head><meta charset=\"windows-1251\"></head>\
+    ///       <body><p>\xcf\xf0\xe8\xe2\xe5\xf2 &#10003;</p></body></html>".to_vec()
+    /// );
+    /// ```
+    #[cfg(feature = "encoding_rs")]
+    pub fn to_bytes(&self, encoding: &'static encoding_rs::Encoding) -> Vec<u8> {
+        let encoding = encoding.output_encoding();
+        let copy = self.clone();
+
+        let mut declared = false;
+        for meta in copy.select("meta") {
+            if meta.has_attr("charset") {
+                meta.set_attr("charset", encoding.name());
+                declared = true;
+            } else if meta
+                .attr("http-equiv")
+                .map(|value| value.eq_ignore_ascii_case("content-type"))
+                .unwrap_or(false)
+            {
+                meta.set_attr(
+                    "content",
+                    &format!("text/html; charset={}", encoding.name()),
+                );
+                declared = true;
+            }
+        }
+        if !declared {
+            if let Some(head) = copy.head() {
+                let meta = copy
+                    .create_element("meta")
+                    .with_attr("charset", encoding.name());
+                head.prepend_child(meta);
+            }
+        }
+
+        let html = copy.html();
+        let (bytes, _, _) = encoding.encode(&html);
+        bytes.into_owned()
+    }
+
+    /// Get indented html of the document for reading, every block element starts a new line
+    /// and text is wrapped at 80 columns. Whitespace of text is collapsed, except in elements
+    /// like `<pre>`, `<textarea>` or `<script>` which are written as they are.
+    ///
+    /// # Arguments
+    /// * `indent` - number of spaces per nesting level
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li>two <b>2</b></li></ul><pre>  keep\n  this</pre>");
+    ///
+    /// assert_eq!(
+    ///     doc.to_pretty_html(2),
+    ///     "<html>\n  <head></head>\n  <body>\n    <ul>\n      <li>one</li>\n      \
+    ///      <li>two <b>2</b></li>\n    </ul>\n    <pre>  keep\n  this</pre>\n  </body>\n</html>\n"
+    /// );
+    /// ```
+    pub fn to_pretty_html(&self, indent: usize) -> String {
+        pretty::to_pretty_html(&self.doc.document, indent)
+    }
+
+    /// Get compact html of the document which parses into the same tree: comments are
+    /// removed, whitespace which does not render is dropped and other whitespace runs are
+    /// collapsed, attribute values are unquoted and optional tags are left out where possible.
+    /// Content of elements like `<pre>`, `<textarea>` or `<script>` is written as it is.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(
+    ///     "<!-- menu -->\n<ul class='nav'>\n  <li><a href='/'>Home</a></li>\n  <li>About   us</li>\n</ul>",
+    /// );
+    ///
+    /// assert_eq!(
+    ///     doc.to_minified_html(),
+    ///     "<ul class=nav><li><a href=/>Home</a><li>About us</ul>"
+    /// );
+    /// ```
+    pub fn to_minified_html(&self) -> String {
+        minify::to_minified_html(&self.doc.document)
+    }
+
+    /// Get canonical copy of the document for deterministic comparisons and snapshot tests,
+    /// tag and attribute names are lowercased, attributes are sorted by name,
+    /// whitespace only text is dropped and other whitespace runs are collapsed into a space.
+    /// Text of `pre`, `textarea`, `script` and `style` elements is kept as is.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let a = Document::from("<div id='x' class='y'>\n  <p>hi   there</p>\n</div>");
+    /// let b = Document::from("<div class='y' id='x'><p>hi\nthere</p></div>");
+    ///
+    /// assert_eq!(a.normalize().html(), b.normalize().html());
+    /// assert_eq!(
+    ///     a.normalize().html(),
+    ///     "<html><head></head><body><div class=\"y\" id=\"x\"><p>hi there</p></div></body></html>"
+    /// );
+    /// ```
+    pub fn normalize(&self) -> Document {
+        let document =
+            normalize_tree(&self.doc.document).unwrap_or_else(|| Node::new(NodeData::Document));
+
+        Document {
+            doc: ArcDom {
+                document,
+                errors: vec![],
+                quirks_mode: self.doc.quirks_mode,
+            },
+            context: Rc::new(Context {
+                base_url: self.context.base_url.clone(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Get contents of all comments in the document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<!-- top --><div><!-- {\"id\": 1} --></div>");
+    ///
+    /// assert_eq!(doc.comments(), vec![" top ", " {\"id\": 1} "]);
+    /// ```
+    pub fn comments(&self) -> Vec<String> {
+        let mut acc = vec![];
+        collect_comments(&self.doc.document, &mut acc);
+        acc
+    }
+
+    /// Get elements between two marker elements in document order,
+    /// descendants of `start` and ancestors of `end` are not included.
+    /// Returns empty list when `end` does not follow `start`.
+    ///
+    /// # Arguments
+    /// * `start` - element the range starts after
+    /// * `end` - element the range ends before
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<h2>One</h2><p>a</p><ul><li>b</li></ul><h2>Two</h2>");
+    /// let headings = doc.select("h2");
+    /// let section = doc.elements_between(&headings[0], &headings[1]);
+    ///
+    /// let tags: Vec<_> = section.iter().map(|el| el.tag().unwrap()).collect();
+    /// assert_eq!(tags, vec!["p", "ul", "li"]);
+    /// ```
+    pub fn elements_between(&self, start: &Element, end: &Element) -> Vec<Element> {
+        let mut acc = vec![];
+        let mut node = start.next_in_document();
+
+        while let Some(el) = node {
+            if el == *end {
+                return acc;
+            }
+            if !start.contains(&el) && !el.contains(end) {
+                acc.push(self.element(&el.handle));
+            }
+            node = el.next_in_document();
+        }
+
+        vec![]
+    }
+
+    /// Compare element trees of the document and its newer version,
+    /// elements are matched by their tag and id and compared by attributes and own text.
+    /// Added and removed subtrees are reported only by their root element.
+    /// Children lists needing more than a thousand additions and removals
+    /// are reported as completely replaced.
+    ///
+    /// # Arguments
+    /// * `other` - newer version of the document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Change, Document};
+    ///
+    /// let old = Document::from("<ul><li>one</li><li>two</li></ul><p class='a'>hi</p>");
+    /// let new = Document::from("<ul><li>one</li></ul><p class='b'>hi</p><img>");
+    ///
+    /// assert_eq!(
+    ///     old.diff(&new),
+    ///     vec![
+    ///         Change::Removed("html > body > ul > li:nth-child(2)".to_string()),
+    ///         Change::Changed("html > body > p".to_string()),
+    ///         Change::Added("html > body > img".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn diff(&self, other: &Document) -> Vec<Change> {
+        diff::diff(self, other)
+    }
+
+    /// Get report of problems in the markup: tags closed or reordered by the parser,
+    /// duplicate ids and obsolete tags. Parser problems are reported in the order
+    /// they were found, the rest in document order.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Warning};
+    ///
+    /// let doc = Document::from("<div id='a'><span>one</div>\n<center id='a'>two</center>");
+    /// let warnings = doc.audit();
+    ///
+    /// assert!(matches!(warnings[0], Warning::UnclosedTag { line: 1, .. }));
+    /// assert!(warnings.contains(&Warning::DuplicateId {
+    ///     id: "a".to_string(),
+    ///     paths: vec![
+    ///         "html > body > div#a".to_string(),
+    ///         "html > body > center#a".to_string(),
+    ///     ],
+    /// }));
+    /// assert!(warnings.contains(&Warning::ObsoleteTag {
+    ///     tag: "center".to_string(),
+    ///     path: "html > body > center#a".to_string(),
+    /// }));
+    /// ```
+    pub fn audit(&self) -> Vec<Warning> {
+        audit::audit(self)
+    }
+
+    /// Rewrite `href`, `src`, `srcset`, `action`, `formaction` and `poster` attributes
+    /// of all elements to absolute urls, resolved against `<base href>` of the document
+    /// which itself is resolved against given url. Empty values and urls
+    /// which can not be resolved are left as is.
+    ///
+    /// # Arguments
+    /// * `base` - absolute url of the document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a href='../about'>about</a><img srcset='a.png 1x, /b.png 2x'>");
+    /// doc.absolutize_urls("https://example.com/docs/page").unwrap();
+    ///
+    /// assert_eq!(doc.select_first("a").unwrap().attr("href").unwrap(), "https://example.com/about");
+    /// assert_eq!(
+    ///     doc.select_first("img").unwrap().attr("srcset").unwrap(),
+    ///     "https://example.com/docs/a.png 1x, https://example.com/b.png 2x"
+    /// );
+    /// assert!(doc.absolutize_urls("not a url").is_err());
+    /// ```
+    pub fn absolutize_urls(&self, base: &str) -> Result<(), Error> {
+        let mut base = Url::parse(base).map_err(Error::InvalidUrl)?;
+        let href = Selector::from("base[href]")
+            .find_first(&self.doc.document)
+            .and_then(|el| get_attr(&element_attrs(&el)?, "href"));
+        if let Some(href) = href {
+            if let Ok(url) = base.join(href.trim()) {
+                base = url;
+            }
+        }
+        let resolve = |value: &str| -> Option<String> {
+            let value = value.trim();
+            if value.is_empty() {
+                return None;
+            }
+            base.join(value).ok().map(String::from)
+        };
+
+        for node in descendants(&self.doc.document) {
+            let attrs = match node.data {
+                NodeData::Element { ref attrs, .. } => attrs,
+                _ => continue,
+            };
+            let has_urls = attrs.borrow().iter().any(|attr| {
+                matches!(
+                    &*attr.name.local,
+                    "href" | "src" | "action" | "formaction" | "poster" | "srcset"
+                )
+            });
+            if has_urls {
+                self.context.record_attrs(&node);
+            }
+            for attr in attrs.borrow_mut().iter_mut() {
+                let value = match &*attr.name.local {
+                    "href" | "src" | "action" | "formaction" | "poster" => resolve(&attr.value),
+                    "srcset" => {
+                        let candidates: Vec<_> = parse_srcset(&attr.value)
+                            .into_iter()
+                            .map(|(url, descriptor)| {
+                                let url = resolve(&url).unwrap_or(url);
+                                match descriptor {
+                                    Some(descriptor) => format!("{} {}", url, descriptor),
+                                    None => url,
+                                }
+                            })
+                            .collect();
+                        Some(candidates.join(", "))
+                    }
+                    _ => None,
+                };
+                if let Some(value) = value {
+                    attr.value = value.into();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy element with its descendants from another document into this one,
+    /// the copy is not attached anywhere until inserted with methods like `Element::append_child`.
+    /// Relative urls of the copy are resolved against base url of this document.
+    ///
+    /// # Arguments
+    /// * `el` - element of any document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let page = Document::from("<article><h1>Part 1</h1></article>");
+    /// let output = Document::from("<main></main>");
+    /// let copy = output.import(&page.select_first("article").unwrap());
+    /// output.select_first("main").unwrap().append_child(&copy);
+    ///
+    /// assert_eq!(output.select("main > article > h1").len(), 1);
+    /// assert_eq!(page.select("article").len(), 1);
+    /// ```
+    pub fn import(&self, el: &Element) -> Element {
+        self.element(&deep_clone(&el.handle))
+    }
+
+    /// Move element with its descendants from another document into this one,
+    /// it is removed from its old document and not attached anywhere until inserted
+    /// with methods like `Element::append_child`. Relative urls of the element are resolved
+    /// against base url of this document, source locations are not kept.
+    ///
+    /// # Arguments
+    /// * `el` - element of any document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let page = Document::from("<article><h1>Part 1</h1></article>");
+    /// let output = Document::from("<main></main>");
+    /// let article = output.adopt(&page.select_first("article").unwrap());
+    /// output.select_first("main").unwrap().append_child(&article);
+    ///
+    /// assert_eq!(output.select("main > article > h1").len(), 1);
+    /// assert!(page.select("article").is_empty());
+    /// ```
+    pub fn adopt(&self, el: &Element) -> Element {
+        el.context.record_parent(&el.handle);
+        detach(&el.handle);
+        self.element(&el.handle)
+    }
+
+    /// Get copy of the document with closure applied to every element matching selector,
+    /// original document is not changed. Elements are matched before any of them is changed,
+    /// result of the closure is ignored, so it can use `?` to skip elements.
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    /// * `f` - closure changing matched element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<img data-src='/a.png'><img src='/b.png'><div class='ad'></div>");
+    /// let lazy = doc.rewrite("img[data-src]", |el| {
+    ///     el.set_attr("src", &el.remove_attr("data-src")?);
+    ///     Some(())
+    /// });
+    /// let clean = lazy.rewrite(".ad", |el| el.remove());
+    ///
+    /// assert_eq!(clean.select("img[src]").len(), 2);
+    /// assert!(clean.select(".ad").is_empty());
+    /// assert_eq!(doc.select("img[data-src]").len(), 1);
+    /// ```
+    pub fn rewrite<F, R>(&self, selector: &str, mut f: F) -> Document
+    where
+        F: FnMut(&Element) -> R,
+    {
+        let copy = self.clone();
+        for el in copy.select(selector) {
+            f(&el);
+        }
+
+        copy
+    }
+
+    /// Get copy of the document with all elements matching any of comma separated
+    /// selectors removed together with their descendants, original document is not changed.
+    /// Fails with `Error::InvalidSelector` when any of the selectors can not be parsed,
+    /// empty parts of the list are skipped.
+    ///
+    /// # Arguments
+    /// * `selectors` - CSS selectors separated by commas
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Error};
+    ///
+    /// let doc = Document::from(
+    ///     "<nav>menu</nav><script>track()</script><p>text</p><div class='ad'>buy</div>",
+    /// );
+    /// let clean = doc.strip("script, style, .ad, nav").unwrap();
+    ///
+    /// assert_eq!(clean.select_first("body").unwrap().children().len(), 1);
+    /// assert_eq!(doc.select_first("body").unwrap().children().len(), 4);
+    /// assert!(matches!(doc.strip("nav, p:hover"), Err(Error::InvalidSelector(_))));
+    /// ```
+    pub fn strip(&self, selectors: &str) -> Result<Document, Error> {
+        let selectors = Selector::parse_list(selectors)?;
+        let copy = self.clone();
+        for selector in selectors.iter() {
+            for handle in copy.find_handles(selector) {
+                copy.element(&handle).remove();
+            }
+        }
+
+        Ok(copy)
+    }
+
+    /// Create new html element belonging to the document, it is not attached anywhere
+    /// until inserted with methods like `Element::append_child`
+    ///
+    /// # Arguments
+    /// * `tag` - tag name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div id='main'><p>text</p></div>");
+    /// let marker = doc
+    ///     .create_element("span")
+    ///     .with_attr("class", "marker")
+    ///     .with_attr("data-id", "1");
+    /// doc.select_first("#main").unwrap().append_child(&marker);
+    ///
+    /// assert_eq!(doc.select("#main > span.marker")[0].attr("data-id").unwrap(), "1");
+    /// ```
+    pub fn create_element(&self, tag: &str) -> Element {
+        let name = LocalName::from(tag.to_ascii_lowercase());
+        let template_contents = if &*name == "template" {
+            Some(Node::new(NodeData::Document))
+        } else {
+            None
+        };
+        let node = Node::new(NodeData::Element {
+            name: QualName::new(None, ns!(html), name),
+            attrs: RefCell::new(vec![]),
+            template_contents,
+            mathml_annotation_xml_integration_point: false,
+        });
+
+        self.element(&node)
+    }
+
+    /// Start recording changes of the document so they can be undone with
+    /// `Transaction::rollback`, which is cheaper than changing a copy of a large document
+    /// when the changes are likely to be thrown away. Only nodes which were changed are
+    /// remembered.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>Call <b>555-0100</b> or <b>555-0199</b></p>");
+    ///
+    /// let tx = doc.begin();
+    /// for b in doc.select("b") {
+    ///     b.replace_with(doc.create_element("i"));
+    /// }
+    /// if doc.select("i").len() > 1 {
+    ///     tx.rollback();
+    /// } else {
+    ///     tx.commit();
+    /// }
+    ///
+    /// assert_eq!(doc.select("b").len(), 2);
+    /// assert!(doc.select("i").is_empty());
+    /// ```
+    pub fn begin(&self) -> Transaction {
+        Transaction::new(&self.context)
+    }
+
+    fn element(&self, handle: &Handle) -> Element {
+        Element {
+            handle: Arc::clone(handle),
+            context: Rc::clone(&self.context),
+        }
+    }
+
+    /// Find matches in document order, selectors requiring an id or a class
+    /// only check elements having it
+    fn find_handles(&self, sel: &Selector) -> Vec<Handle> {
+        let root = &self.doc.document;
+        match self.context.candidates(root, sel) {
+            Some(nodes) => nodes
+                .into_iter()
+                .filter(|n| sel.matches_within(n, root))
+                .collect(),
+            None => sel.find(root.children.borrow()),
+        }
+    }
+} //}}}
+
+fn collect_comments(handle: &Handle, acc: &mut Vec<String>) {
+    for node in descendants(handle) {
+        if let NodeData::Comment { ref contents } = node.data {
+            acc.push(contents.to_string());
+        }
+    }
+}
+
+fn collect_text_nodes(handle: &Handle, acc: &mut Vec<String>) {
+    acc.extend(descendants(handle).iter().filter_map(text_contents));
+}
+
+/// Sort nodes found among the roots and their descendants into document order
+/// dropping duplicates, the roots are walked until all the nodes are seen
+fn document_order(roots: &[Handle], nodes: Vec<Handle>) -> Vec<Handle> {
+    if nodes.len() < 2 {
+        return nodes;
+    }
+
+    let mut pending: HashSet<usize> = nodes.iter().map(|n| Arc::as_ptr(n) as usize).collect();
+    let mut acc = Vec::with_capacity(pending.len());
+    let mut stack: Vec<_> = roots.iter().rev().cloned().collect();
+
+    while let Some(node) = stack.pop() {
+        if pending.remove(&(Arc::as_ptr(&node) as usize)) {
+            acc.push(Arc::clone(&node));
+            if pending.is_empty() {
+                break;
+            }
+        }
+        stack.extend(node.children.borrow().iter().rev().cloned());
+    }
+
+    acc
+}
+
+/// Get all descendants of the node in document order, collected without recursion
+fn descendants(handle: &Handle) -> Vec<Handle> {
+    let mut acc = vec![];
+    let mut stack: Vec<_> = handle.children.borrow().iter().rev().cloned().collect();
+
+    while let Some(node) = stack.pop() {
+        stack.extend(node.children.borrow().iter().rev().cloned());
+        acc.push(node);
+    }
+
+    acc
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum AttributeSpec {
+    //{{{
+    /// Implementation of [attribute] selector
+    Present,
+    /// Implementation of [attribute="value"] selector
+    Exact(String),
+    // Implementation of [attribute~="value"] selector
+    // ContainsWord(String, String),
+    // Implementation of [attribute|="value"] selector
+    // StartsWord(String, String),
+    /// Implementation of [attribute^="value"] selector
+    Starts(String),
+    /// Implementation of [attribute$="value"] selector
+    Ends(String),
+    /// Implementation of [attribute*="value"] selector
+    Contains(String),
+}
+
+impl AttributeSpec {
+    fn matches(&self, other: &str) -> bool {
+        use AttributeSpec::*;
+
+        match self {
+            Present => true,
+            Exact(v) => other == v,
+            Starts(v) => other.starts_with(v),
+            Ends(v) => other.ends_with(v),
+            Contains(v) => other.contains(v),
+        }
+    }
+} //}}}
+
+#[derive(Debug, PartialEq, Clone)]
+struct Matcher {
+    //{{{
+    tag: SmallVec<[LocalName; 1]>,
+    class: SmallVec<[String; 2]>,
+    id: SmallVec<[String; 1]>,
+    /// Attribute conditions, at most one per attribute name
+    attribute: SmallVec<[(LocalName, AttributeSpec); 1]>,
+    nth_child: Option<usize>,
+    direct_match: bool,
+}
+
+impl Matcher {
+    /// Parse single whitespace separated part of a selector
+    fn parse(input: &str) -> Result<Self, Error> {
+        let mut segments = vec![];
+        let mut buf = "".to_string();
+        let mut in_brackets = false;
+
+        for c in input.chars() {
+            match c {
+                '>' => {
+                    return Ok(Self {
+                        tag: SmallVec::new(),
+                        class: SmallVec::new(),
+                        id: SmallVec::new(),
+                        attribute: SmallVec::new(),
+                        nth_child: None,
+                        direct_match: true,
+                    });
+                }
+                '[' if !in_brackets => {
+                    in_brackets = true;
+                    segments.push(buf);
+                    buf = "".to_string();
+                }
+                '#' | '.' | ':' if !in_brackets => {
+                    segments.push(buf);
+                    buf = "".to_string();
+                }
+                ']' if in_brackets => {
+                    in_brackets = false;
+                    segments.push(buf);
+                    buf = "".to_string();
+                    continue;
+                }
+                _ => {}
+            };
+
+            buf.push(c);
+        }
+        segments.push(buf);
+
+        let mut res = Self {
+            tag: SmallVec::new(),
+            class: SmallVec::new(),
+            id: SmallVec::new(),
+            attribute: SmallVec::new(),
+            nth_child: None,
+            direct_match: false,
+        };
+
+        for segment in segments {
+            match segment.chars().next() {
+                Some('#') => res.id.push(segment[1..].to_string()),
+                Some('.') => res.class.push(segment[1..].to_string()),
+                Some('[') => res.add_data_attribute(&segment[1..])?,
+                Some(':') => res.add_pseudo_class(&segment[1..])?,
+                None => {}
+                _ => res.tag.push(LocalName::from(segment)),
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn add_data_attribute(&mut self, spec: &str) -> Result<(), Error> {
+        use AttributeSpec::*;
+
+        let invalid = || Error::InvalidSelector(format!("[{}]", spec));
+        let parts = spec.split('=').collect::<Vec<_>>();
+
+        if parts.len() == 1 {
+            if spec.is_empty() {
+                return Err(invalid());
+            }
+            self.set_attribute(LocalName::from(spec), Present);
+            return Ok(());
+        }
+
+        let v = parts[1].trim_matches('"').to_string();
+        let (k, spec) = match parts[0] {
+            k if k.ends_with('^') => (&k[..k.len() - 1], Starts(v)),
+            k if k.ends_with('$') => (&k[..k.len() - 1], Ends(v)),
+            k if k.ends_with('*') => (&k[..k.len() - 1], Contains(v)),
+            k => (k, Exact(v)),
+        };
+        if k.is_empty() {
+            return Err(invalid());
+        }
+        self.set_attribute(LocalName::from(k), spec);
+
+        Ok(())
+    }
+
+    /// Set condition on the attribute, replacing the one given before
+    fn set_attribute(&mut self, name: LocalName, spec: AttributeSpec) {
+        self.attribute.retain(|(n, _)| *n != name);
+        self.attribute.push((name, spec));
+    }
+
+    fn add_pseudo_class(&mut self, spec: &str) -> Result<(), Error> {
+        let n = spec
+            .strip_prefix("nth-child(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|n| n.trim().parse().ok());
+
+        match n {
+            Some(n) => self.nth_child = Some(n),
+            None => return Err(Error::InvalidSelector(format!(":{}", spec))),
+        }
+
+        Ok(())
+    }
+
+    fn matches(&self, handle: &Handle) -> bool {
+        match handle.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } => self.matches_parts(&name.local, &attrs.borrow(), || element_index(handle)),
+            _ => false,
+        }
+    }
+
+    /// Check element given by its tag name and attributes, names are compared as interned atoms
+    /// and values are not copied. Position among sibling elements is only computed
+    /// when selector needs it.
+    fn matches_parts(
+        &self,
+        name: &LocalName,
+        attrs: &[Attribute],
+        index: impl FnOnce() -> Option<usize>,
+    ) -> bool {
+        if !self.tag.is_empty() && !self.tag.iter().any(|tag| tag == name) {
+            return false;
+        }
+
+        // id is compared as a whole with surrounding whitespace trimmed, class is a list of words
+        let id_match = self.id.is_empty()
+            || match attr_value(attrs, &local_name!("id")) {
+                Some(value) => self.id.iter().all(|id| value.trim() == id),
+                None => false,
+            };
+        let class_match = self.class.is_empty()
+            || match attr_value(attrs, &local_name!("class")) {
+                Some(value) => self
+                    .class
+                    .iter()
+                    .all(|class| value.split_whitespace().any(|w| w == class)),
+                None => false,
+            };
+        if !id_match || !class_match {
+            return false;
+        }
+
+        let attr_match = self
+            .attribute
+            .iter()
+            .all(|(k, v)| matches!(attr_value(attrs, k), Some(value) if v.matches(value)));
+        if !attr_match {
+            return false;
+        }
+
+        match self.nth_child {
+            Some(n) => index().map(|i| i + 1) == Some(n),
+            None => true,
+        }
+    }
+}
+//}}}
+
+#[derive(Debug, PartialEq, Default)]
+struct Selector {
+    //{{{
+    matchers: Vec<Matcher>,
+}
+
+impl Selector {
+    fn parse(input: &str) -> Result<Self, Error> {
+        let matchers = input
+            .split_whitespace()
+            .map(Matcher::parse)
+            .collect::<Result<_, _>>()?;
+
+        Ok(Selector { matchers })
+    }
+
+    /// Parse comma separated list of selectors, commas inside of quoted attribute values
+    /// do not separate selectors and empty parts of the list are skipped
+    fn parse_list(input: &str) -> Result<Vec<Self>, Error> {
+        let mut parts = vec![];
+        let mut quoted = false;
+        let mut start = 0;
+        for (i, c) in input.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ',' if !quoted => {
+                    parts.push(&input[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        parts.push(&input[start..]);
+
+        parts
+            .into_iter()
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(Self::parse)
+            .collect()
+    }
+}
+
+/// Selector which can not be parsed matches nothing
+impl From<&str> for Selector {
+    fn from(input: &str) -> Self {
+        Self::parse(input).unwrap_or_default()
+    }
+}
+
+/// Number of compiled selectors kept for reuse on every thread
+const SELECTOR_CACHE_SIZE: usize = 64;
+
+thread_local! {
+    /// Recently used selectors, most recently used last
+    static SELECTOR_CACHE: RefCell<VecDeque<(String, Rc<Selector>)>> =
+        const { RefCell::new(VecDeque::new()) };
+}
+
+impl Selector {
+    /// Parse selector or reuse it when it was parsed recently on this thread,
+    /// selectors which can not be parsed are not kept
+    fn cached(input: &str) -> Result<Rc<Self>, Error> {
+        SELECTOR_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if let Some(i) = cache.iter().position(|(key, _)| key == input) {
+                if let Some(entry) = cache.remove(i) {
+                    let sel = Rc::clone(&entry.1);
+                    cache.push_back(entry);
+                    return Ok(sel);
+                }
+            }
+
+            let sel = Rc::new(Self::parse(input)?);
+            if cache.len() >= SELECTOR_CACHE_SIZE {
+                cache.pop_front();
+            }
+            cache.push_back((input.to_string(), Rc::clone(&sel)));
+
+            Ok(sel)
+        })
+    }
+
+    /// Get cached selector, selector which can not be parsed matches nothing
+    fn compile(input: &str) -> Rc<Self> {
+        Self::cached(input).unwrap_or_default()
+    }
+}
+
+/// Get value of attribute without copying it
+fn attr_value<'a>(attrs: &'a [Attribute], name: &LocalName) -> Option<&'a str> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.local == *name)
+        .map(|attr| &*attr.value)
+}
+
+fn get_attr(attrs: &[Attribute], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .filter(|attr| &attr.name.local == name)
+        .take(1)
+        .map(|attr| attr.value.to_string())
+        .collect::<Vec<_>>()
+        .pop()
+}
+
+fn parent_handle(handle: &Handle) -> Option<Handle> {
+    let parent = handle.parent.take()?;
+    let res = parent.upgrade();
+    handle.parent.set(Some(parent));
+
+    res
+}
+
+fn element_attrs(handle: &Handle) -> Option<Ref<'_, Vec<Attribute>>> {
+    match handle.data {
+        NodeData::Element { ref attrs, .. } => Some(attrs.borrow()),
+        _ => None,
+    }
+}
+
+fn element_index(handle: &Handle) -> Option<usize> {
+    let parent = parent_handle(handle)?;
+    let children = parent.children.borrow();
+
+    children
+        .iter()
+        .filter(|n| matches!(n.data, NodeData::Element { .. }))
+        .position(|n| Arc::ptr_eq(n, handle))
+}
+
+fn text_contents(handle: &Handle) -> Option<String> {
+    match handle.data {
+        NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+        _ => None,
+    }
+}
+
+fn is_element(handle: &Handle) -> bool {
+    matches!(handle.data, NodeData::Element { .. })
+}
+
+/// Check if the node is an element which text is not parsed as html
+fn is_raw_text(handle: &Handle) -> bool {
+    match handle.data {
+        NodeData::Element { ref name, .. } => RAW_TEXT.contains(&&*name.local),
+        _ => false,
+    }
+}
+
+fn next_element_sibling(handle: &Handle) -> Option<Handle> {
+    let parent = parent_handle(handle)?;
+    let children = parent.children.borrow();
+    let i = children.iter().position(|n| Arc::ptr_eq(n, handle))?;
+
+    children[i + 1..].iter().find(|n| is_element(n)).cloned()
+}
+
+fn prev_element_sibling(handle: &Handle) -> Option<Handle> {
+    let parent = parent_handle(handle)?;
+    let children = parent.children.borrow();
+    let i = children.iter().position(|n| Arc::ptr_eq(n, handle))?;
+
+    children[..i].iter().rev().find(|n| is_element(n)).cloned()
+}
+
+/// Copy node and all its descendants into new detached subtree,
+/// the tree is walked without recursion so deeply nested documents can not overflow the stack
+fn deep_clone(handle: &Handle) -> Handle {
+    let mut pending = vec![];
+    let root = clone_node(handle, &mut pending);
+
+    while let Some((from, to)) = pending.pop() {
+        for child in from.children.borrow().iter() {
+            append_child(&to, clone_node(child, &mut pending));
+        }
+    }
+
+    root
+}
+
+/// Copy single node, pairs of nodes which children still need to be copied are added to `pending`
+fn clone_node(handle: &Handle, pending: &mut Vec<(Handle, Handle)>) -> Handle {
+    let data = match handle.data {
+        NodeData::Document => NodeData::Document,
+        NodeData::Doctype {
+            ref name,
+            ref public_id,
+            ref system_id,
+        } => NodeData::Doctype {
+            name: name.clone(),
+            public_id: public_id.clone(),
+            system_id: system_id.clone(),
+        },
+        NodeData::Text { ref contents } => NodeData::Text {
+            contents: RefCell::new(contents.borrow().clone()),
+        },
+        NodeData::Comment { ref contents } => NodeData::Comment {
+            contents: contents.clone(),
+        },
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ref template_contents,
+            mathml_annotation_xml_integration_point,
+        } => NodeData::Element {
+            name: name.clone(),
+            attrs: RefCell::new(attrs.borrow().clone()),
+            template_contents: template_contents.as_ref().map(|contents| {
+                let copy = Node::new(NodeData::Document);
+                pending.push((Arc::clone(contents), Arc::clone(&copy)));
+                copy
+            }),
+            mathml_annotation_xml_integration_point,
+        },
+        NodeData::ProcessingInstruction {
+            ref target,
+            ref contents,
+        } => NodeData::ProcessingInstruction {
+            target: target.clone(),
+            contents: contents.clone(),
+        },
+    };
+
+    let node = Node::new(data);
+    pending.push((Arc::clone(handle), Arc::clone(&node)));
+
+    node
+}
+
+fn append_child(parent: &Handle, child: Handle) {
+    index::touch();
+    child.parent.set(Some(Arc::downgrade(parent)));
+    parent.children.borrow_mut().push(child);
+}
+
+/// Follow first child elements down to the one without child elements
+fn innermost_element(handle: &Handle) -> Handle {
+    let mut inner = Arc::clone(handle);
+
+    loop {
+        let child = inner
+            .children
+            .borrow()
+            .iter()
+            .find(|n| is_element(n))
+            .cloned();
+        match child {
+            Some(child) => inner = child,
+            None => return inner,
+        }
+    }
+}
+
+/// Remove node from children of its parent, returns the parent and position the node was at
+fn detach(handle: &Handle) -> Option<(Handle, usize)> {
+    let parent = parent_handle(handle)?;
+    handle.parent.set(None);
+    index::touch();
+
+    let mut children = parent.children.borrow_mut();
+    let i = children.iter().position(|n| Arc::ptr_eq(n, handle))?;
+    children.remove(i);
+    drop(children);
+
+    Some((parent, i))
+}
+
+/// Copy node and its descendants in canonical form used by `Document::normalize`
+fn normalize_tree(handle: &Handle) -> Option<Handle> {
+    let mut pending = vec![];
+    let root = normalize_node(handle, false, &mut pending)?;
+
+    while let Some((from, to, preserve_whitespace)) = pending.pop() {
+        for child in from.children.borrow().iter() {
+            if let Some(child) = normalize_node(child, preserve_whitespace, &mut pending) {
+                append_child(&to, child);
+            }
+        }
+    }
+
+    Some(root)
+}
+
+/// Copy single node in canonical form, returns None for dropped whitespace only text.
+/// Nodes which children still need to be copied are added to `pending`.
+fn normalize_node(
+    handle: &Handle,
+    preserve_whitespace: bool,
+    pending: &mut Vec<(Handle, Handle, bool)>,
+) -> Option<Handle> {
+    let mut preserve_whitespace = preserve_whitespace;
+
+    let data = match handle.data {
+        NodeData::Document => NodeData::Document,
+        NodeData::Text { ref contents } => {
+            let text = contents.borrow();
+            if preserve_whitespace {
+                NodeData::Text {
+                    contents: RefCell::new(text.clone()),
+                }
+            } else if text.trim().is_empty() {
+                return None;
+            } else {
+                let mut collapsed = String::with_capacity(text.len());
+                for c in text.chars() {
+                    if !c.is_whitespace() {
+                        collapsed.push(c);
+                    } else if !collapsed.ends_with(' ') {
+                        collapsed.push(' ');
+                    }
+                }
+
+                NodeData::Text {
+                    contents: RefCell::new(collapsed.into()),
+                }
+            }
+        }
+        NodeData::Element {
+            ref name,
+            ref attrs,
+            ref template_contents,
+            mathml_annotation_xml_integration_point,
+        } => {
+            let lowercase = |name: &QualName| {
+                QualName::new(
+                    name.prefix.clone(),
+                    name.ns.clone(),
+                    LocalName::from(name.local.to_ascii_lowercase()),
+                )
+            };
+
+            let mut attrs: Vec<_> = attrs
+                .borrow()
+                .iter()
+                .map(|attr| Attribute {
+                    name: lowercase(&attr.name),
+                    value: attr.value.clone(),
+                })
+                .collect();
+            attrs.sort_by(|a, b| a.name.local.cmp(&b.name.local));
+
+            let name = lowercase(name);
+            preserve_whitespace = preserve_whitespace
+                || matches!(&*name.local, "pre" | "textarea" | "script" | "style");
+
+            NodeData::Element {
+                name,
+                attrs: RefCell::new(attrs),
+                template_contents: template_contents.as_ref().map(|contents| {
+                    let copy = Node::new(NodeData::Document);
+                    pending.push((Arc::clone(contents), Arc::clone(&copy), preserve_whitespace));
+                    copy
+                }),
+                mathml_annotation_xml_integration_point,
+            }
+        }
+        _ => return Some(deep_clone(handle)),
+    };
+
+    let node = Node::new(data);
+    pending.push((Arc::clone(handle), Arc::clone(&node), preserve_whitespace));
+
+    Some(node)
+}
+
+/// Copy source information of nodes to their copies made by `deep_clone`
+fn copy_sources(from: &Handle, to: &Handle, from_context: &Context, to_context: &mut Context) {
+    let mut pending = vec![(Arc::clone(from), Arc::clone(to))];
+
+    while let Some((from, to)) = pending.pop() {
+        if let Some(source) = from_context.source(&from) {
+            to_context.set_source(&to, source.line, source.column, source.range.clone());
+            if let Some(ref attrs) = source.raw_attrs {
+                to_context.set_raw_attrs(&to, attrs.clone());
+            }
+        }
+
+        if let (
+            NodeData::Element {
+                template_contents: Some(ref from_contents),
+                ..
+            },
+            NodeData::Element {
+                template_contents: Some(ref to_contents),
+                ..
+            },
+        ) = (&from.data, &to.data)
+        {
+            pending.push((Arc::clone(from_contents), Arc::clone(to_contents)));
+        }
+
+        let from_children = from.children.borrow();
+        let to_children = to.children.borrow();
+        pending.extend(
+            from_children
+                .iter()
+                .cloned()
+                .zip(to_children.iter().cloned()),
+        );
+    }
+}
+
+fn camel_case(name: &str) -> String {
+    let mut res = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '-' && next.is_ascii_lowercase() => {
+                res.push(next.to_ascii_uppercase());
+                chars.next();
+            }
+            _ => res.push(c),
+        }
+    }
+
+    res
+}
+
+/// Split srcset value into urls and descriptors following HTML candidate parsing rules,
+/// commas are allowed inside of urls and in parentheses of descriptors
+fn parse_srcset(value: &str) -> Vec<(String, Option<String>)> {
+    let mut res = vec![];
+    let mut rest = value;
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if rest.is_empty() {
+            break;
+        }
+
+        let url_end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let url = &rest[..url_end];
+        rest = &rest[url_end..];
+
+        let trimmed = url.trim_end_matches(',');
+        if trimmed.len() != url.len() {
+            res.push((trimmed.to_string(), None));
+            continue;
+        }
+
+        let mut depth = 0;
+        let descriptor_end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' if depth > 0 => depth -= 1,
+                    ',' if depth == 0 => return true,
+                    _ => {}
+                }
+                false
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let descriptor = rest[..descriptor_end].trim();
+        rest = &rest[descriptor_end..];
+
+        let descriptor = if descriptor.is_empty() {
+            None
+        } else {
+            Some(descriptor.to_string())
+        };
+        res.push((url.to_string(), descriptor));
+    }
+
+    res
+}
+
+/// Size of srcset candidate for comparison, width descriptors rank above densities
+fn srcset_size(descriptor: Option<&str>) -> (u8, f64) {
+    let descriptor = match descriptor {
+        Some(descriptor) => descriptor,
+        None => return (0, 1.0),
+    };
+
+    for part in descriptor.split_ascii_whitespace() {
+        if let Some(width) = part.strip_suffix('w') {
+            if let Ok(width) = width.parse() {
+                return (1, width);
+            }
+        } else if let Some(density) = part.strip_suffix('x') {
+            if let Ok(density) = density.parse() {
+                return (0, density);
+            }
+        }
+    }
+
+    (0, 1.0)
+}
+
+impl Selector {
+    /// Find nodes matching the matcher among elements and, unless direct match is required,
+    /// their descendants, adding them to `acc`. Children are listed before their parent,
+    /// the tree is walked without recursion keeping pending nodes in `stack`.
+    /// Subtrees of elements nested in each other are walked once, addresses of walked
+    /// nodes are kept in `seen`, so every node is added at most once.
+    fn find_nodes(
+        &self,
+        matcher: &Matcher,
+        elements: &[Handle],
+        direct_match: bool,
+        stack: &mut Vec<(Handle, bool)>,
+        seen: &mut HashSet<usize>,
+        acc: &mut Vec<Handle>,
+    ) {
+        if direct_match {
+            acc.extend(elements.iter().filter(|el| matcher.matches(el)).cloned());
+            return;
+        }
+
+        stack.clear();
+        seen.clear();
+        stack.extend(elements.iter().rev().map(|el| (Arc::clone(el), false)));
+
+        while let Some((el, visited)) = stack.pop() {
+            if visited {
+                if matcher.matches(&el) {
+                    acc.push(el);
+                }
+                continue;
+            }
+            if !seen.insert(Arc::as_ptr(&el) as usize) {
+                continue;
+            }
+
+            let children = el.children.borrow();
+            stack.push((Arc::clone(&el), true));
+            stack.extend(
+                children
+                    .iter()
+                    .rev()
+                    .map(|child| (Arc::clone(child), false)),
+            );
+        }
+    }
+
+    /// Apply matchers one after another, buffers are reused between the steps
+    fn walk(&self, matchers: &[Matcher], mut elements: Vec<Handle>) -> (Vec<Handle>, bool) {
+        let mut direct_match = false;
+        let mut next = vec![];
+        let mut stack = vec![];
+        let mut seen = HashSet::new();
+
+        for matcher in matchers {
+            next.clear();
+            if matcher.direct_match {
+                for el in elements.iter() {
+                    next.extend(
+                        el.children
+                            .borrow()
+                            .iter()
+                            .filter(|n| is_element(n))
+                            .cloned(),
+                    );
+                }
+                direct_match = true;
+            } else {
+                self.find_nodes(
+                    matcher,
+                    &elements,
+                    direct_match,
+                    &mut stack,
+                    &mut seen,
+                    &mut next,
+                );
+                direct_match = false;
+            }
+            std::mem::swap(&mut elements, &mut next);
+        }
+
+        (elements, direct_match)
+    }
+
+    /// Find matches among elements and their descendants, every node is returned once
+    /// and in document order no matter how many ways the selector matched it
+    fn find(&self, elements: Ref<'_, Vec<Handle>>) -> Vec<Handle> {
+        if self.matchers.is_empty() {
+            return vec![];
+        }
+        let roots: Vec<_> = elements.iter().map(Arc::clone).collect();
+        let (matched, _) = self.walk(&self.matchers, roots.clone());
+
+        document_order(&roots, matched)
+    }
+
+    /// Find first match among descendants of the scope in document order,
+    /// the walk stops at the match
+    fn find_first(&self, scope: &Handle) -> Option<Handle> {
+        let mut stack: Vec<_> = scope.children.borrow().iter().rev().cloned().collect();
+
+        while let Some(node) = stack.pop() {
+            if self.matches_within(&node, scope) {
+                return Some(node);
+            }
+            stack.extend(node.children.borrow().iter().rev().cloned());
+        }
+
+        None
+    }
+
+    /// Check if node is matched by the selector evaluating matchers right to left,
+    /// ancestors are checked the same way `walk` checks descendants
+    fn matches(&self, handle: &Handle) -> bool {
+        Self::matches_from(&self.matchers, handle, None)
+    }
+
+    /// Check if node is matched by the selector applied to descendants of the scope,
+    /// the scope and its ancestors are not matched against the selector
+    fn matches_within(&self, handle: &Handle, scope: &Handle) -> bool {
+        Self::matches_from(&self.matchers, handle, Some(scope))
+    }
+
+    /// Find matches among descendants of the scope in document order,
+    /// the tree is walked only as far as the returned iterator is consumed
+    fn iter(self: Rc<Self>, scope: Handle) -> impl Iterator<Item = Handle> {
+        let mut stack: Vec<_> = scope.children.borrow().iter().rev().cloned().collect();
+
+        std::iter::from_fn(move || {
+            while let Some(node) = stack.pop() {
+                stack.extend(node.children.borrow().iter().rev().cloned());
+                if self.matches_within(&node, &scope) {
+                    return Some(node);
+                }
+            }
+            None
+        })
+    }
+
+    /// Find last match among descendants of the scope in document order,
+    /// the tree is walked backwards and the walk stops at the match
+    fn find_last(&self, scope: &Handle) -> Option<Handle> {
+        let mut stack: Vec<_> = scope
+            .children
+            .borrow()
+            .iter()
+            .map(|child| (Arc::clone(child), false))
+            .collect();
+
+        while let Some((node, visited)) = stack.pop() {
+            if visited {
+                if self.matches_within(&node, scope) {
+                    return Some(node);
+                }
+                continue;
+            }
+            // descendants come after the node in document order
+            let children: Vec<_> = node.children.borrow().iter().cloned().collect();
+            stack.push((node, true));
+            stack.extend(children.into_iter().map(|child| (child, false)));
+        }
+
+        None
+    }
+
+    fn matches_from(matchers: &[Matcher], handle: &Handle, scope: Option<&Handle>) -> bool {
+        let (last, init) = match matchers.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+        if !last.matches(handle) {
+            return false;
+        }
+        let in_scope = |node: &Handle| match scope {
+            Some(scope) => !Arc::ptr_eq(node, scope),
+            None => true,
+        };
+
+        // trailing `>` matches children of what is before it
+        if last.direct_match {
+            return match parent_handle(handle) {
+                Some(parent) if in_scope(&parent) => Self::matches_from(init, &parent, scope),
+                _ => false,
+            };
+        }
+
+        match init.split_last() {
+            None => true,
+            Some((direct, init)) if direct.direct_match => match parent_handle(handle) {
+                Some(parent) if !in_scope(&parent) => false,
+                Some(parent) => Self::matches_from(init, &parent, scope),
+                None => false,
+            },
+            // `walk` looks for next matcher among the matched node and its descendants
+            Some(_) => {
+                let mut node = Some(Arc::clone(handle));
+                while let Some(current) = node {
+                    if !in_scope(&current) {
+                        return false;
+                    }
+                    if Self::matches_from(init, &current, scope) {
+                        return true;
+                    }
+                    node = parent_handle(&current);
+                }
+                false
+            }
+        }
+    }
+} //}}}
+
+#[derive(Clone)]
+pub struct Element {
+    //{{{
+    handle: Handle,
+    context: Rc<Context>,
+}
+
+impl From<Handle> for Element {
+    fn from(e: Handle) -> Self {
+        Self::from(&e)
+    }
+}
+
+impl From<&Handle> for Element {
+    fn from(e: &Handle) -> Self {
+        Element {
+            handle: Arc::clone(e),
+            context: Rc::default(),
+        }
+    }
+}
+
+impl IntoIterator for &Element {
+    type Item = Element;
+    type IntoIter = Children;
+
+    fn into_iter(self) -> Children {
+        self.children_iter()
+    }
+}
+
+/// Iterator over child elements of an element
+pub struct Children {
+    parent: Handle,
+    context: Rc<Context>,
+    index: usize,
+}
+
+impl Iterator for Children {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        let children = self.parent.children.borrow();
+
+        while let Some(child) = children.get(self.index) {
+            self.index += 1;
+            if is_element(child) {
+                return Some(Element {
+                    handle: Arc::clone(child),
+                    context: Rc::clone(&self.context),
+                });
+            }
+        }
+
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let children = self.parent.children.borrow();
+        let remaining = children
+            .iter()
+            .skip(self.index)
+            .filter(|n| is_element(n))
+            .count();
+
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Children {}
+
+impl PartialEq for Element {
+    /// Elements are equal when they point to the same node in the tree
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.handle, &other.handle)
+    }
+}
+
+impl Eq for Element {}
+
+impl Hash for Element {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.handle).hash(state);
+    }
+}
+
+impl Element {
+    /// Get value of an attribue
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.attr("class").unwrap(), "link");
+    /// ```
+    pub fn attr(&self, name: &str) -> Option<String> {
+        match self.handle.data {
+            NodeData::Element { ref attrs, .. } => get_attr(&attrs.borrow(), name),
+            _ => None,
+        }
+    }
+
+    /// Get value of an attribute without copying it. The element can not be changed
+    /// while the returned reference is alive, doing so panics.
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a href='/one'>1</a><a href='/two'>2</a><a>3</a>");
+    /// let links = doc.select("a");
+    /// let hrefs: Vec<_> = links.iter().filter_map(|a| a.attr_ref("href")).collect();
+    ///
+    /// assert_eq!(hrefs.len(), 2);
+    /// assert_eq!(&*hrefs[1], "/two");
+    /// ```
+    pub fn attr_ref(&self, name: &str) -> Option<Ref<'_, str>> {
+        match self.handle.data {
+            NodeData::Element { ref attrs, .. } => Ref::filter_map(attrs.borrow(), |attrs| {
+                attrs
+                    .iter()
+                    .find(|attr| &*attr.name.local == name)
+                    .map(|attr| &*attr.value)
+            })
+            .ok(),
+            _ => None,
+        }
+    }
+
+    /// Get all attributes in the order they were written in the markup,
+    /// including duplicate names which html parser drops keeping only the first one
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a href='/one' class='link' href='/two'>hi there</a>");
+    /// let el = doc.select_first("a").unwrap();
+    ///
+    /// assert_eq!(el.attr("href").unwrap(), "/one");
+    /// assert_eq!(
+    ///     el.attrs(),
+    ///     vec![
+    ///         ("href".to_string(), "/one".to_string()),
+    ///         ("class".to_string(), "link".to_string()),
+    ///         ("href".to_string(), "/two".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn attrs(&self) -> Vec<(String, String)> {
+        let attrs = match element_attrs(&self.handle) {
+            Some(attrs) => attrs,
+            None => return vec![],
+        };
+        let parsed: Vec<_> = attrs
+            .iter()
+            .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+            .collect();
+
+        // attributes as written are stale once the element was changed with `set_attr`
+        // or `remove_attr`, parser keeps first of the duplicates
+        let unchanged = |raw: &&Vec<(String, String)>| {
+            raw.iter()
+                .all(|(name, _)| parsed.iter().any(|(n, _)| n == name))
+                && parsed
+                    .iter()
+                    .all(|(name, value)| match raw.iter().find(|(n, _)| n == name) {
+                        Some((_, v)) => v == value,
+                        None => true,
+                    })
+        };
+        let raw = self
+            .context
+            .source(&self.handle)
+            .and_then(|source| source.raw_attrs.as_ref())
+            .filter(unchanged);
+
+        match raw {
+            Some(raw) => {
+                // attributes added by the parser later, e.g. from repeated <body> tags
+                let extra: Vec<_> = parsed
+                    .into_iter()
+                    .filter(|(name, _)| !raw.iter().any(|(n, _)| n == name))
+                    .collect();
+                raw.iter().cloned().chain(extra).collect()
+            }
+            None => parsed,
+        }
+    }
+
+    /// Check if element has given attribute, regardless of its value
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<input disabled>");
+    /// let el = doc.select_first("input").unwrap();
+    ///
+    /// assert!(el.has_attr("disabled"));
+    /// assert!(!el.has_attr("checked"));
+    /// ```
+    pub fn has_attr(&self, name: &str) -> bool {
+        self.attr(name).is_some()
+    }
+
+    /// Set value of an attribute, attribute is added when missing.
+    /// Change is visible to all elements pointing to the same node and in serialized html.
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    /// * `value` - new value
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a href='/item?id=1&utm_source=feed'>item</a>");
+    /// for a in doc.select("a[href*=\"utm_\"]") {
+    ///     let href = a.attr("href").unwrap();
+    ///     a.set_attr("href", href.split('?').next().unwrap());
+    ///     a.set_attr("rel", "nofollow");
+    /// }
+    ///
+    /// assert!(doc.html().contains(r#"<a href="/item" rel="nofollow">item</a>"#));
+    /// ```
+    pub fn set_attr(&self, name: &str, value: &str) {
+        if let NodeData::Element { ref attrs, .. } = self.handle.data {
+            self.context.record_attrs(&self.handle);
+            let mut attrs = attrs.borrow_mut();
+            match attrs.iter_mut().find(|attr| &*attr.name.local == name) {
+                Some(attr) => attr.value = value.into(),
+                None => attrs.push(Attribute {
+                    name: QualName::new(None, ns!(), LocalName::from(name)),
+                    value: value.into(),
+                }),
+            }
+        }
+    }
+
+    /// Set value of an attribute and return the element, handy for elements
+    /// made with `Document::create_element`
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    /// * `value` - new value
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>text</p>");
+    /// let link = doc.create_element("a").with_attr("href", "/about");
+    ///
+    /// assert_eq!(link.attr("href").unwrap(), "/about");
+    /// ```
+    pub fn with_attr(self, name: &str, value: &str) -> Self {
+        self.set_attr(name, value);
+        self
+    }
+
+    /// Remove an attribute, returns its value when it was present
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p style='color: red' class='note'>hi</p>");
+    /// let el = doc.select_first("p").unwrap();
+    ///
+    /// assert_eq!(el.remove_attr("style").unwrap(), "color: red");
+    /// assert!(el.remove_attr("style").is_none());
+    /// assert!(doc.html().contains(r#"<p class="note">hi</p>"#));
+    /// ```
+    pub fn remove_attr(&self, name: &str) -> Option<String> {
+        match self.handle.data {
+            NodeData::Element { ref attrs, .. } => {
+                self.context.record_attrs(&self.handle);
+                let mut attrs = attrs.borrow_mut();
+                let i = attrs.iter().position(|attr| &*attr.name.local == name)?;
+                Some(attrs.remove(i).value.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Get value of a boolean attribute like `disabled`, `checked` or `selected`.
+    /// Following html rules attribute is true when present, whatever its value is,
+    /// so `<input disabled>` and `<input disabled="false">` are both disabled,
+    /// while `attr("disabled")` returns an empty string for the first one.
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<input disabled><input>");
+    /// let sel = doc.select("input");
+    ///
+    /// assert_eq!(sel[0].attr("disabled").unwrap(), "");
+    /// assert!(sel[0].bool_attr("disabled"));
+    /// assert!(!sel[1].bool_attr("disabled"));
+    /// ```
+    pub fn bool_attr(&self, name: &str) -> bool {
+        self.has_attr(name)
+    }
+
+    /// Get value of an attribute resolved to an absolute url
+    /// against document base url taken from `<base href>` tag.
+    /// Without base url only values which are already absolute urls are returned.
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(
+    ///     "<base href='https://example.com/docs/'><a href='../about?x=1'>about</a>",
+    /// );
+    /// let el = doc.select_first("a").unwrap();
+    ///
+    /// assert_eq!(el.abs_attr("href").unwrap(), "https://example.com/about?x=1");
+    /// ```
+    pub fn abs_attr(&self, name: &str) -> Option<String> {
+        let value = self.attr(name)?;
+        let value = value.trim();
+
+        let url = match self.context.base_url {
+            Some(ref base) => base.join(value),
+            None => Url::parse(value),
+        };
+
+        url.ok().map(String::from)
+    }
+
+    /// Get candidates of `srcset` attribute as pairs of url and descriptor (`480w`, `2x`)
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<img srcset='small.jpg 480w, large.jpg 1080w, plain.jpg'>");
+    /// let el = doc.select_first("img").unwrap();
+    ///
+    /// assert_eq!(
+    ///     el.srcset(),
+    ///     vec![
+    ///         ("small.jpg".to_string(), Some("480w".to_string())),
+    ///         ("large.jpg".to_string(), Some("1080w".to_string())),
+    ///         ("plain.jpg".to_string(), None),
+    ///     ]
+    /// );
+    /// ```
+    pub fn srcset(&self) -> Vec<(String, Option<String>)> {
+        match self.attr("srcset") {
+            Some(value) => parse_srcset(&value),
+            None => vec![],
+        }
+    }
+
+    /// Get url of the largest `srcset` candidate, by width if candidates have width
+    /// descriptors, by pixel density otherwise. Candidate without descriptor counts as `1x`.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<img srcset='a.jpg, b.jpg 3x, c.jpg 2x'>");
+    /// let el = doc.select_first("img").unwrap();
+    ///
+    /// assert_eq!(el.srcset_largest().unwrap(), "b.jpg");
+    /// ```
+    pub fn srcset_largest(&self) -> Option<String> {
+        let mut largest: Option<((u8, f64), String)> = None;
+
+        for (url, descriptor) in self.srcset() {
+            let size = srcset_size(descriptor.as_deref());
+            match largest {
+                Some((current, _)) if current >= size => {}
+                _ => largest = Some((size, url)),
+            }
+        }
+
+        largest.map(|(_, url)| url)
+    }
+
+    /// Parse value of an attribute into given type, surrounding whitespace is ignored
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a data-count=' 42 '>hi there</a>");
+    /// let el = doc.select_first("a").unwrap();
+    ///
+    /// assert_eq!(el.attr_parse::<u32>("data-count").unwrap(), Ok(42));
+    /// assert!(el.attr_parse::<bool>("data-count").unwrap().is_err());
+    /// assert!(el.attr_parse::<u32>("data-missing").is_none());
+    /// ```
+    pub fn attr_parse<T: FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.attr(name).map(|value| value.trim().parse())
+    }
+
+    /// Parse value of an attribute into given type,
+    /// falling back to default when attribute is missing or can't be parsed
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    /// * `default` - value used when attribute is missing or invalid
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a data-count='42' data-page='n/a'>hi there</a>");
+    /// let el = doc.select_first("a").unwrap();
+    ///
+    /// assert_eq!(el.attr_or("data-count", 0), 42);
+    /// assert_eq!(el.attr_or("data-page", 1), 1);
+    /// assert_eq!(el.attr_or("data-missing", 1), 1);
+    /// ```
+    pub fn attr_or<T: FromStr>(&self, name: &str, default: T) -> T {
+        self.attr_parse(name)
+            .and_then(Result::ok)
+            .unwrap_or(default)
+    }
+
+    /// Get `data-*` attributes with prefix stripped and names camel-cased,
+    /// mirroring DOM `dataset` API
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a data-product-id='42'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.dataset().get("productId").unwrap(), "42");
+    /// ```
+    pub fn dataset(&self) -> HashMap<String, String> {
+        self.dataset_raw()
+            .into_iter()
+            .map(|(k, v)| (camel_case(&k), v))
+            .collect()
+    }
+
+    /// Get `data-*` attributes with prefix stripped and names left as is
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a data-product-id='42'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.dataset_raw().get("product-id").unwrap(), "42");
+    /// ```
+    pub fn dataset_raw(&self) -> HashMap<String, String> {
+        match self.handle.data {
+            NodeData::Element { ref attrs, .. } => attrs
+                .borrow()
+                .iter()
+                .filter_map(|attr| {
+                    attr.name
+                        .local
+                        .strip_prefix("data-")
+                        .map(|k| (k.to_string(), attr.value.to_string()))
+                })
+                .collect(),
+            _ => HashMap::new(),
+        }
+    }
+
+    /// Get element id, surrounding whitespace is trimmed the same way
+    /// selector id matching does
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a id=' mainlink '>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.id().unwrap(), "mainlink");
+    /// assert_eq!(doc.select("#mainlink").len(), 1);
+    /// ```
+    pub fn id(&self) -> Option<String> {
+        self.attr("id")
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    }
+
+    /// Get list of classes
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link  button'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.classes(), vec!["link", "button"]);
+    /// ```
+    pub fn classes(&self) -> Vec<String> {
+        self.attr("class")
+            .map(|class| class.split_whitespace().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Check if element has given class
+    ///
+    /// # Arguments
+    /// * `class` - class name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link button'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert!(el.has_class("button"));
+    /// assert!(!el.has_class("butt"));
+    /// ```
+    pub fn has_class(&self, class: &str) -> bool {
+        self.classes().iter().any(|c| c == class)
+    }
+
+    /// Add classes missing from `class` attribute, attribute is added when missing
+    ///
+    /// # Arguments
+    /// * `class` - class name or several whitespace separated names
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link'>hi there</a>");
+    /// let el = doc.select_first("a").unwrap();
+    /// el.add_class("external link visited");
+    ///
+    /// assert_eq!(el.attr("class").unwrap(), "link external visited");
+    /// ```
+    pub fn add_class(&self, class: &str) {
+        self.update_classes(|classes| {
+            for name in class.split_whitespace() {
+                if !classes.iter().any(|c| c == name) {
+                    classes.push(name.to_string());
+                }
+            }
+        });
+    }
+
+    /// Remove all occurrences of classes from `class` attribute,
+    /// attribute is kept even when no classes are left
+    ///
+    /// # Arguments
+    /// * `class` - class name or several whitespace separated names
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='ad link  ad'>hi there</a>");
+    /// let el = doc.select_first("a").unwrap();
+    /// el.remove_class("ad");
+    ///
+    /// assert_eq!(el.attr("class").unwrap(), "link");
+    /// ```
+    pub fn remove_class(&self, class: &str) {
+        if !self.has_attr("class") {
+            return;
+        }
+
+        let names: Vec<_> = class.split_whitespace().collect();
+        self.update_classes(|classes| classes.retain(|c| !names.contains(&c.as_str())));
+    }
+
+    /// Remove class when present or add it otherwise, every class of whitespace separated
+    /// list is toggled on its own. Returns true when element has all of the classes afterwards,
+    /// false for empty list.
+    ///
+    /// # Arguments
+    /// * `class` - class name or whitespace separated list of class names
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<li class='item active'>one</li>");
+    /// let el = doc.select_first("li").unwrap();
+    ///
+    /// assert!(!el.toggle_class("active"));
+    /// assert_eq!(el.attr("class").unwrap(), "item");
+    /// assert!(el.toggle_class("active"));
+    /// assert_eq!(el.attr("class").unwrap(), "item active");
+    /// assert!(!el.toggle_class("item new"));
+    /// assert_eq!(el.attr("class").unwrap(), "active new");
+    /// ```
+    pub fn toggle_class(&self, class: &str) -> bool {
+        let mut names: Vec<_> = vec![];
+        for name in class.split_whitespace() {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        if names.is_empty() {
+            return false;
+        }
+
+        self.update_classes(|classes| {
+            for name in names.iter() {
+                match classes.iter().position(|c| c == name) {
+                    Some(i) => {
+                        classes.remove(i);
+                    }
+                    None => classes.push(name.to_string()),
+                }
+            }
+        });
+        names.iter().all(|name| self.has_class(name))
+    }
+
+    /// Rewrite `class` attribute from its unique class names changed by `f`
+    fn update_classes(&self, f: impl FnOnce(&mut Vec<String>)) {
+        let mut classes: Vec<String> = vec![];
+        for class in self.classes() {
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+        f(&mut classes);
+        self.set_attr("class", &classes.join(" "));
+    }
+
+    /// Get tag value
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.tag().unwrap(), "a");
+    /// ```
+    pub fn tag(&self) -> Option<String> {
+        match self.handle.data {
+            NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Get qualified name of the element, local name with namespace prefix if there is one
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<svg><foreignObject></foreignObject></svg>");
+    /// let el = doc.select_first("foreignObject").unwrap();
+    ///
+    /// assert_eq!(el.qual_name().unwrap(), "foreignObject");
+    /// ```
+    pub fn qual_name(&self) -> Option<String> {
+        match self.handle.data {
+            NodeData::Element { ref name, .. } => Some(match name.prefix {
+                Some(ref prefix) => format!("{}:{}", prefix, name.local),
+                None => name.local.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Get namespace url of the element, allows to tell HTML, SVG and MathML elements apart
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a>link</a><svg><a>svg link</a></svg><math><mi>x</mi></math>");
+    /// let sel = doc.select("a");
+    ///
+    /// assert_eq!(sel[0].namespace().unwrap(), "http://www.w3.org/1999/xhtml");
+    /// assert_eq!(sel[1].namespace().unwrap(), "http://www.w3.org/2000/svg");
+    /// assert_eq!(
+    ///     doc.select_first("mi").unwrap().namespace().unwrap(),
+    ///     "http://www.w3.org/1998/Math/MathML"
+    /// );
+    /// ```
+    pub fn namespace(&self) -> Option<String> {
+        match self.handle.data {
+            NodeData::Element { ref name, .. } => Some(name.ns.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Get text
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link'>hi there</a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.text().unwrap(), "hi there");
+    /// ```
+    pub fn text(&self) -> Option<String> {
+        let mut res = "".to_string();
+        let children = self.handle.children.borrow();
+
+        for child in children.iter() {
+            if let NodeData::Text { ref contents } = child.data {
+                res.push_str(&contents.borrow());
+            }
+        }
+
+        Some(res)
+    }
+
+    /// Get text like `text` does, text of a single text node is shared with the document
+    /// instead of being copied. The result dereferences to `str`.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>hi there</p><p>one <b>and</b> two</p>");
+    /// let texts: Vec<_> = doc.select("p").iter().map(|p| p.text_ref()).collect();
+    ///
+    /// assert_eq!(&*texts[0], "hi there");
+    /// assert_eq!(&*texts[1], "one  two");
+    /// ```
+    pub fn text_ref(&self) -> StrTendril {
+        let children = self.handle.children.borrow();
+        let mut texts = children.iter().filter_map(|child| match child.data {
+            NodeData::Text { ref contents } => Some(contents.borrow().clone()),
+            _ => None,
+        });
+
+        let mut res = texts.next().unwrap_or_default();
+        for text in texts {
+            res.push_tendril(&text);
+        }
+
+        res
+    }
+
+    /// Get current value of a form control following HTML defaults:
+    /// * `<input>` - `value` attribute, `on` for checkboxes and radio buttons without it
+    /// * `<textarea>` - its text
+    /// * `<select>` - value of the selected option, first enabled option if none is selected
+    /// * `<option>` - `value` attribute or its text with collapsed whitespace
+    ///
+    /// Returns None for other elements and for `<select>` without selected option.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(
+    ///     "<input name='q' value='crab'>
+    ///      <textarea>hi there</textarea>
+    ///      <select><option>one</option><option value='2' selected>two</option></select>",
+    /// );
+    ///
+    /// assert_eq!(doc.select_first("input").unwrap().value().unwrap(), "crab");
+    /// assert_eq!(doc.select_first("textarea").unwrap().value().unwrap(), "hi there");
+    /// assert_eq!(doc.select_first("select").unwrap().value().unwrap(), "2");
+    /// ```
+    pub fn value(&self) -> Option<String> {
+        match self.tag()?.as_str() {
+            "input" => match self.attr("value") {
+                Some(value) => Some(value),
+                None => {
+                    let kind = self.attr("type").unwrap_or_default().to_ascii_lowercase();
+                    match kind.trim() {
+                        "checkbox" | "radio" => Some("on".to_string()),
+                        _ => Some("".to_string()),
+                    }
+                }
+            },
+            "textarea" => Some(self.descendant_text_nodes().concat()),
+            "option" => match self.attr("value") {
+                Some(value) => Some(value),
+                None => Some(self.option_text()),
+            },
+            "select" => {
+                let options = self.select("option");
+                let selected = self.selectedness(&options);
+
+                options
+                    .iter()
+                    .zip(selected)
+                    .find(|(_, selected)| *selected)
+                    .and_then(|(option, _)| option.value())
+            }
+            _ => None,
+        }
+    }
+
+    /// Get options of a `<select>` element as `(value, label, selected)` tuples,
+    /// label is taken from `label` attribute or option text.
+    /// Selection follows the same defaults as `value()`.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(
+    ///     "<select><option value='1'>One</option><option label='Two' selected>2</option></select>",
+    /// );
+    /// let el = doc.select_first("select").unwrap();
+    ///
+    /// assert_eq!(
+    ///     el.options(),
+    ///     vec![
+    ///         ("1".to_string(), "One".to_string(), false),
+    ///         ("2".to_string(), "Two".to_string(), true),
+    ///     ]
+    /// );
+    /// ```
+    pub fn options(&self) -> Vec<(String, String, bool)> {
+        if self.tag().as_deref() != Some("select") {
+            return vec![];
+        }
+
+        let options = self.select("option");
+        let selected = self.selectedness(&options);
+
+        options
+            .iter()
+            .zip(selected)
+            .map(|(option, selected)| {
+                let value = option.value().unwrap_or_default();
+                let label = match option.attr("label") {
+                    Some(label) if !label.is_empty() => label,
+                    _ => option.option_text(),
+                };
+                (value, label, selected)
+            })
+            .collect()
+    }
+
+    /// Get selected options of a `<select>` element as `(value, label, selected)` tuples
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(
+    ///     "<select multiple><option selected>a</option><option>b</option><option selected>c</option></select>",
+    /// );
+    /// let el = doc.select_first("select").unwrap();
+    /// let values: Vec<_> = el.selected_options().into_iter().map(|o| o.0).collect();
+    ///
+    /// assert_eq!(values, vec!["a", "c"]);
+    /// ```
+    pub fn selected_options(&self) -> Vec<(String, String, bool)> {
+        self.options()
+            .into_iter()
+            .filter(|(_, _, selected)| *selected)
+            .collect()
+    }
+
+    /// Get text node immediately following the element, None if next sibling is not a text
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p><b>Price:</b> $10</p>");
+    /// let el = doc.select_first("b").unwrap();
+    ///
+    /// assert_eq!(el.next_text().unwrap(), " $10");
+    /// ```
+    pub fn next_text(&self) -> Option<String> {
+        let parent = parent_handle(&self.handle)?;
+        let children = parent.children.borrow();
+        let i = children.iter().position(|n| Arc::ptr_eq(n, &self.handle))?;
+
+        children.get(i + 1).and_then(text_contents)
+    }
+
+    /// Get text node immediately preceding the element, None if previous sibling is not a text
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>Total: <b>$10</b></p>");
+    /// let el = doc.select_first("b").unwrap();
+    ///
+    /// assert_eq!(el.prev_text().unwrap(), "Total: ");
+    /// ```
+    pub fn prev_text(&self) -> Option<String> {
+        let parent = parent_handle(&self.handle)?;
+        let children = parent.children.borrow();
+        let i = children.iter().position(|n| Arc::ptr_eq(n, &self.handle))?;
+
+        children[..i].last().and_then(text_contents)
+    }
+
+    /// Get contents of text nodes which are direct children of the element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>one <b>two</b> three</p>");
+    /// let el = doc.select_first("p").unwrap();
+    ///
+    /// assert_eq!(el.text_nodes(), vec!["one ", " three"]);
+    /// ```
+    pub fn text_nodes(&self) -> Vec<String> {
+        self.handle
+            .children
+            .borrow()
+            .iter()
+            .filter_map(|child| match child.data {
+                NodeData::Text { ref contents } => Some(contents.borrow().to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Get contents of all text nodes inside of the element in document order
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>one <b>two</b> three</p>");
+    /// let el = doc.select_first("p").unwrap();
+    ///
+    /// assert_eq!(el.descendant_text_nodes(), vec!["one ", "two", " three"]);
+    /// ```
+    pub fn descendant_text_nodes(&self) -> Vec<String> {
+        let mut acc = vec![];
+        collect_text_nodes(&self.handle, &mut acc);
+        acc
+    }
+
+    /// Get children elements
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link'><span>hi there</span></a>");
+    /// let sel = doc.select("a");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.children().first().unwrap().text().unwrap(), "hi there");
+    /// ```
+    pub fn children(&self) -> Vec<Element> {
+        self.handle
+            .children
+            .borrow()
+            .iter()
+            .filter(|n| matches!(n.data, NodeData::Element { .. }))
+            .map(|n| self.element(n))
+            .collect::<Vec<_>>()
+    }
+
+    /// Get depth of the element subtree, number of levels of nested elements
+    /// below the element, so element without child elements has depth 0
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><p><b>hi</b></p><p>there</p></div>");
+    /// let el = doc.select_first("div").unwrap();
+    ///
+    /// assert_eq!(el.depth(), 2);
+    /// ```
+    pub fn depth(&self) -> usize {
+        let mut max = 0;
+        let mut stack = vec![(Arc::clone(&self.handle), 0)];
+
+        while let Some((node, depth)) = stack.pop() {
+            max = max.max(depth);
+            for child in node.children.borrow().iter().filter(|n| is_element(n)) {
+                stack.push((Arc::clone(child), depth + 1));
+            }
+        }
+
+        max
+    }
+
+    /// Get number of elements nested inside of the element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><p><b>hi</b></p><p>there</p></div>");
+    /// let el = doc.select_first("div").unwrap();
+    ///
+    /// assert_eq!(el.descendant_count(), 3);
+    /// ```
+    pub fn descendant_count(&self) -> usize {
+        descendants(&self.handle)
+            .iter()
+            .filter(|n| is_element(n))
+            .count()
+    }
+
+    /// Get next element in document order, which is the first child element,
+    /// or next sibling of the element or of its closest ancestor having one
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><h2>Price</h2></div><p><b>10</b></p>");
+    /// let el = doc.select_first("h2").unwrap();
+    ///
+    /// assert_eq!(el.next_in_document().unwrap().tag().unwrap(), "p");
+    /// ```
+    pub fn next_in_document(&self) -> Option<Element> {
+        let first_child = self
+            .handle
+            .children
+            .borrow()
+            .iter()
+            .find(|n| is_element(n))
+            .cloned();
+        if let Some(child) = first_child {
+            return Some(self.element(&child));
+        }
+
+        let mut current = Arc::clone(&self.handle);
+        loop {
+            if let Some(sibling) = next_element_sibling(&current) {
+                return Some(self.element(&sibling));
+            }
+            current = parent_handle(&current)?;
+        }
+    }
+
+    /// Get previous element in document order, which is the deepest last descendant
+    /// of the previous sibling, or the parent element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><h2>Price</h2></div><p><b>10</b></p>");
+    /// let el = doc.select_first("p").unwrap();
+    ///
+    /// assert_eq!(el.prev_in_document().unwrap().tag().unwrap(), "h2");
+    /// ```
+    pub fn prev_in_document(&self) -> Option<Element> {
+        let mut node = match prev_element_sibling(&self.handle) {
+            Some(node) => node,
+            None => {
+                return parent_handle(&self.handle)
+                    .filter(is_element)
+                    .map(|n| self.element(&n))
+            }
+        };
+
+        loop {
+            let last_child = node
+                .children
+                .borrow()
+                .iter()
+                .rev()
+                .find(|n| is_element(n))
+                .cloned();
+            match last_child {
+                Some(child) => node = child,
+                None => return Some(self.element(&node)),
+            }
+        }
+    }
+
+    /// Get following sibling elements up to but excluding the first one matching given selector,
+    /// all following siblings are returned if none matches
+    ///
+    /// # Arguments
+    /// * `selector` - css selector of the boundary element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<h2>One</h2><p>a</p><p>b</p><h2>Two</h2><p>c</p>");
+    /// let heading = doc.select_first("h2").unwrap();
+    /// let section = heading.next_until("h2");
+    ///
+    /// assert_eq!(section.len(), 2);
+    /// assert_eq!(section[1].text().unwrap(), "b");
+    /// ```
+    pub fn next_until(&self, selector: &str) -> Vec<Element> {
+        let sel = Selector::compile(selector);
+        let mut acc = vec![];
+
+        let mut node = next_element_sibling(&self.handle);
+        while let Some(n) = node {
+            if sel.matches(&n) {
+                break;
+            }
+            node = next_element_sibling(&n);
+            acc.push(self.element(&n));
+        }
+
+        acc
+    }
+
+    /// Get preceding sibling elements up to but excluding the first one matching given selector,
+    /// closest sibling comes first
+    ///
+    /// # Arguments
+    /// * `selector` - css selector of the boundary element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<h2>One</h2><p>a</p><p>b</p><h2>Two</h2>");
+    /// let heading = doc.select("h2").into_iter().last().unwrap();
+    /// let section = heading.prev_until("h2");
+    ///
+    /// assert_eq!(section.len(), 2);
+    /// assert_eq!(section[0].text().unwrap(), "b");
+    /// ```
+    pub fn prev_until(&self, selector: &str) -> Vec<Element> {
+        let sel = Selector::compile(selector);
+        let mut acc = vec![];
+
+        let mut node = prev_element_sibling(&self.handle);
+        while let Some(n) = node {
+            if sel.matches(&n) {
+                break;
+            }
+            node = prev_element_sibling(&n);
+            acc.push(self.element(&n));
+        }
+
+        acc
+    }
+
+    /// Iterate over children elements without collecting them
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li>two</li></ul>");
+    /// let el = doc.select_first("ul").unwrap();
+    ///
+    /// assert_eq!(el.children_iter().len(), 2);
+    /// for child in &el {
+    ///     assert_eq!(child.tag().unwrap(), "li");
+    /// }
+    /// ```
+    pub fn children_iter(&self) -> Children {
+        Children {
+            parent: Arc::clone(&self.handle),
+            context: Rc::clone(&self.context),
+            index: 0,
+        }
+    }
+
+    /// Get parent element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a class='link'><span>hi there</span></a>");
+    /// let sel = doc.select("span");
+    /// let el = sel.first().unwrap();
+    ///
+    /// assert_eq!(el.parent().unwrap().tag().unwrap(), "a");
+    /// ```
+    pub fn parent(&self) -> Option<Element> {
+        parent_handle(&self.handle).map(|n| self.element(&n))
+    }
+
+    /// Get ancestor `n` levels up, same as calling `parent()` `n` times
+    ///
+    /// # Arguments
+    /// * `n` - number of levels, `0` returns the element itself
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<table><tr><td><b>hi</b></td></tr></table>");
+    /// let el = doc.select_first("b").unwrap();
+    ///
+    /// assert_eq!(el.nth_ancestor(2).unwrap().tag().unwrap(), "tr");
+    /// assert!(el.nth_ancestor(100).is_none());
+    /// ```
+    pub fn nth_ancestor(&self, n: usize) -> Option<Element> {
+        let mut handle = Arc::clone(&self.handle);
+        for _ in 0..n {
+            handle = parent_handle(&handle)?;
+        }
+
+        Some(self.element(&handle))
+    }
+
+    /// Get ancestor elements up to but excluding the first one matching given selector,
+    /// closest ancestor comes first and all ancestors are returned if none matches
+    ///
+    /// # Arguments
+    /// * `selector` - css selector of the boundary element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<article><div><p><a>link</a></p></div></article>");
+    /// let el = doc.select_first("a").unwrap();
+    /// let parents = el.parents_until("article");
+    ///
+    /// assert_eq!(parents.len(), 2);
+    /// assert_eq!(parents[0].tag().unwrap(), "p");
+    /// assert_eq!(parents[1].tag().unwrap(), "div");
+    /// ```
+    pub fn parents_until(&self, selector: &str) -> Vec<Element> {
+        let sel = Selector::compile(selector);
+        let mut acc = vec![];
+
+        let mut node = parent_handle(&self.handle);
+        while let Some(n) = node.filter(is_element) {
+            if sel.matches(&n) {
+                break;
+            }
+            node = parent_handle(&n);
+            acc.push(self.element(&n));
+        }
+
+        acc
+    }
+
+    /// Check if given element is nested inside of this element
+    ///
+    /// # Arguments
+    /// * `other` - possible descendant element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div class='card'><span class='price'>10</span></div>");
+    /// let card = doc.select_first(".card").unwrap();
+    /// let price = doc.select_first(".price").unwrap();
+    ///
+    /// assert!(card.contains(&price));
+    /// assert!(!price.contains(&card));
+    /// assert!(!card.contains(&card));
+    /// ```
+    pub fn contains(&self, other: &Element) -> bool {
+        other.is_descendant_of(self)
+    }
+
+    /// Check if this element is an ancestor of given element, same as `contains`
+    ///
+    /// # Arguments
+    /// * `other` - possible descendant element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div class='card'><span class='price'>10</span></div>");
+    /// let card = doc.select_first(".card").unwrap();
+    /// let price = doc.select_first(".price").unwrap();
+    ///
+    /// assert!(card.is_ancestor_of(&price));
+    /// ```
+    pub fn is_ancestor_of(&self, other: &Element) -> bool {
+        self.contains(other)
+    }
+
+    /// Check if this element is nested inside of given element
+    ///
+    /// # Arguments
+    /// * `other` - possible ancestor element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div class='card'><span class='price'>10</span></div>");
+    /// let card = doc.select_first(".card").unwrap();
+    /// let price = doc.select_first(".price").unwrap();
+    ///
+    /// assert!(price.is_descendant_of(&card));
+    /// ```
+    pub fn is_descendant_of(&self, other: &Element) -> bool {
+        let mut current = parent_handle(&self.handle);
+
+        while let Some(node) = current {
+            if Arc::ptr_eq(&node, &other.handle) {
+                return true;
+            }
+            current = parent_handle(&node);
+        }
+
+        false
+    }
+
+    /// Generate css selector uniquely identifying the element in its document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div id='main'><ul><li>one</li><li>two</li></ul></div>");
+    /// let sel = doc.select("li");
+    /// let path = sel[1].css_path();
+    ///
+    /// assert_eq!(path, "html > body > div#main > ul > li:nth-child(2)");
+    /// assert_eq!(doc.select(&path)[0].text().unwrap(), "two");
+    /// ```
+    pub fn css_path(&self) -> String {
+        let mut parts = vec![];
+        let mut current = Some(Arc::clone(&self.handle));
+
+        while let Some(node) = current {
+            let name = match node.data {
+                NodeData::Element { ref name, .. } => name.local.to_string(),
+                _ => break,
+            };
+            let el = self.element(&node);
+            let mut part = name.clone();
+
+            if let Some(id) = el.id() {
+                if id
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+                {
+                    part.push('#');
+                    part.push_str(&id);
+                }
+            }
+
+            if let Some(parent) = parent_handle(&node) {
+                let same_tag = parent
+                    .children
+                    .borrow()
+                    .iter()
+                    .filter(|n| matches!(n.data, NodeData::Element { name: ref n, .. } if n.local == *name))
+                    .count();
+
+                if same_tag > 1 {
+                    if let Some(i) = element_index(&node) {
+                        part.push_str(&format!(":nth-child({})", i + 1));
+                    }
+                }
+            }
+
+            parts.push(part);
+            current = parent_handle(&node);
+        }
+
+        parts.reverse();
+        parts.join(" > ")
+    }
+
+    /// Get position of the element among its parent's child elements
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li>text<li>two</li></ul>");
+    /// let sel = doc.select("li");
+    ///
+    /// assert_eq!(sel[1].index_in_parent().unwrap(), 1);
+    /// ```
+    pub fn index_in_parent(&self) -> Option<usize> {
+        element_index(&self.handle)
+    }
+
+    /// Get position of the element among all its parent's child nodes,
+    /// including text and comment nodes
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li>text<li>two</li></ul>");
+    /// let sel = doc.select("li");
+    ///
+    /// assert_eq!(sel[1].node_index_in_parent().unwrap(), 2);
+    /// ```
+    pub fn node_index_in_parent(&self) -> Option<usize> {
+        let parent = parent_handle(&self.handle)?;
+        let children = parent.children.borrow();
+
+        children.iter().position(|n| Arc::ptr_eq(n, &self.handle))
+    }
+
+    /// Select child elements using given css selector, matches are in document order
+    /// and every element is included once. Selector which can not be parsed matches nothing
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<span><a class='link'>hi there</a></span>");
+    /// let sel = doc.select("span");
+    /// let el = sel.first().unwrap();
+    /// let sel = el.select("a");
+    /// let a = sel.first().unwrap();
+    ///
+    /// assert_eq!(a.attr("class").unwrap(), "link");
+    /// ```
+    pub fn select(&self, selector: &str) -> Selection {
+        let sel = Selector::compile(selector);
+        sel.find(self.handle.children.borrow())
+            .iter()
+            .map(|handle| self.element(handle))
+            .collect()
+    }
+
+    /// Select child elements using given css selector, fails with `Error::InvalidSelector`
+    /// when the selector can not be parsed
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><a href='/'>home</a></div>");
+    /// let div = doc.select_first("div").unwrap();
+    ///
+    /// assert_eq!(div.try_select("a[href]").unwrap().len(), 1);
+    /// assert!(div.try_select("a[=\"/\"]").is_err());
+    /// ```
+    pub fn try_select(&self, selector: &str) -> Result<Selection, Error> {
+        let sel = Selector::cached(selector)?;
+        Ok(sel
+            .find(self.handle.children.borrow())
+            .iter()
+            .map(|handle| self.element(handle))
+            .collect())
+    }
+
+    /// Lazily select descendant elements matching given css selector in document order,
+    /// the subtree is traversed only as far as the iterator is consumed
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><p>one</p><section><p>two</p></section></div><p>three</p>");
+    /// let div = doc.select_first("div").unwrap();
+    ///
+    /// assert_eq!(div.select_iter("p").count(), 2);
+    /// assert_eq!(div.select_iter("section p").next().unwrap().text().unwrap(), "two");
+    /// assert!(div.select_iter("div p").next().is_none());
+    /// ```
+    pub fn select_iter(&self, selector: &str) -> impl Iterator<Item = Element> {
+        let context = Rc::clone(&self.context);
+        Selector::compile(selector)
+            .iter(Arc::clone(&self.handle))
+            .map(move |handle| Element {
+                handle,
+                context: Rc::clone(&context),
+            })
+    }
+
+    /// Select first child element in document order matching given css selector,
+    /// traversal stops as soon as the match is found
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<span><a>one</a><a>two</a></span>");
+    /// let el = doc.select_first("span").unwrap();
+    /// let a = el.select_first("a").unwrap();
+    ///
+    /// assert_eq!(a.text().unwrap(), "one");
+    /// ```
+    pub fn select_first(&self, selector: &str) -> Option<Element> {
+        let sel = Selector::compile(selector);
+        sel.find_first(&self.handle)
+            .map(|handle| self.element(&handle))
+    }
+
+    /// Select at most given number of child elements matching css selector in document order,
+    /// traversal stops once enough matches are found
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    /// * `limit` - maximum number of elements
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>1</li><li>2</li><li>3</li></ul>");
+    /// let ul = doc.first("ul").unwrap();
+    ///
+    /// assert_eq!(ul.select_with_limit("li", 1).texts(), vec!["1"]);
+    /// assert!(ul.select_with_limit("li", 0).is_empty());
+    /// ```
+    pub fn select_with_limit(&self, selector: &str, limit: usize) -> Selection {
+        self.select_iter(selector).take(limit).collect()
+    }
+
+    /// Count child elements matching given css selector without collecting them
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<table><tr><td>1</td><td>2</td></tr></table><td>3</td>");
+    /// let table = doc.first("table").unwrap();
+    ///
+    /// assert_eq!(table.select_count("td"), 2);
+    /// ```
+    pub fn select_count(&self, selector: &str) -> usize {
+        Selector::compile(selector)
+            .iter(Arc::clone(&self.handle))
+            .count()
+    }
+
+    /// Get first child element in document order matching given css selector,
+    /// same as `select_first`
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><b>one</b><b>two</b></div>");
+    /// let div = doc.first("div").unwrap();
+    ///
+    /// assert_eq!(div.first("b").unwrap().text().unwrap(), "one");
+    /// ```
+    pub fn first(&self, selector: &str) -> Option<Element> {
+        self.select_first(selector)
+    }
+
+    /// Get last child element in document order matching given css selector,
+    /// the subtree is walked from the end and traversal stops at the match
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><p><b>one</b></p><b>two</b><i>three</i></div><b>four</b>");
+    /// let div = doc.first("div").unwrap();
+    ///
+    /// assert_eq!(div.last("b").unwrap().text().unwrap(), "two");
+    /// ```
+    pub fn last(&self, selector: &str) -> Option<Element> {
+        Selector::compile(selector)
+            .find_last(&self.handle)
+            .map(|handle| self.element(&handle))
+    }
+
+    /// Get child element matching given css selector at given position in document order,
+    /// traversal stops once it is found
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    /// * `n` - position counting from zero
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ol><li>one</li><li>two</li></ol><li>three</li>");
+    /// let ol = doc.first("ol").unwrap();
+    ///
+    /// assert_eq!(ol.nth("li", 1).unwrap().text().unwrap(), "two");
+    /// assert!(ol.nth("li", 2).is_none());
+    /// ```
+    pub fn nth(&self, selector: &str, n: usize) -> Option<Element> {
+        self.select_iter(selector).nth(n)
+    }
+
+    /// Check if any child element matches given css selector,
+    /// traversal stops at the first match
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<article><img src='a.png'></article><p>text</p>");
+    /// let article = doc.first("article").unwrap();
+    ///
+    /// assert!(article.exists("img"));
+    /// assert!(!article.exists("p"));
+    /// ```
+    pub fn exists(&self, selector: &str) -> bool {
+        self.select_first(selector).is_some()
+    }
+
+    /// Get location of the element in the original html input
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div>\n  <span>hi there</span>\n</div>");
+    /// let location = doc.select_first("span").unwrap().source_location().unwrap();
+    ///
+    /// assert_eq!(location.line, 2);
+    /// assert_eq!(location.column, Some(3));
+    /// ```
+    pub fn source_location(&self) -> Option<SourceLocation> {
+        self.context
+            .source(&self.handle)
+            .map(|source| SourceLocation {
+                line: source.line,
+                column: Some(source.column),
+            })
+    }
+
+    /// Get byte range of the element markup in the original html input,
+    /// useful to slice raw html preserving its formatting and entities.
+    /// Range of an element closed implicitly by the parser ends where the next tag starts.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let html = "<div><a href='/'>hi &amp; bye</a></div>";
+    /// let doc = Document::from(html);
+    /// let el = doc.select_first("a").unwrap();
+    ///
+    /// assert_eq!(&html[el.source_range().unwrap()], "<a href='/'>hi &amp; bye</a>");
+    /// ```
+    pub fn source_range(&self) -> Option<Range<usize>> {
+        self.context
+            .source(&self.handle)
+            .map(|source| source.range.clone())
+    }
+
+    /// Copy the element with its subtree into new standalone document
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><ul><li>one</li></ul></div><p>two</p>");
+    /// let list = doc.select_first("ul").unwrap().into_document();
+    ///
+    /// assert_eq!(list.select("li").len(), 1);
+    /// assert!(list.select("p").is_empty());
+    /// ```
+    pub fn into_document(self) -> Document {
+        let doc = ArcDom::default();
+        let node = deep_clone(&self.handle);
+        node.parent.set(Some(Arc::downgrade(&doc.document)));
+        doc.document.children.borrow_mut().push(node);
+
+        let context = Context {
+            base_url: self.context.base_url.clone(),
+            ..Default::default()
+        };
+
+        Document {
+            doc,
+            context: Rc::new(context),
+        }
+    }
+
+    /// Get independent copy of the element and its descendants detached from the document,
+    /// changes to the copy do not affect the original
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><ul><li>one</li></ul></div>");
+    /// let list = doc.select_first("ul").unwrap();
+    /// let copy = list.clone_subtree();
+    ///
+    /// assert!(list != copy);
+    /// assert!(copy.parent().is_none());
+    /// assert_eq!(copy.select("li")[0].text().unwrap(), "one");
+    /// ```
+    pub fn clone_subtree(&self) -> Element {
+        let context = Context {
+            base_url: self.context.base_url.clone(),
+            ..Default::default()
+        };
+
+        Element {
+            handle: deep_clone(&self.handle),
+            context: Rc::new(context),
+        }
+    }
+
+    /// Remove the element with its descendants from the document,
+    /// element stays usable as a detached subtree
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<nav>menu</nav><article><p>text</p><div class='ad'>buy</div></article>");
+    /// for el in doc.select("nav").iter().chain(doc.select(".ad").iter()) {
+    ///     el.remove();
+    /// }
+    ///
+    /// assert!(doc.select("nav").is_empty());
+    /// assert!(doc.select(".ad").is_empty());
+    /// assert_eq!(doc.select_first("article").unwrap().children().len(), 1);
+    /// ```
+    pub fn remove(&self) {
+        self.context.record_parent(&self.handle);
+        detach(&self.handle);
+    }
+
+    /// Insert content after the last child of the element. Nothing is changed when
+    /// the content is the element itself or its ancestor.
+    ///
+    /// # Arguments
+    /// * `content` - element, html fragment or text, see `Content`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Content, Document};
+    ///
+    /// let doc = Document::from("<ul><li>one</li></ul>");
+    /// let list = doc.select_first("ul").unwrap();
+    /// list.append_child(Content::html("<li>two</li>"));
+    /// list.append_child(&doc.create_element("li"));
+    ///
+    /// assert_eq!(doc.select("li").len(), 3);
+    /// assert_eq!(doc.select("li")[1].text().unwrap(), "two");
+    /// ```
+    pub fn append_child(&self, content: impl Into<Content>) {
+        let content = content.into();
+        self.record_insert(&self.handle, &content);
+        if let Some(nodes) = content.into_nodes(&self.handle) {
+            content::insert_nodes(&self.handle, usize::MAX, nodes);
+        }
+    }
+
+    /// Insert content before the first child of the element. Nothing is changed when
+    /// the content is the element itself or its ancestor.
+    ///
+    /// # Arguments
+    /// * `content` - element, html fragment or text, see `Content`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Content, Document};
+    ///
+    /// let doc = Document::from("<p>world</p>");
+    /// let p = doc.select_first("p").unwrap();
+    /// p.prepend_child(Content::text("hello "));
+    ///
+    /// assert_eq!(p.text().unwrap(), "hello world");
+    /// ```
+    pub fn prepend_child(&self, content: impl Into<Content>) {
+        let content = content.into();
+        self.record_insert(&self.handle, &content);
+        if let Some(nodes) = content.into_nodes(&self.handle) {
+            content::insert_nodes(&self.handle, 0, nodes);
+        }
+    }
+
+    /// Replace children of the element with a text node, text is inserted as is.
+    /// For `<template>` its content is replaced.
+    ///
+    /// # Arguments
+    /// * `text` - new text
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p class='email'>contact <b>me@example.com</b></p>");
+    /// let p = doc.select_first(".email").unwrap();
+    /// p.set_text("[redacted]");
+    ///
+    /// assert_eq!(p.text().unwrap(), "[redacted]");
+    /// assert!(doc.select("b").is_empty());
+    /// ```
+    pub fn set_text(&self, text: &str) {
+        self.set_children(Content::text(text));
+    }
+
+    /// Replace children of the element with html fragment parsed in context of the element,
+    /// for `<template>` its content is replaced
+    ///
+    /// # Arguments
+    /// * `html` - html fragment
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<table><tr><td>old</td></tr></table>");
+    /// let row = doc.select_first("tr").unwrap();
+    /// row.set_inner_html("<td>one</td><td>two</td>");
+    ///
+    /// assert_eq!(doc.select("tr > td").len(), 2);
+    /// ```
+    pub fn set_inner_html(&self, html: &str) {
+        self.set_children(Content::html(html));
+    }
+
+    /// Replace every match of regular expression in text nodes inside of the element,
+    /// elements are kept as they are and matches can not span several text nodes.
+    /// Replacement can refer to capture groups like `$1`, same as `Regex::replace_all`.
+    /// Empty matches and text of elements like `<script>`, `<style>` or `<textarea>`
+    /// are skipped. Returns number of replaced matches. Available with `regex` feature.
+    ///
+    /// # Arguments
+    /// * `regex` - regular expression
+    /// * `replacement` - replacement for every match
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    /// use crabquery::regex::Regex;
+    ///
+    /// let doc = Document::from(
+    ///     "<div>Mail <b>ann@example.com</b> or bob@example.org, id: <i>12-345</i></div>",
+    /// );
+    /// let el = doc.select_first("div").unwrap();
+    ///
+    /// assert_eq!(el.replace_text(&Regex::new(r"\S+@(\S+)\.\w+").unwrap(), "[email at $1]"), 2);
+    /// assert_eq!(el.replace_text(&Regex::new(r"\d+-\d+").unwrap(), "[id]"), 1);
+    /// assert_eq!(
+    ///     el.descendant_text_nodes().concat(),
+    ///     "Mail [email at example] or [email at example], id: [id]"
+    /// );
+    /// assert_eq!(doc.select("div > b").len(), 1);
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn replace_text(&self, regex: &regex::Regex, replacement: &str) -> usize {
+        let mut count = 0;
+
+        for node in descendants(&self.handle) {
+            let text = match text_contents(&node) {
+                Some(text) => text,
+                None => continue,
+            };
+            let parent = match parent_handle(&node) {
+                Some(parent) if !is_raw_text(&parent) => parent,
+                _ => continue,
+            };
+
+            let mut replaced = String::new();
+            let mut end = 0;
+            let mut matches = 0;
+            for caps in regex.captures_iter(&text) {
+                let found = match caps.get(0) {
+                    Some(found) if !found.as_str().is_empty() => found,
+                    _ => continue,
+                };
+                replaced.push_str(&text[end..found.start()]);
+                caps.expand(replacement, &mut replaced);
+                end = found.end();
+                matches += 1;
+            }
+            if matches == 0 {
+                continue;
+            }
+            count += matches;
+            replaced.push_str(&text[end..]);
+
+            self.context.record_children(&parent);
+            if let Some((_, i)) = detach(&node) {
+                if !replaced.is_empty() {
+                    let node = Node::new(NodeData::Text {
+                        contents: RefCell::new(replaced.as_str().into()),
+                    });
+                    content::insert_nodes(&parent, i, vec![node]);
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Wrap every occurrence of text inside of text nodes into a copy of the first element
+    /// of html fragment, when the wrapper has child elements the text goes into the innermost
+    /// first one. Text nodes are split around occurrences, which can not span several text
+    /// nodes. Text of elements like `<script>`, `<style>` or `<textarea>` is skipped.
+    /// Returns number of wrapped occurrences.
+    ///
+    /// # Arguments
+    /// * `text` - text to look for, case sensitive
+    /// * `wrapper` - html fragment with the wrapper
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>Crabs walk sideways, <i>crabs</i> eat. Crabs!</p>");
+    /// let p = doc.select_first("p").unwrap();
+    ///
+    /// assert_eq!(p.highlight("Crabs", "<mark class='hit'></mark>"), 2);
+    /// assert_eq!(doc.select("p > mark.hit").len(), 2);
+    /// assert_eq!(p.text_nodes(), vec![" walk sideways, ", " eat. ", "!"]);
+    /// ```
+    pub fn highlight(&self, text: &str, wrapper: &str) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+
+        self.wrap_matches(wrapper, |contents| {
+            contents
+                .match_indices(text)
+                .map(|(i, m)| i..i + m.len())
+                .collect()
+        })
+    }
+
+    /// Wrap every match of regular expression inside of text nodes into a copy of the first
+    /// element of html fragment, same as `highlight`. Empty matches are skipped.
+    /// Available with `regex` feature.
+    ///
+    /// # Arguments
+    /// * `regex` - regular expression
+    /// * `wrapper` - html fragment with the wrapper
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    /// use crabquery::regex::Regex;
+    ///
+    /// let doc = Document::from("<p>Order 1234 shipped, order <b>5678</b> pending</p>");
+    /// let p = doc.select_first("p").unwrap();
+    ///
+    /// assert_eq!(p.highlight_regex(&Regex::new(r"\d+").unwrap(), "<mark></mark>"), 2);
+    /// let marks: Vec<_> = doc.select("mark").iter().map(|m| m.text().unwrap()).collect();
+    /// assert_eq!(marks, vec!["1234", "5678"]);
+    /// assert_eq!(doc.select("b > mark").len(), 1);
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn highlight_regex(&self, regex: &regex::Regex, wrapper: &str) -> usize {
+        self.wrap_matches(wrapper, |contents| {
+            regex
+                .find_iter(contents)
+                .filter(|m| !m.as_str().is_empty())
+                .map(|m| m.range())
+                .collect()
+        })
+    }
+
+    /// Split descendant text nodes around ranges found by `find`
+    /// and put every range into a copy of the wrapper
+    fn wrap_matches<F>(&self, wrapper: &str, find: F) -> usize
+    where
+        F: Fn(&str) -> Vec<Range<usize>>,
+    {
+        let nodes = Content::html(wrapper)
+            .into_nodes(&self.handle)
+            .unwrap_or_default();
+        let wrapper = match nodes.into_iter().find(is_element) {
+            Some(wrapper) => wrapper,
+            None => return 0,
+        };
+        let text_node = |text: &str| {
+            Node::new(NodeData::Text {
+                contents: RefCell::new(text.into()),
+            })
+        };
+        let mut count = 0;
+
+        for node in descendants(&self.handle) {
+            let text = match text_contents(&node) {
+                Some(text) => text,
+                None => continue,
+            };
+            let parent = match parent_handle(&node) {
+                Some(parent) => parent,
+                None => continue,
+            };
+            let ranges = find(&text);
+            if is_raw_text(&parent) || ranges.is_empty() {
+                continue;
+            }
+            count += ranges.len();
+
+            let mut parts = vec![];
+            let mut end = 0;
+            for range in ranges {
+                if range.start > end {
+                    parts.push(text_node(&text[end..range.start]));
+                }
+                let copy = deep_clone(&wrapper);
+                append_child(&innermost_element(&copy), text_node(&text[range.clone()]));
+                parts.push(copy);
+                end = range.end;
+            }
+            if end < text.len() {
+                parts.push(text_node(&text[end..]));
+            }
+
+            self.context.record_children(&parent);
+            if let Some((_, i)) = detach(&node) {
+                content::insert_nodes(&parent, i, parts);
+            }
+        }
+
+        count
+    }
+
+    fn set_children(&self, content: Content) {
+        let parent = match self.handle.data {
+            NodeData::Element {
+                template_contents: Some(ref contents),
+                ..
+            } => Arc::clone(contents),
+            _ => Arc::clone(&self.handle),
+        };
+        self.record_insert(&parent, &content);
+        let nodes = content.into_nodes(&self.handle).unwrap_or_default();
+
+        let children = std::mem::take(&mut *parent.children.borrow_mut());
+        for child in children.iter() {
+            child.parent.set(None);
+        }
+        content::insert_nodes(&parent, 0, nodes);
+    }
+
+    /// Replace the element with given content at the same position among its siblings,
+    /// element is detached from the document afterwards. Nothing is changed when
+    /// the element has no parent or the content is the element itself or its ancestor.
+    ///
+    /// # Arguments
+    /// * `content` - element, html fragment or text, see `Content`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Content, Document};
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li class='ad'>buy</li><li>three</li></ul>");
+    /// let ad = doc.select_first(".ad").unwrap();
+    /// ad.replace_with(Content::html("<li>two</li>"));
+    ///
+    /// let items: Vec<_> = doc.select("li").iter().map(|li| li.text().unwrap()).collect();
+    /// assert_eq!(items, vec!["one", "two", "three"]);
+    /// assert!(ad.parent().is_none());
+    /// ```
+    pub fn replace_with(&self, content: impl Into<Content>) {
+        if let Some(nodes) = self.sibling_nodes(content.into()) {
+            if let Some((parent, i)) = detach(&self.handle) {
+                content::insert_nodes(&parent, i, nodes);
+            }
+        }
+    }
+
+    /// Insert content right before the element, as its previous siblings.
+    /// Nothing is changed when the element has no parent or the content is
+    /// the element itself or its ancestor.
+    ///
+    /// # Arguments
+    /// * `content` - element, html fragment or text, see `Content`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Content, Document};
+    ///
+    /// let doc = Document::from("<h2>Title</h2><p>text</p>");
+    /// let title = doc.select_first("h2").unwrap();
+    /// title.insert_before(Content::html("<a id='title'></a>"));
+    ///
+    /// assert_eq!(doc.select("a#title")[0].index_in_parent().unwrap(), 0);
+    /// ```
+    pub fn insert_before(&self, content: impl Into<Content>) {
+        if let Some(nodes) = self.sibling_nodes(content.into()) {
+            if let (Some(parent), Some(i)) =
+                (parent_handle(&self.handle), self.node_index_in_parent())
+            {
+                content::insert_nodes(&parent, i, nodes);
+            }
+        }
+    }
+
+    /// Insert content right after the element, as its next siblings.
+    /// Nothing is changed when the element has no parent or the content is
+    /// the element itself or its ancestor.
+    ///
+    /// # Arguments
+    /// * `content` - element, html fragment or text, see `Content`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Content, Document};
+    ///
+    /// let doc = Document::from("<p>one</p><p>three</p>");
+    /// let first = doc.select_first("p").unwrap();
+    /// first.insert_after(Content::html("<p>two</p>"));
+    ///
+    /// assert_eq!(doc.select("p")[1].text().unwrap(), "two");
+    /// ```
+    pub fn insert_after(&self, content: impl Into<Content>) {
+        if let Some(nodes) = self.sibling_nodes(content.into()) {
+            if let (Some(parent), Some(i)) =
+                (parent_handle(&self.handle), self.node_index_in_parent())
+            {
+                content::insert_nodes(&parent, i + 1, nodes);
+            }
+        }
+    }
+
+    /// Wrap the element into the first element of html fragment, when the wrapper has
+    /// child elements the element goes into the innermost first one.
+    /// Nothing is changed when the element has no parent or the html has no elements.
+    ///
+    /// # Arguments
+    /// * `html` - html fragment with the wrapper
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>see <b>this</b></p>");
+    /// doc.select_first("b").unwrap().wrap("<span class='highlight'><mark></mark></span>");
+    ///
+    /// assert_eq!(doc.select("p > span.highlight > mark > b").len(), 1);
+    /// ```
+    pub fn wrap(&self, html: &str) {
+        let parent = match parent_handle(&self.handle) {
+            Some(parent) => parent,
+            None => return,
+        };
+        let nodes = Content::html(html).into_nodes(&parent).unwrap_or_default();
+        let wrapper = match nodes.into_iter().find(is_element) {
+            Some(wrapper) => wrapper,
+            None => return,
+        };
+        let inner = innermost_element(&wrapper);
+
+        self.context.record_parent(&self.handle);
+        if let Some((parent, i)) = detach(&self.handle) {
+            content::insert_nodes(&parent, i, vec![wrapper]);
+            append_child(&inner, Arc::clone(&self.handle));
+        }
+    }
+
+    /// Replace the element with its children, element is left empty and detached.
+    /// Nothing is changed when the element has no parent.
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p><span>one</span> <span>two</span></p>");
+    /// for span in doc.select("span") {
+    ///     span.unwrap();
+    /// }
+    ///
+    /// assert_eq!(doc.select_first("p").unwrap().text().unwrap(), "one two");
+    /// ```
+    pub fn unwrap(&self) {
+        self.context.record_parent(&self.handle);
+        self.context.record_children(&self.handle);
+        if let Some((parent, i)) = detach(&self.handle) {
+            let children = std::mem::take(&mut *self.handle.children.borrow_mut());
+            content::insert_nodes(&parent, i, children);
+        }
+    }
+
+    /// Convert content to be inserted next to the element,
+    /// `None` when there is nowhere to insert it
+    fn sibling_nodes(&self, content: Content) -> Option<Vec<Handle>> {
+        let parent = parent_handle(&self.handle)?;
+        if let Content::Element(ref el) = content {
+            if el == self {
+                return None;
+            }
+        }
+
+        self.record_insert(&parent, &content);
+        content.into_nodes(&parent)
+    }
+
+    /// Record children of the parent and of the place content is moved from
+    /// before the content is inserted into the parent, the place content is moved from
+    /// is recorded by the document it belongs to
+    fn record_insert(&self, parent: &Handle, content: &Content) {
+        if let Content::Element(ref el) = content {
+            el.context.record_parent(&el.handle);
+        }
+        self.context.record_children(parent);
+    }
+
+    /// Get content of `<template>` element, it is kept apart from the element children
+    /// so `select` does not find it unless `DocumentBuilder::inline_templates` is used
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<template><li class='item'>one</li></template>");
+    /// let template = doc.select_first("template").unwrap();
+    /// let contents = template.template_contents().unwrap();
+    ///
+    /// assert!(doc.select(".item").is_empty());
+    /// assert_eq!(contents.select(".item")[0].text().unwrap(), "one");
+    /// ```
+    pub fn template_contents(&self) -> Option<Element> {
+        match self.handle.data {
+            NodeData::Element {
+                template_contents: Some(ref contents),
+                ..
+            } => Some(self.element(contents)),
+            _ => None,
+        }
+    }
+
+    /// Parse content of `<noscript>` element into new document, with scripting enabled
+    /// (the default) the parser keeps it as a text
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<noscript><img src='/pixel.gif'></noscript>");
+    /// let noscript = doc.select_first("noscript").unwrap();
+    /// let contents = noscript.noscript_contents().unwrap();
+    ///
+    /// assert!(doc.select("img").is_empty());
+    /// assert_eq!(contents.select("img")[0].attr("src").unwrap(), "/pixel.gif");
+    /// ```
+    pub fn noscript_contents(&self) -> Option<Document> {
+        if self.tag()? != "noscript" {
+            return None;
+        }
+
+        // with scripting enabled content is a raw text, serializer would escape it
+        let html: String = self
+            .handle
+            .children
+            .borrow()
+            .iter()
+            .map(|child| {
+                text_contents(child)
+                    .unwrap_or_else(|| serialize::to_html(child, TraversalScope::IncludeNode))
+            })
+            .collect();
+        let mut doc = Document::parse_fragment(&html, "div");
+        if let Some(context) = Rc::get_mut(&mut doc.context) {
+            context.base_url = self.context.base_url.clone();
+        }
+
+        Some(doc)
+    }
+
+    /// Parse `srcdoc` attribute of `<iframe>` element into new document,
+    /// relative urls are resolved against base url of the parent document
+    /// unless `srcdoc` has its own `<base>`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from(r#"<iframe srcdoc="<p class='widget'>hi</p>"></iframe>"#);
+    /// let iframe = doc.select_first("iframe").unwrap();
+    /// let inner = iframe.srcdoc_document().unwrap();
+    ///
+    /// assert_eq!(inner.select(".widget")[0].text().unwrap(), "hi");
+    /// ```
+    pub fn srcdoc_document(&self) -> Option<Document> {
+        if self.tag()? != "iframe" {
+            return None;
+        }
+
+        let srcdoc = self.attr("srcdoc")?;
+        let mut doc = Document::from(srcdoc);
+        if let Some(context) = Rc::get_mut(&mut doc.context) {
+            if context.base_url.is_none() {
+                context.base_url = self.context.base_url.clone();
+            }
+        }
+
+        Some(doc)
+    }
+
+    /// Get contents of all comments inside of the element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div><!-- marker --><span>hi</span></div>");
+    /// let el = doc.select_first("div").unwrap();
+    ///
+    /// assert_eq!(el.comments(), vec![" marker "]);
+    /// ```
+    pub fn comments(&self) -> Vec<String> {
+        let mut acc = vec![];
+        collect_comments(&self.handle, &mut acc);
+        acc
+    }
+
+    /// Text of an option with stripped and collapsed whitespace
+    fn option_text(&self) -> String {
+        self.descendant_text_nodes()
+            .concat()
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Selected state of select options, single choice select keeps the last selected option
+    /// and falls back to the first enabled one when drop down is shown
+    fn selectedness(&self, options: &[Element]) -> Vec<bool> {
+        let mut selected: Vec<bool> = options.iter().map(|o| o.has_attr("selected")).collect();
+        if self.has_attr("multiple") {
+            return selected;
+        }
+
+        match selected.iter().rposition(|s| *s) {
+            Some(last) => {
+                for (i, s) in selected.iter_mut().enumerate() {
+                    *s = i == last;
+                }
+            }
+            None => {
+                let size = self.attr_parse::<u32>("size").and_then(Result::ok);
+                let shown_as_list = matches!(size, Some(size) if size > 1);
+                if !shown_as_list {
+                    if let Some(first) = options.iter().position(|o| !o.has_attr("disabled")) {
+                        selected[first] = true;
+                    }
+                }
+            }
+        }
+
+        selected
+    }
+
+    fn element(&self, handle: &Handle) -> Element {
+        Element {
+            handle: Arc::clone(handle),
+            context: Rc::clone(&self.context),
+        }
+    }
+} //}}}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Matcher tests{{{
+    #[test]
+    fn test_matcher_tag() {
+        let m = Matcher::parse("a").unwrap();
+        assert_eq!(m.tag.as_slice(), &[LocalName::from("a")]);
+    }
+
+    #[test]
+    fn test_matcher_complex() {
+        let m = Matcher::parse("a.link.another_class#idofel.klass").unwrap();
+        assert_eq!(m.tag.as_slice(), &[LocalName::from("a")]);
+        assert_eq!(m.class.as_slice(), &["link", "another_class", "klass"]);
+        assert_eq!(m.id.as_slice(), &["idofel"]);
+    }
+
+    #[test]
+    fn test_matcher_pseudo_class() {
+        let m = Matcher::parse("li.item:nth-child(3)").unwrap();
+        assert_eq!(m.tag.as_slice(), &[LocalName::from("li")]);
+        assert_eq!(m.class.as_slice(), &["item"]);
+        assert_eq!(m.nth_child, Some(3));
+    }
+
+    #[test]
+    fn test_matcher_data_attribute_with_separators() {
+        let m = Matcher::parse("a[href$=\".pdf#page:1\"]").unwrap();
+        assert_eq!(
+            m.attribute.as_slice(),
+            &[(
+                LocalName::from("href"),
+                AttributeSpec::Ends(".pdf#page:1".to_string())
+            )]
+        );
+        assert!(m.class.is_empty());
+        assert!(m.id.is_empty());
+    }
+
+    #[test]
+    fn test_matcher_direct_match() {
+        let m = Matcher::parse(">").unwrap();
+        assert!(m.direct_match);
+    }
+
+    #[test]
+    fn test_matcher_data_attribute_present() {
+        let m = Matcher::parse("a[target]").unwrap();
+        assert_eq!(
+            m.attribute.as_slice(),
+            &[(LocalName::from("target"), AttributeSpec::Present)]
+        );
+    }
+
+    #[test]
+    fn test_matcher_data_attribute_exact() {
+        let m = Matcher::parse("a[target=\"_blank\"]").unwrap();
+        assert_eq!(
+            m.attribute.as_slice(),
+            &[(
+                LocalName::from("target"),
+                AttributeSpec::Exact("_blank".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_matcher_data_attribute_starts() {
+        let m = Matcher::parse("a[target^=\"_blank\"]").unwrap();
+        assert_eq!(
+            m.attribute.as_slice(),
+            &[(
+                LocalName::from("target"),
+                AttributeSpec::Starts("_blank".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_matcher_data_attribute_ends() {
+        let m = Matcher::parse("a[target$=\"_blank\"]").unwrap();
+        assert_eq!(
+            m.attribute.as_slice(),
+            &[(
+                LocalName::from("target"),
+                AttributeSpec::Ends("_blank".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn test_matcher_data_attribute_contains() {
+        let m = Matcher::parse("a[target*=\"_blank\"]").unwrap();
+        assert_eq!(
+            m.attribute.as_slice(),
+            &[(
+                LocalName::from("target"),
+                AttributeSpec::Contains("_blank".to_string())
+            )]
+        );
+    }
+
+    //}}}
+
+    // // Selector tests{{{
+    // #[test]
+    // fn test_selector_parse_simple() {
+    //     let sel = Selector::from("a");
+    //     assert_eq!(
+    //         sel,
+    //         Selector {
+    //             css: "a".to_string(),
+    //             next: None
+    //         }
+    //     );
+    // }
+
+    // #[test]
+    // fn test_selector_parse_simple_with_class() {
+    //     let sel = Selector::from("a.link");
+    //     assert_eq!(
+    //         sel,
+    //         Selector {
+    //             css: "a.link".to_string(),
+    //             next: None
+    //         }
+    //     );
+    // } //}}}
+
+    // Element tests{{{
+    #[test]
+    fn test_el_tag() {
+        let doc = Document::from("<a class='link'>hi there</a>");
+        let sel = doc.select("a");
+        let el = sel.first().unwrap();
+        assert_eq!(el.tag(), Some("a".to_string()));
+    }
+
+    #[test]
+    fn test_el_attr_class() {
+        let doc = Document::from("<a class='link'>hi there</a>");
+        let sel = doc.select("a");
+        let el = sel.first().unwrap();
+        assert_eq!(el.attr("class"), Some("link".to_string()));
+    }
+
+    #[test]
+    fn test_el_attr_id() {
+        let doc = Document::from("<a class='link' id=linkilink>hi there</a>");
+        let sel = doc.select("a");
+        let el = sel.first().unwrap();
+        assert_eq!(el.attr("id"), Some("linkilink".to_string()));
+    }
+
+    #[test]
+    fn test_el_identity() {
+        use std::collections::HashSet;
+
+        let doc = Document::from("<div><a class='link'>one</a><a>two</a></div>");
+        let links = doc.select("a");
+        let by_class = doc.select("a.link");
+        assert!(links[0] == by_class[0]);
+        assert!(links[1] != by_class[0]);
+
+        let set: HashSet<_> = links.into_iter().chain(by_class).collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn test_select_first() {
+        let doc = Document::from(
+            "<div class='outer'>
+               <div class='inner'><span>one</span></div>
+               <span>two</span>
+             </div>
+             <p><span>three</span></p>",
+        );
+        assert_eq!(
+            doc.select_first("div").unwrap().attr("class").unwrap(),
+            "outer"
+        );
+        assert_eq!(doc.select_first("span").unwrap().text().unwrap(), "one");
+        assert_eq!(
+            doc.select_first("div > span").unwrap().text().unwrap(),
+            "one"
+        );
+        assert_eq!(doc.select_first("p span").unwrap().text().unwrap(), "three");
+        assert_eq!(doc.select_first("p >").unwrap().text().unwrap(), "three");
+        assert!(doc.select_first("a").is_none());
+
+        let el = doc.select_first("p").unwrap();
+        assert_eq!(el.select_first("span").unwrap().text().unwrap(), "three");
+        assert!(el.select_first("div").is_none());
+    }
+
+    #[test]
+    fn test_el_source_location() {
+        let doc = Document::from(
+            "<html>
+<body>
+  <div>
+    <span>one</span>
+    <a
+      href='/'>two</a>
+  </div>
+</body>
+</html>",
+        );
+        let el = doc.select_first("div").unwrap();
+        assert_eq!(
+            el.source_location(),
+            Some(SourceLocation {
+                line: 3,
+                column: Some(3)
+            })
+        );
+        let el = doc.select_first("span").unwrap();
+        assert_eq!(el.source_location().unwrap().line, 4);
+        assert_eq!(el.source_location().unwrap().column, Some(5));
+        let el = doc.select_first("a").unwrap();
+        assert_eq!(el.source_location().unwrap().line, 6);
+        assert_eq!(el.source_location().unwrap().column, Some(5));
+        assert_eq!(el.parent().unwrap().source_location().unwrap().line, 3);
+
+        // columns count characters and do not depend on how input is split
+        let html = "<p>Краб <b>x</b>\n\t<i>y</i></p>";
+        let mut sink = DocumentSink::new();
+        for chunk in html.as_bytes().chunks(1) {
+            sink.feed_bytes(chunk);
+        }
+        for doc in [Document::from(html), sink.finish()].iter() {
+            let column = |sel: &str| {
+                doc.select_first(sel)
+                    .unwrap()
+                    .source_location()
+                    .unwrap()
+                    .column
+            };
+            assert_eq!(column("p"), Some(1));
+            assert_eq!(column("b"), Some(9));
+            assert_eq!(column("i"), Some(2));
+        }
+
+        let el = Element::from(Arc::clone(&el.handle));
+        assert!(el.source_location().is_none());
+    }
+
+    #[test]
+    fn test_el_source_range() {
+        let html = "<!DOCTYPE html>
+<html><body>
+<div class='a>b'><p>one<p>two &amp; <B>three</B></div>
+<img src='x.png'><ul><li>four</ul>";
+        let doc = Document::from(html);
+        let range = |sel: &str| {
+            let el = doc.select_first(sel).unwrap();
+            &html[el.source_range().unwrap()]
+        };
+
+        assert_eq!(
+            range("div"),
+            "<div class='a>b'><p>one<p>two &amp; <B>three</B></div>"
+        );
+        assert_eq!(range("p"), "<p>one");
+        assert_eq!(range("p:nth-child(2)"), "<p>two &amp; <B>three</B>");
+        assert_eq!(range("b"), "<B>three</B>");
+        assert_eq!(range("img"), "<img src='x.png'>");
+        assert_eq!(range("ul"), "<ul><li>four</ul>");
+        assert_eq!(range("li"), "<li>four");
+        assert!(range("html").starts_with("<html><body>"));
+        assert!(range("html").ends_with("</ul>"));
+    }
+
+    #[test]
+    fn test_el_source_range_markup() {
+        let html = "<p>a</p> x > y <b>c</b><!-- <i> > --><i title='>'>d</i>\
+            <script>if (a<b && c>d) {}</script><s>e</s><textarea><u>f</u></textarea>";
+        let docs = vec![Document::from(html), {
+            let mut sink = DocumentSink::new();
+            for chunk in html.as_bytes().chunks(1) {
+                sink.feed_bytes(chunk);
+            }
+            sink.finish()
+        }];
+
+        for doc in docs {
+            let range = |sel: &str| {
+                let el = doc.select_first(sel).unwrap();
+                &html[el.source_range().unwrap()]
+            };
+            assert_eq!(range("p"), "<p>a</p>");
+            assert_eq!(range("b"), "<b>c</b>");
+            assert_eq!(range("i"), "<i title='>'>d</i>");
+            assert_eq!(range("script"), "<script>if (a<b && c>d) {}</script>");
+            assert_eq!(range("s"), "<s>e</s>");
+            assert_eq!(range("textarea"), "<textarea><u>f</u></textarea>");
+            assert!(doc.select_first("u").is_none());
+        }
+    }
+
+    #[test]
+    fn test_el_into_document() {
+        let doc = Document::from(
+            "<base href='https://example.com/'>
+             <div class='item'><a href='/one'>one</a><!--c--></div>
+             <div class='item'><a href='/two'>two</a></div>",
+        );
+        let el = doc.select_first("div.item").unwrap();
+        let fragment = el.clone().into_document();
+
+        let sel = fragment.select("a");
+        assert_eq!(sel.len(), 1);
+        assert_eq!(sel[0].text().unwrap(), "one");
+        assert_eq!(sel[0].abs_attr("href").unwrap(), "https://example.com/one");
+        assert_eq!(fragment.comments(), vec!["c"]);
+        assert_eq!(fragment.select("div.item > a").len(), 1);
+        assert!(sel[0] != doc.select_first("a").unwrap());
+        assert!(sel[0].parent().unwrap().parent().is_some());
+        assert!(el.parent().is_some());
+    }
+
+    #[test]
+    fn test_el_clone_subtree() {
+        let doc = Document::from(
+            "<base href='https://example.com/'>
+             <div id='main'><a href='/one' class='x'>one</a><template><b>t</b></template></div>",
+        );
+        let el = doc.select_first("div").unwrap();
+        let copy = el.clone_subtree();
+
+        assert!(copy.parent().is_none());
+        assert_eq!(copy.id().unwrap(), "main");
+        assert!(copy.source_range().is_none());
+
+        let link = copy.select_first("a").unwrap();
+        assert!(link.parent().unwrap() == copy);
+        assert_eq!(link.abs_attr("href").unwrap(), "https://example.com/one");
+
+        if let NodeData::Element { ref attrs, .. } = link.handle.data {
+            attrs.borrow_mut().clear();
+        }
+        if let NodeData::Text { ref contents } = link.handle.children.borrow()[0].data {
+            contents.borrow_mut().push_slice(" changed");
+        }
+        assert!(link.attr("href").is_none());
+        assert_eq!(link.text().unwrap(), "one changed");
+
+        let original = doc.select_first("a").unwrap();
+        assert_eq!(original.attr("href").unwrap(), "/one");
+        assert_eq!(original.text().unwrap(), "one");
+    }
+
+    #[test]
+    fn test_comments() {
+        let doc = Document::from(
+            "<!--before-->
+             <div>
+               <!--one-->
+               <span>text<!--two--></span>
+             </div>
+             <p><!--three--></p>",
+        );
+        assert_eq!(doc.comments(), vec!["before", "one", "two", "three"]);
+
+        let el = doc.select_first("div").unwrap();
+        assert_eq!(el.comments(), vec!["one", "two"]);
+
+        let el = doc.select_first("span").unwrap();
+        assert_eq!(el.text().unwrap(), "text");
+    }
+
+    #[test]
+    fn test_el_attrs() {
+        let doc = Document::from(
+            "<div id=main data-x=\"a&amp;b\" ID='dup' data-x = 'c &lt; d' hidden/>
+             <p class='one' title=\"x > y\">text</p>
+             <span></span>",
+        );
+        let el = doc.select_first("div").unwrap();
+        assert_eq!(el.attr("id").unwrap(), "main");
+        assert_eq!(
+            el.attrs(),
+            vec![
+                ("id".to_string(), "main".to_string()),
+                ("data-x".to_string(), "a&b".to_string()),
+                ("id".to_string(), "dup".to_string()),
+                ("data-x".to_string(), "c < d".to_string()),
+                ("hidden".to_string(), "".to_string()),
+            ]
+        );
+
+        let el = doc.select_first("p").unwrap();
+        assert_eq!(
+            el.attrs(),
+            vec![
+                ("class".to_string(), "one".to_string()),
+                ("title".to_string(), "x > y".to_string()),
+            ]
+        );
+        assert!(doc.select_first("span").unwrap().attrs().is_empty());
+    }
+
+    #[test]
+    fn test_el_srcset() {
+        let doc = Document::from(
+            "<img id='a' srcset=' one.png 1x ,two.png 2x,three.png'>
+             <img id='b' srcset='data:image/png;base64,AAA= 100w, /x,y.png 300w, z.png 200w'>
+             <img id='c' srcset='icon.svg, icon-hd.svg 2x, huge.svg 1500w 1000h'>
+             <img id='d' srcset='a.png func(1, 2) 1x,,b.png,'>
+             <img id='e' src='plain.png'>",
+        );
+
+        let el = doc.select_first("img#a").unwrap();
+        assert_eq!(
+            el.srcset(),
+            vec![
+                ("one.png".to_string(), Some("1x".to_string())),
+                ("two.png".to_string(), Some("2x".to_string())),
+                ("three.png".to_string(), None),
+            ]
+        );
+        assert_eq!(el.srcset_largest().unwrap(), "two.png");
+
+        let el = doc.select_first("img#b").unwrap();
+        let urls: Vec<_> = el.srcset().into_iter().map(|(url, _)| url).collect();
+        assert_eq!(
+            urls,
+            vec!["data:image/png;base64,AAA=", "/x,y.png", "z.png"]
+        );
+        assert_eq!(el.srcset_largest().unwrap(), "/x,y.png");
+
+        let el = doc.select_first("img#c").unwrap();
+        assert_eq!(el.srcset()[2].1.as_deref(), Some("1500w 1000h"));
+        assert_eq!(el.srcset_largest().unwrap(), "huge.svg");
+
+        let el = doc.select_first("img#d").unwrap();
+        assert_eq!(
+            el.srcset(),
+            vec![
+                ("a.png".to_string(), Some("func(1, 2) 1x".to_string())),
+                ("b.png".to_string(), None),
+            ]
+        );
+        assert_eq!(el.srcset_largest().unwrap(), "a.png");
+
+        let el = doc.select_first("img#e").unwrap();
+        assert!(el.srcset().is_empty());
+        assert!(el.srcset_largest().is_none());
+    }
+
+    #[test]
+    fn test_el_value() {
+        let doc = Document::from(
+            "<form>
+               <input id='text' name='q'>
+               <input id='check' type='CheckBox' checked>
+               <input id='radio' type='radio' value='r1'>
+               <textarea id='area'>
+line one
+line &amp; two</textarea>
+               <select id='single'><option value='a'>A</option><option disabled>B</option></select>
+               <select id='disabled'><option disabled>A</option><option>  B
+                 b </option></select>
+               <select id='last'><option selected>A</option><option selected>B</option></select>
+               <select id='multi' multiple><option>A</option><option selected>B</option><option selected>C</option></select>
+               <select id='multi-none' multiple><option>A</option></select>
+               <select id='list' size='3'><optgroup><option>A</option></optgroup></select>
+               <select id='group'><optgroup><option>A</option><option value='b' selected>B</option></optgroup></select>
+               <div id='other' value='x'></div>
+             </form>",
+        );
+        let value = |sel: &str| doc.select_first(sel).unwrap().value();
+
+        assert_eq!(value("#text").unwrap(), "");
+        assert_eq!(value("#check").unwrap(), "on");
+        assert_eq!(value("#radio").unwrap(), "r1");
+        assert_eq!(value("#area").unwrap(), "line one\nline & two");
+        assert_eq!(value("#single").unwrap(), "a");
+        assert_eq!(value("#disabled").unwrap(), "B b");
+        assert_eq!(value("#last").unwrap(), "B");
+        assert_eq!(value("#multi").unwrap(), "B");
+        assert!(value("#multi-none").is_none());
+        assert!(value("#list").is_none());
+        assert_eq!(value("#group").unwrap(), "b");
+        assert!(value("#other").is_none());
+    }
+
+    #[test]
+    fn test_el_options() {
+        let doc = Document::from(
+            "<select id='single'>
+               <option value='a' selected>A</option>
+               <optgroup label='group'><option label=''>  B  b </option></optgroup>
+               <option value='c' selected label='Cee'>C</option>
+             </select>
+             <select id='default'><option disabled>x</option><option>y</option></select>
+             <select id='multi' multiple><option>x</option><option>y</option></select>
+             <div><option>z</option></div>",
+        );
+
+        let el = doc.select_first("select#single").unwrap();
+        assert_eq!(
+            el.options(),
+            vec![
+                ("a".to_string(), "A".to_string(), false),
+                ("B b".to_string(), "B b".to_string(), false),
+                ("c".to_string(), "Cee".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            el.selected_options(),
+            vec![("c".to_string(), "Cee".to_string(), true)]
+        );
+
+        let el = doc.select_first("select#default").unwrap();
+        assert_eq!(
+            el.selected_options(),
+            vec![("y".to_string(), "y".to_string(), true)]
+        );
+
+        let el = doc.select_first("select#multi").unwrap();
+        assert_eq!(el.options().len(), 2);
+        assert!(el.selected_options().is_empty());
+
+        assert!(doc.select_first("div").unwrap().options().is_empty());
+    }
+
+    #[test]
+    fn test_el_nth_ancestor() {
+        let doc = Document::from("<div><ul><li><a>x</a></li></ul></div>");
+        let el = doc.select_first("a").unwrap();
+
+        assert!(el.nth_ancestor(0).unwrap() == el);
+        assert!(el.nth_ancestor(1).unwrap() == el.parent().unwrap());
+        assert_eq!(el.nth_ancestor(3).unwrap().tag().unwrap(), "div");
+        // div, body, html and the document node itself
+        assert!(el.nth_ancestor(6).unwrap().tag().is_none());
+        assert!(el.nth_ancestor(7).is_none());
+    }
+
+    #[test]
+    fn test_el_sibling_until() {
+        let doc = Document::from(
+            "<div class='doc'>
+               <h2 id='one'>One</h2>text<p>a</p><!--c--><p>b</p>
+               <h2 id='two'>Two</h2><p class='x'>c</p><div><span>d</span></div>
+             </div>",
+        );
+        let texts = |els: Vec<Element>| -> Vec<String> {
+            els.iter()
+                .map(|el| el.descendant_text_nodes().concat())
+                .collect()
+        };
+
+        let one = doc.select_first("h2#one").unwrap();
+        let two = doc.select_first("h2#two").unwrap();
+        assert_eq!(texts(one.next_until("h2")), vec!["a", "b"]);
+        assert_eq!(texts(one.next_until("p.x")), vec!["a", "b", "Two"]);
+        assert_eq!(
+            texts(one.next_until(".doc > div")),
+            vec!["a", "b", "Two", "c"]
+        );
+        assert_eq!(texts(two.next_until("h2")), vec!["c", "d"]);
+        assert_eq!(texts(two.prev_until("h2")), vec!["b", "a"]);
+        assert_eq!(texts(two.prev_until(".doc h2#one")), vec!["b", "a"]);
+        assert!(one.prev_until("h2").is_empty());
+        assert!(one.next_until("p").is_empty());
+    }
+
+    #[test]
+    fn test_el_parents_until() {
+        let doc = Document::from(
+            "<article class='post'><section><div><p><a>link</a></p></div></section></article>",
+        );
+        let tags =
+            |els: Vec<Element>| -> Vec<String> { els.iter().map(|el| el.tag().unwrap()).collect() };
+        let el = doc.select_first("a").unwrap();
+
+        assert_eq!(
+            tags(el.parents_until("article")),
+            vec!["p", "div", "section"]
+        );
+        assert_eq!(tags(el.parents_until("section > div")), vec!["p"]);
+        assert_eq!(tags(el.parents_until("p")), Vec::<String>::new());
+        assert_eq!(
+            tags(el.parents_until("table")),
+            vec!["p", "div", "section", "article", "body", "html"]
+        );
+    }
+
+    #[test]
+    fn test_elements_between() {
+        let doc = Document::from(
+            "<h2 id='one'>One <small>x</small></h2>
+             <p>a</p>
+             <div><p>b</p><h2 id='two'>Two</h2><p>c</p></div>
+             <h2 id='three'>Three</h2>",
+        );
+        let tags =
+            |els: Vec<Element>| -> Vec<String> { els.iter().map(|el| el.tag().unwrap()).collect() };
+        let one = doc.select_first("h2#one").unwrap();
+        let two = doc.select_first("h2#two").unwrap();
+        let three = doc.select_first("h2#three").unwrap();
+
+        assert_eq!(tags(doc.elements_between(&one, &two)), vec!["p", "p"]);
+        assert_eq!(tags(doc.elements_between(&two, &three)), vec!["p"]);
+        assert_eq!(
+            tags(doc.elements_between(&one, &three)),
+            vec!["p", "div", "p", "h2", "p"]
+        );
+        assert!(doc.elements_between(&three, &one).is_empty());
+        assert!(doc.elements_between(&one, &one).is_empty());
+    }
+
+    #[test]
+    fn test_el_adjacent_text() {
+        let doc = Document::from(
+            "<ul>
+               <li><b>Price:</b> $10 <i>each</i></li>
+               <li><b>Size:</b><!--c--> XL</li>
+             </ul>",
+        );
+        let labels = doc.select("b");
+
+        assert_eq!(labels[0].next_text().unwrap(), " $10 ");
+        assert!(labels[0].prev_text().is_none());
+        assert_eq!(doc.select_first("i").unwrap().prev_text().unwrap(), " $10 ");
+        assert!(doc.select_first("i").unwrap().next_text().is_none());
+        assert!(labels[1].next_text().is_none());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        /// Reader returning input in tiny pieces to split tags and utf-8 sequences
+        struct Trickle<'a>(&'a [u8]);
+
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = self.0.len().min(buf.len()).min(3);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+
+        let html = "<base href='https://example.com/'>
+                    <div id='main' data-x='a > b'>
+                      <a href='/crab' class='x' class='y'>Краб 🦀</a>
+                      <p>one<p>two
+                    </div>";
+        let doc = Document::from_reader(Trickle(html.as_bytes())).unwrap();
+
+        let el = doc.select_first("a").unwrap();
+        assert_eq!(el.text().unwrap(), "Краб 🦀");
+        assert_eq!(el.abs_attr("href").unwrap(), "https://example.com/crab");
+        assert_eq!(el.attrs().len(), 3);
+        assert_eq!(el.source_location().unwrap().line, 3);
+        assert_eq!(
+            &html[el.source_range().unwrap()],
+            "<a href='/crab' class='x' class='y'>Краб 🦀</a>"
+        );
+
+        let el = doc.select_first("div").unwrap();
+        assert_eq!(el.attr("data-x").unwrap(), "a > b");
+        assert!(html[el.source_range().unwrap()].ends_with("</div>"));
+        assert_eq!(doc.select("p").len(), 2);
+
+        let doc = Document::from_reader(&b"<p>a\xffb\xe2\x82</p>"[..]).unwrap();
+        assert_eq!(
+            doc.select_first("p").unwrap().text().unwrap(),
+            "a\u{fffd}b\u{fffd}"
+        );
+
+        let doc = Document::from_reader(&b"<p>a\xe2\x82"[..]).unwrap();
+        assert_eq!(doc.select_first("p").unwrap().text().unwrap(), "a\u{fffd}");
+    }
+
+    #[test]
+    fn test_feed_long_markup() {
+        // every chunk used to rescan the input waiting for `>`
+        let text = "crab ".repeat(200_000);
+        let pages = [
+            format!("<p>{}</p>", text),
+            format!("<p title='{}'>x</p>", text),
+            format!("<!--{}--><p>x</p>", text),
+        ];
+
+        for html in pages.iter() {
+            let mut sink = DocumentSink::new();
+            for chunk in html.as_bytes().chunks(64) {
+                sink.feed_bytes(chunk);
+            }
+            let doc = sink.finish();
+            let p = doc.select_first("p").unwrap();
+            assert_eq!(
+                &html[p.source_range().unwrap()],
+                &html[html.find("<p").unwrap()..]
+            );
+        }
+        let doc = Document::from_reader(pages[0].as_bytes()).unwrap();
+        assert_eq!(doc.select_first("p").unwrap().text().unwrap(), text);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_from_async_reader() {
+        let html = "<div><a href='/one'>Краб</a><p>one<p>two</div>";
+        let (client, mut server) = tokio::io::duplex(4);
+
+        let write = async move {
+            use tokio::io::AsyncWriteExt;
+
+            for chunk in html.as_bytes().chunks(5) {
+                server.write_all(chunk).await.unwrap();
+            }
+        };
+        let (doc, _) = tokio::join!(Document::from_async_reader(client), write);
+        let doc = doc.unwrap();
+
+        let el = doc.select_first("a").unwrap();
+        assert_eq!(el.text().unwrap(), "Краб");
+        assert_eq!(&html[el.source_range().unwrap()], "<a href='/one'>Краб</a>");
+        assert_eq!(doc.select("p").len(), 2);
+    }
+
+    #[test]
+    fn test_parse_fragment() {
+        let html = "<tr><td class='x'>one</td><td>two</td></tr>";
+        let doc = Document::parse_fragment(html, "TBODY");
+        assert!(doc.select("table").is_empty());
+        assert!(doc.select("tbody").is_empty());
+        assert!(doc.select("html").is_empty());
+        assert_eq!(doc.select("tr > td").len(), 2);
+
+        let el = doc.select_first("td.x").unwrap();
+        assert_eq!(el.css_path(), "tr > td:nth-child(1)");
+        assert_eq!(&html[el.source_range().unwrap()], "<td class='x'>one</td>");
+        assert!(el.parent().unwrap().parent().unwrap().tag().is_none());
+
+        let doc = Document::parse_fragment("<li>a</li>text<li>b", "ul");
+        assert_eq!(doc.select("li").len(), 2);
+        assert_eq!(doc.select_first("li").unwrap().next_text().unwrap(), "text");
+
+        let doc = Document::from("<td>x</td>");
+        assert!(doc.select("td").is_empty());
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_from_bytes() {
+        let text = |doc: Document| doc.select_first("p").unwrap().text().unwrap();
+
+        let html =
+            b"<html><head><meta http-equiv=Content-Type content='text/html; charset=Shift_JIS'>
+                     </head><p>\x83\x4e\x83\x89\x83\x75</p>";
+        assert_eq!(text(Document::from_bytes(html)), "クラブ");
+
+        let html = b"<meta charset=\"windows-1251\"/><p>\xea\xf0\xe0\xe1</p>";
+        assert_eq!(text(Document::from_bytes(html)), "краб");
+
+        let html = b"<meta charset='utf-16'><p>\xd0\xba\xd1\x80\xd0\xb0\xd0\xb1</p>";
+        assert_eq!(text(Document::from_bytes(html)), "краб");
+
+        let html = "<p>краб</p>".as_bytes();
+        assert_eq!(text(Document::from_bytes(html)), "краб");
+
+        let html = b"<p>na\xefve</p>";
+        assert_eq!(text(Document::from_bytes(html)), "naïve");
+
+        let html = b"\xef\xbb\xbf<meta charset='windows-1251'><p>\xd0\xba</p>";
+        assert_eq!(text(Document::from_bytes(html)), "к");
+
+        let html = b"<!-- <meta charset='windows-1251'> --><p>na\xefve</p>";
+        assert_eq!(text(Document::from_bytes(html)), "naïve");
+
+        let html = b"<div title='<meta charset=windows-1251>'></div><p>na\xefve</p>";
+        assert_eq!(text(Document::from_bytes(html)), "naïve");
+
+        let html = b"<meta name='x' content='text/html; charset=windows-1251'><p>na\xefve</p>";
+        assert_eq!(text(Document::from_bytes(html)), "naïve");
+
+        let html = b"<META CONTENT=\"text/html;charset='windows-1251'\"
+                     HTTP-EQUIV=\"content-type\"><p>\xea\xf0\xe0\xe1</p>";
+        assert_eq!(text(Document::from_bytes(html)), "краб");
+
+        let html = b"<meta charset=bogus><meta charset=windows-1251><p>\xea\xf0\xe0\xe1</p>";
+        assert_eq!(text(Document::from_bytes(html)), "краб");
+
+        let html = b"<p>\xea\xf0\xe0\xe1</p>";
+        let doc = Document::from_bytes_with_encoding(html, encoding_rs::WINDOWS_1251);
+        assert_eq!(text(doc), "краб");
+    }
+
+    #[test]
+    fn test_try_parse() {
+        let (doc, errors) = Document::try_parse(
+            "<!DOCTYPE html><html><body>
+               <div><p><span>one</div>
+               <a href='/' href='/x'>link</a>
+             </body></html>",
+        )
+        .unwrap();
+        assert_eq!(doc.select("p").len(), 1);
+        assert!(errors.len() >= 2);
+        assert!(errors.iter().all(|e| e.line == 2 || e.line == 3));
+        assert!(errors
+            .iter()
+            .any(|e| e.line == 3 && e.message.contains("attribute")));
+        assert!(errors[0].to_string().starts_with("line 2: "));
+
+        let (_, errors) =
+            Document::try_parse("<!DOCTYPE html><html><head></head><body><p>ok</p></body></html>")
+                .unwrap();
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_document_builder() {
+        let html = "<!DOCTYPE html><p>text</p><noscript><a href='/'>home</a></noscript>";
+
+        let doc = DocumentBuilder::new().parse(html);
+        assert!(doc.select("a").is_empty());
+        assert!(doc.doc.document.children.borrow().iter().all(is_element));
+
+        let builder = DocumentBuilder::new()
+            .scripting_enabled(false)
+            .drop_doctype(false);
+        let doc = builder.parse(html);
+        assert_eq!(doc.select("noscript > a").len(), 1);
+        assert!(matches!(
+            doc.doc.document.children.borrow()[0].data,
+            NodeData::Doctype { .. }
+        ));
+
+        let doc = builder.parse_reader(html.as_bytes()).unwrap();
+        assert_eq!(doc.select("noscript > a").len(), 1);
+        let doc = builder.parse_fragment("<noscript><b>x</b></noscript>", "div");
+        assert_eq!(doc.select("noscript > b").len(), 1);
+
+        let (_, errors) = DocumentBuilder::new().try_parse("<p></span>").unwrap();
+        let (_, exact_errors) = DocumentBuilder::new()
+            .exact_errors(true)
+            .try_parse("<p></span>")
+            .unwrap();
+        assert_eq!(errors.len(), exact_errors.len());
+        assert!(errors[0].message != exact_errors[0].message);
+    }
+
+    #[test]
+    fn test_html() {
+        let html = "<!DOCTYPE html><html><head><title>T</title></head><body>\
+                    <div id=\"a\" data-x=\"&quot;q&quot;\"><!--c--><p>one &lt; two</p>\
+                    <template><b>t</b></template><br><script>if (a < b) {}</script></div>\
+                    </body></html>";
+        let doc = DocumentBuilder::new().drop_doctype(false).parse(html);
+        assert_eq!(doc.html(), html);
+        assert_eq!(Document::from(html).html(), html["<!DOCTYPE html>".len()..]);
+
+        let doc = Document::from("");
+        assert_eq!(doc.html(), "<html><head></head><body></body></html>");
+    }
+
+    #[test]
+    fn test_document_accessors() {
+        let doc = Document::from(
+            "<!DOCTYPE html><title> One  Two </title><meta charset=utf-8><p>text</p>
+             <svg><title>svg</title></svg>",
+        );
+        assert_eq!(doc.root().unwrap().tag().unwrap(), "html");
+        assert_eq!(doc.head().unwrap().children().len(), 2);
+        assert_eq!(doc.body().unwrap().children()[0].text().unwrap(), "text");
+        assert_eq!(doc.title().unwrap(), "One Two");
+
+        let doc = Document::from("<frameset><frame src='a.html'></frameset>");
+        assert_eq!(doc.body().unwrap().tag().unwrap(), "frameset");
+        assert!(doc.title().is_none());
+
+        let doc = Document::parse_fragment("<li>a</li><li>b</li>", "ul");
+        assert_eq!(doc.root().unwrap().tag().unwrap(), "li");
+        assert!(doc.head().is_none());
+        assert!(doc.body().is_none());
+    }
+
+    #[test]
+    fn test_quirks_mode_and_doctype() {
+        let builder = DocumentBuilder::new().drop_doctype(false);
+
+        let doc = builder.parse("<!doctype HTML><p>x</p>");
+        assert_eq!(doc.quirks_mode(), QuirksMode::NoQuirks);
+        assert_eq!(
+            doc.doctype().unwrap(),
+            Doctype {
+                name: "html".to_string(),
+                public_id: "".to_string(),
+                system_id: "".to_string(),
+            }
+        );
+
+        let doc = builder.parse(
+            "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\"
+             \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\"><p>x</p>",
+        );
+        assert_eq!(doc.quirks_mode(), QuirksMode::LimitedQuirks);
+        assert_eq!(
+            doc.doctype().unwrap().system_id,
+            "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd"
+        );
+
+        let doc = builder.parse("<p>x</p>");
+        assert_eq!(doc.quirks_mode(), QuirksMode::Quirks);
+        assert!(doc.doctype().is_none());
+
+        let doc = Document::from("<!DOCTYPE html><p>x</p>");
+        assert_eq!(doc.quirks_mode(), QuirksMode::NoQuirks);
+        assert!(doc.doctype().is_none());
+    }
+
+    #[test]
+    fn test_base_url() {
+        let html = "<head><base target='_blank'><base href='../img/'><base href='/other/'></head>
+                    <a href='x.png'>x</a><a href='//cdn.example.com/y.png'>y</a>";
+        let doc = Document::from_with_base(html, "https://example.com/a/b/page.html").unwrap();
+        assert_eq!(doc.base_url().unwrap(), "https://example.com/a/img/");
+
+        let links = doc.select("a");
+        assert_eq!(
+            links[0].abs_attr("href").unwrap(),
+            "https://example.com/a/img/x.png"
+        );
+        assert_eq!(
+            links[1].abs_attr("href").unwrap(),
+            "https://cdn.example.com/y.png"
+        );
+
+        let doc = Document::from_with_base("<a href='x'>x</a>", "https://example.com/a/").unwrap();
+        assert_eq!(doc.base_url().unwrap(), "https://example.com/a/");
+        assert_eq!(
+            doc.select_first("a").unwrap().abs_attr("href").unwrap(),
+            "https://example.com/a/x"
+        );
+
+        let doc = Document::from("<base href='../img/'><a href='x'>x</a>");
+        assert!(doc.base_url().is_none());
+        assert!(doc.select_first("a").unwrap().abs_attr("href").is_none());
+
+        assert!(Document::from_with_base("<p>x</p>", "not a url").is_err());
+    }
+
+    #[test]
+    fn test_document_clone() {
+        let html = "<!DOCTYPE html><base href='https://example.com/'>
+                    <a href='/x' href='/y'>one</a><template><b>t</b></template>";
+        let doc = DocumentBuilder::new().drop_doctype(false).parse(html);
+        let copy = doc.clone();
+
+        assert_eq!(copy.html(), doc.html());
+        assert_eq!(copy.quirks_mode(), doc.quirks_mode());
+        assert_eq!(copy.base_url(), doc.base_url());
+
+        let link = copy.select_first("a").unwrap();
+        assert!(link != doc.select_first("a").unwrap());
+        assert_eq!(link.abs_attr("href").unwrap(), "https://example.com/x");
+        assert_eq!(link.attrs().len(), 2);
+        assert_eq!(link.source_location().unwrap().line, 2);
+        assert_eq!(
+            &html[link.source_range().unwrap()],
+            "<a href='/x' href='/y'>one</a>"
+        );
+
+        if let NodeData::Text { ref contents } = link.handle.children.borrow()[0].data {
+            contents.borrow_mut().push_slice(" changed");
+        }
+        assert_eq!(link.text().unwrap(), "one changed");
+        assert_eq!(doc.select_first("a").unwrap().text().unwrap(), "one");
+    }
+
+    #[test]
+    fn test_document_sink() {
+        let html = "<div id='main' title='a > b'>\n<p>Краб</p><!-- c --><a href='/x'>x</a></div>";
+
+        let mut sink = DocumentSink::new();
+        for chunk in html.as_bytes().chunks(3) {
+            sink.feed_bytes(chunk);
+        }
+        let doc = sink.finish();
+        assert_eq!(doc.html(), Document::from(html).html());
+
+        let el = doc.select_first("a").unwrap();
+        assert_eq!(el.source_location().unwrap().line, 2);
+        assert_eq!(&html[el.source_range().unwrap()], "<a href='/x'>x</a>");
+
+        let mut sink = DocumentBuilder::new()
+            .base_url("https://example.com/")
+            .unwrap()
+            .sink();
+        sink.feed("<a href='/x'");
+        sink.feed(">x</a>");
+        let doc = sink.finish();
+        assert_eq!(
+            doc.select_first("a").unwrap().abs_attr("href").unwrap(),
+            "https://example.com/x"
+        );
+    }
+
+    #[cfg(feature = "binary")]
+    #[test]
+    fn test_binary() {
+        let html = "<!DOCTYPE html><html><head><base href='/docs/'></head><body>\
+            <!-- c --><p a=1 a=2 id='x'>hi &amp; bye</p><template><b>t</b></template>\
+            <svg><use xlink:href='#i'/></svg></body></html>";
+        let doc = DocumentBuilder::new()
+            .drop_doctype(false)
+            .base_url("https://example.com/")
+            .unwrap()
+            .parse(html);
+        let bytes = doc.to_binary();
+        let restored = Document::from_binary(&bytes).unwrap();
+
+        assert_eq!(restored.html(), doc.html());
+        assert_eq!(restored.doctype(), doc.doctype());
+        assert_eq!(restored.quirks_mode(), doc.quirks_mode());
+        assert_eq!(restored.base_url().unwrap(), "https://example.com/docs/");
+        assert_eq!(restored.comments(), vec![" c "]);
+
+        let p = restored.select_first("p").unwrap();
+        assert_eq!(p.attrs(), doc.select_first("p").unwrap().attrs());
+        assert_eq!(
+            p.source_range(),
+            doc.select_first("p").unwrap().source_range()
+        );
+        assert_eq!(
+            p.source_location(),
+            doc.select_first("p").unwrap().source_location()
+        );
+        let template = restored.select_first("template").unwrap();
+        assert_eq!(
+            template.source_range(),
+            doc.select_first("template").unwrap().source_range()
+        );
+        let used = restored.select_first("use").unwrap();
+        assert_eq!(used.attr("href").unwrap(), "#i");
+
+        assert_eq!(restored.to_binary(), bytes);
+        for len in 0..bytes.len() {
+            assert!(Document::from_binary(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Document::from_binary(&trailing).is_err());
+    }
+
+    #[test]
+    fn test_deeply_nested_document() {
+        // small stack makes any recursion over the tree overflow
+        let test = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(|| {
+                let depth = 5_000;
+                let html = format!(
+                    "{}<p>deep<!-- c --></p>{}",
+                    "<div>".repeat(depth),
+                    "</div>".repeat(depth)
+                );
+                let doc = Document::from(html.as_str());
+
+                let p = doc.select_first("p").unwrap();
+                assert_eq!(&html[p.source_range().unwrap()], "<p>deep<!-- c --></p>");
+                assert_eq!(doc.select("div > p").len(), 1);
+                assert_eq!(doc.select("body div > p").len(), 1);
+                assert_eq!(doc.select_count("div p"), 1);
+                assert_eq!(doc.select("div").len(), depth);
+                assert_eq!(doc.comments(), vec![" c "]);
+
+                let el = doc.select_first("div").unwrap();
+                assert_eq!(el.depth(), depth);
+                assert_eq!(el.descendant_count(), depth);
+                assert_eq!(el.source_range().unwrap(), 0..html.len());
+
+                let copy = doc.clone();
+                assert_eq!(copy.html(), doc.html());
+                assert_eq!(doc.normalize().html(), doc.html());
+                assert!(doc.diff(&copy).is_empty());
+                assert_eq!(el.clone_subtree().descendant_count(), depth);
+            });
+
+        test.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn test_normalize() {
+        let doc = Document::from(
+            "<div b='2' a='1'>\n  <pre>  keep\n  this </pre> <!-- c -->  <span> x \t y </span>\n</div>\
+             <template><i  z='1'   y='2'>  t  </i></template><svg viewBox='0 0 1 1'><foreignObject/></svg>",
+        );
+        let normalized = doc.normalize();
+
+        assert_eq!(
+            normalized.html(),
+            "<html><head></head><body><div a=\"1\" b=\"2\"><pre>  keep\n  this </pre><!-- c -->\
+             <span> x y </span></div><template><i y=\"2\" z=\"1\"> t </i></template>\
+             <svg viewbox=\"0 0 1 1\"><foreignobject></foreignobject></svg></body></html>"
+        );
+        assert_eq!(normalized.html(), normalized.normalize().html());
+        assert_eq!(doc.select("pre").len(), 1);
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = Document::from(
+            "<div id='a'><p>one</p><p>two</p></div><div id='b'><span title='x'>s</span></div>",
+        );
+        assert!(old.diff(&old.clone()).is_empty());
+
+        // whitespace only changes of text are ignored
+        let new = Document::from(
+            "<div id='a'><p> one </p><p>two</p></div>\n<div id='b'><span title='x'>s</span></div>",
+        );
+        assert!(old.diff(&new).is_empty());
+
+        let new = Document::from(
+            "<div id='c'></div><div id='a'><p>one</p><p>2</p><p>three</p></div><div id='b'><span title='y'>s</span></div>",
+        );
+        assert_eq!(
+            old.diff(&new),
+            vec![
+                Change::Added("html > body > div#c:nth-child(1)".to_string()),
+                Change::Changed("html > body > div#a:nth-child(2) > p:nth-child(2)".to_string()),
+                Change::Added("html > body > div#a:nth-child(2) > p:nth-child(3)".to_string()),
+                Change::Changed("html > body > div#b:nth-child(3) > span".to_string()),
+            ]
+        );
+
+        let new = Document::from("<div id='b'><span title='x'>s</span></div>");
+        assert_eq!(
+            old.diff(&new),
+            vec![Change::Removed(
+                "html > body > div#a:nth-child(1)".to_string()
+            )]
+        );
+
+        // long lists of siblings differing at both ends
+        let items = |ids: Vec<String>| {
+            let items: String = ids
+                .iter()
+                .map(|id| format!("<li id='{}'></li>", id))
+                .collect();
+            Document::from(format!("<ul>{}</ul>", items).as_str())
+        };
+        let ids: Vec<_> = (0..20_000).map(|i| i.to_string()).collect();
+        let old = items(ids.clone());
+        let mut changed = ids.clone();
+        changed.remove(1);
+        changed.insert(19_990, "x".to_string());
+        assert_eq!(
+            old.diff(&items(changed)),
+            vec![
+                Change::Removed("html > body > ul > li#1:nth-child(2)".to_string()),
+                Change::Added("html > body > ul > li#x:nth-child(19991)".to_string()),
+            ]
+        );
+
+        // too different lists are reported as replaced
+        let old = items(ids[..3000].to_vec());
+        let new = items(ids[..3000].iter().map(|id| format!("n{}", id)).collect());
+        let changes = old.diff(&new);
+        assert_eq!(changes.len(), 6000);
+        assert!(changes[..3000]
+            .iter()
+            .all(|change| matches!(change, Change::Removed(_))));
+        assert_eq!(
+            changes[3000],
+            Change::Added("html > body > ul > li#n0:nth-child(1)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_select_map() {
+        let doc = Document::from(
+            "<div id='1'><div id='2'><span id='3'></span></div><p id='4'><span id='5'></span></p></div>",
+        );
+        let selectors = [
+            ("divs", "div"),
+            ("spans", "div span"),
+            ("direct", "div > span"),
+            ("nested", "div div > span"),
+            ("none", "ul"),
+        ];
+        let res = doc.select_map(&selectors);
+        let ids = |name: &str| -> Vec<String> {
+            res[name].iter().map(|el| el.attr("id").unwrap()).collect()
+        };
+
+        assert_eq!(res.len(), 5);
+        assert_eq!(ids("divs"), vec!["1", "2"]);
+        assert_eq!(ids("spans"), vec!["3", "5"]);
+        assert_eq!(ids("direct"), vec!["3"]);
+        assert_eq!(ids("nested"), vec!["3"]);
+        assert!(ids("none").is_empty());
+
+        for (name, selector) in selectors.iter() {
+            let mut expected: Vec<_> = doc
+                .select(selector)
+                .iter()
+                .map(|el| el.attr("id").unwrap())
+                .collect();
+            expected.sort();
+            expected.dedup();
+            assert_eq!(ids(name), expected, "{}", selector);
+        }
+    }
+
+    #[test]
+    fn test_template_contents() {
+        let html = "<div><template><p class='a'>one</p><p class='a'>two</p></template></div>\
+            <noscript><a href='/no-js'>link</a></noscript><p class='a'>three</p>";
+
+        let doc = Document::from(html);
+        assert_eq!(doc.select(".a").len(), 1);
+        let template = doc.select_first("template").unwrap();
+        let contents = template.template_contents().unwrap();
+        assert_eq!(contents.select(".a").len(), 2);
+        assert!(doc
+            .select_first("div")
+            .unwrap()
+            .template_contents()
+            .is_none());
+
+        let noscript = doc.select_first("noscript").unwrap();
+        assert!(noscript.select("a").is_empty());
+        let inner = noscript.noscript_contents().unwrap();
+        assert_eq!(inner.select("a")[0].attr("href").unwrap(), "/no-js");
+        assert!(template.noscript_contents().is_none());
+
+        let doc = DocumentBuilder::new()
+            .inline_templates(true)
+            .scripting_enabled(false)
+            .parse(html);
+        assert_eq!(doc.select("template > .a").len(), 2);
+        assert_eq!(doc.select("noscript > a").len(), 1);
+        let template = doc.select_first("template").unwrap();
+        assert!(template.template_contents().unwrap().children().is_empty());
+    }
+
+    #[test]
+    fn test_srcdoc_document() {
+        let html = r#"<iframe srcdoc="<a href='/inner'>x</a>"></iframe><iframe src="/x"></iframe>"#;
+        let doc = DocumentBuilder::new()
+            .base_url("https://example.com/page")
+            .unwrap()
+            .parse(html);
+
+        let iframes = doc.select("iframe");
+        assert!(iframes[1].srcdoc_document().is_none());
+        assert!(doc
+            .select_first("body")
+            .unwrap()
+            .srcdoc_document()
+            .is_none());
+
+        let inner = iframes[0].srcdoc_document().unwrap();
+        let link = inner.select_first("a").unwrap();
+        assert_eq!(link.abs_attr("href").unwrap(), "https://example.com/inner");
+        assert!(doc.select("a").is_empty());
+    }
+
+    #[test]
+    fn test_audit() {
+        let html = "<!DOCTYPE html><div id='x'><span>one</div>\n<b><i>two</b></i>\n\
+            <p id='x'><font>three</font><p id='y'>four<section>";
+        let warnings = Document::from(html).audit();
+
+        assert!(matches!(warnings[0], Warning::UnclosedTag { line: 1, .. }));
+        assert!(matches!(warnings[1], Warning::MisnestedTag { line: 2, .. }));
+        assert!(matches!(warnings[3], Warning::UnclosedTag { line: 3, .. }));
+        assert!(
+            warnings[4]
+                == Warning::DuplicateId {
+                    id: "x".to_string(),
+                    paths: vec![
+                        "html > body > div#x".to_string(),
+                        "html > body > p#x:nth-child(3)".to_string()
+                    ],
+                }
+        );
+        assert_eq!(
+            warnings[5].to_string(),
+            "obsolete tag <font>: html > body > p#x:nth-child(3) > font"
+        );
+        assert_eq!(warnings.len(), 6);
+
+        let (doc, errors) = Document::try_parse(html).unwrap();
+        assert!(!errors.is_empty());
+        assert_eq!(doc.audit().len(), warnings.len());
+        assert_eq!(doc.clone().audit(), doc.audit());
+        assert_eq!(doc.strip("font").unwrap().audit().len(), warnings.len() - 1);
+        assert!(Document::from("<!DOCTYPE html><p>ok<p>fine")
+            .audit()
+            .is_empty());
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_from_json() {
+        use serde_json::json;
+
+        let doc = Document::from_json(&json!([
+            {"tag": "h1", "children": ["Title"]},
+            {"tag": "div", "attrs": {"id": "main", "class": "a b"}, "children": [
+                "text ",
+                {"tag": "a", "attrs": {"href": "/x"}, "children": ["link"]},
+                {"tag": "br", "attrs": null},
+            ]},
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            doc.html(),
+            "<h1>Title</h1><div class=\"a b\" id=\"main\">text <a href=\"/x\">link</a><br></div>"
+        );
+        assert_eq!(doc.select("div#main.b > a")[0].attr("href").unwrap(), "/x");
+        assert!(doc.select_first("a").unwrap().source_location().is_none());
+
+        let err = Document::from_json(&json!({"tag": "div", "children": [
+            "ok", {"tag": "p", "attrs": {"id": 1}},
+        ]}))
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid json document: /children/1: expected string value of id"
+        );
+        let err = Document::from_json(&json!(1)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid json document: /: expected object or string"
+        );
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_from_json_names() {
+        use serde_json::json;
+
+        let err = Document::from_json(&json!({"tag": "img src=x onerror=alert(1)"}))
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidJson(_)));
+        let err = Document::from_json(&json!({"tag": "a", "attrs": {"x\"><script": "y"}}))
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidJson(_)));
+        assert!(Document::from_json(&json!({"tag": "p/"})).is_err());
+
+        let doc = Document::from_json(&json!({"tag": "DIV", "attrs": {"Class": "x"}})).unwrap();
+        assert_eq!(doc.html(), "<div class=\"x\"></div>");
+        assert_eq!(doc.select("div").len(), 1);
+        assert_eq!(doc.select(".x").len(), 1);
+    }
+
+    #[test]
+    fn test_from_fragments() {
+        let pages = [
+            "<tr><td>ignored</td></tr><div class='item'><a href='/1'>one</a></div>",
+            "",
+            "<div class='item'>\n<a href='/2'>two</a></div><p>end",
+        ];
+        let doc = Document::from_fragments(&pages);
+
+        assert_eq!(
+            doc.html(),
+            "ignored<div class=\"item\"><a href=\"/1\">one</a></div>\
+             <div class=\"item\">\n<a href=\"/2\">two</a></div><p>end</p>"
+        );
+        let links = doc.select(".item > a");
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[1].source_location().unwrap().line, 2);
+        assert_eq!(
+            &pages[2][links[1].source_range().unwrap()],
+            "<a href='/2'>two</a>"
+        );
+        assert!(Document::from_fragments(&[]).html().is_empty());
+    }
+
+    #[test]
+    fn test_set_remove_attr() {
+        let doc = Document::from("<a href='/a' href='/b' id='x'>one</a><template>t</template>");
+        let a = doc.select_first("a").unwrap();
+        assert_eq!(a.attrs().len(), 3);
+
+        a.set_attr("id", "y");
+        assert_eq!(doc.select("#y").len(), 1);
+        assert!(doc.select("#x").is_empty());
+        assert_eq!(
+            a.attrs(),
+            vec![
+                ("href".to_string(), "/a".to_string()),
+                ("id".to_string(), "y".to_string())
+            ]
+        );
+
+        a.set_attr("title", "t");
+        assert_eq!(a.remove_attr("href").unwrap(), "/a");
+        assert_eq!(a.into_document().html(), r#"<a id="y" title="t">one</a>"#);
+
+        let template = doc.select_first("template").unwrap();
+        let contents = template.template_contents().unwrap();
+        assert!(contents.remove_attr("id").is_none());
+        contents.set_attr("id", "z");
+        assert!(contents.attr("id").is_none());
+    }
+
+    #[test]
+    fn test_class_manipulation() {
+        let doc = Document::from("<p>one</p><p class=' a\tb  a '>two</p>");
+        let p = doc.select("p");
+
+        p[0].remove_class("a");
+        assert!(p[0].attr("class").is_none());
+        p[0].add_class("  x y ");
+        assert_eq!(p[0].attr("class").unwrap(), "x y");
+        assert_eq!(doc.select("p.x.y").len(), 1);
+
+        p[1].add_class("b c");
+        assert_eq!(p[1].attr("class").unwrap(), "a b c");
+        p[1].remove_class("c a");
+        assert_eq!(p[1].attr("class").unwrap(), "b");
+        assert!(!p[1].toggle_class("b"));
+        assert_eq!(p[1].attr("class").unwrap(), "");
+        assert!(p[1].toggle_class("b"));
+        assert!(p[1].has_class("b"));
+        assert!(!p[1].toggle_class("b c"));
+        assert_eq!(p[1].attr("class").unwrap(), "c");
+        assert!(p[1].toggle_class(" d  e d "));
+        assert_eq!(p[1].attr("class").unwrap(), "c d e");
+        assert!(!p[1].toggle_class("  "));
+        assert_eq!(p[1].attr("class").unwrap(), "c d e");
+
+        let doc = Document::from("<template></template>");
+        let contents = doc.select_first("template").unwrap().template_contents();
+        assert!(!contents.unwrap().toggle_class("a"));
+    }
+
+    #[test]
+    fn test_remove() {
+        let doc = Document::from("<ul><li id='a'>one</li>text<li id='b'>two <b>2</b></li></ul>");
+        let b = doc.select_first("#b").unwrap();
+        b.remove();
+
+        assert!(b.parent().is_none());
+        assert!(doc.select("#b").is_empty());
+        assert!(doc.select("b").is_empty());
+        assert_eq!(b.select("b")[0].text().unwrap(), "2");
+        assert_eq!(doc.select_first("ul").unwrap().text().unwrap(), "text");
+
+        // removing detached element does nothing
+        b.remove();
+        let a = doc.select_first("#a").unwrap();
+        a.remove();
+        assert!(doc.select("li").is_empty());
+        assert_eq!(a.source_location().unwrap().line, 1);
+    }
+
+    #[test]
+    fn test_replace_with() {
+        let doc =
+            Document::from("<table><tr><td id='a'>1</td><td id='b'>2</td></tr></table><p>x</p>");
+        let a = doc.select_first("#a").unwrap();
+        a.replace_with(Content::html("<td>one</td><td>uno</td>"));
+        assert_eq!(
+            doc.select_first("tr").unwrap().into_document().html(),
+            r#"<tr><td>one</td><td>uno</td><td id="b">2</td></tr>"#
+        );
+
+        // moving element keeps its position among siblings
+        let p = doc.select_first("p").unwrap();
+        doc.select_first("#b").unwrap().replace_with(&p);
+        assert!(p.parent().unwrap().tag().unwrap() == "tr");
+        assert_eq!(p.index_in_parent().unwrap(), 2);
+        assert_eq!(doc.select("p").len(), 1);
+
+        // replacing with itself or ancestor does nothing
+        let tr = doc.select_first("tr").unwrap();
+        p.replace_with(&p);
+        p.replace_with(&tr);
+        assert!(p.parent().unwrap() == tr);
+
+        p.replace_with(Content::text("<b>text</b>"));
+        assert!(doc.select("p").is_empty());
+        assert_eq!(tr.text().unwrap(), "<b>text</b>");
+
+        // detached element has nothing to be replaced in
+        p.replace_with(Content::text("again"));
+        assert!(p.parent().is_none());
+    }
+
+    #[test]
+    fn test_create_and_append() {
+        let doc = Document::from("<div id='a'><p>one</p></div><div id='b'></div>");
+        let a = doc.select_first("#a").unwrap();
+        let b = doc.select_first("#b").unwrap();
+
+        let el = doc.create_element("SECTION").with_attr("class", "new");
+        assert!(el.parent().is_none());
+        assert!(doc.select(".new").is_empty());
+        b.append_child(&el);
+        b.prepend_child(Content::text("start"));
+        assert_eq!(doc.select("#b > section.new").len(), 1);
+        assert_eq!(b.text().unwrap(), "start");
+
+        // element is moved, not copied
+        el.append_child(doc.select_first("p").unwrap());
+        assert!(doc.select("#a > p").is_empty());
+        assert_eq!(doc.select("section > p").len(), 1);
+
+        // appending ancestor or itself does nothing
+        el.append_child(&b);
+        el.prepend_child(&el);
+        assert!(el.parent().unwrap() == b);
+
+        a.prepend_child(Content::html("<i>x</i><b>y</b>"));
+        assert_eq!(
+            a.into_document().html(),
+            r#"<div id="a"><i>x</i><b>y</b></div>"#
+        );
+
+        let template = doc.create_element("template");
+        template
+            .template_contents()
+            .unwrap()
+            .append_child(Content::html("<li>t</li>"));
+        assert_eq!(template.template_contents().unwrap().select("li").len(), 1);
+    }
+
+    #[test]
+    fn test_insert_before_after() {
+        let doc = Document::from("<ul><li id='a'>a</li><li id='b'>b</li><li id='c'>c</li></ul>");
+        let ids = || -> Vec<String> {
+            doc.select("li")
+                .iter()
+                .map(|li| li.id().unwrap_or_default())
+                .collect()
+        };
+        let a = doc.select_first("#a").unwrap();
+        let b = doc.select_first("#b").unwrap();
+        let c = doc.select_first("#c").unwrap();
+
+        // moving siblings around accounts for their old position
+        a.insert_after(&c);
+        assert_eq!(ids(), vec!["a", "c", "b"]);
+        b.insert_before(&a);
+        assert_eq!(ids(), vec!["c", "a", "b"]);
+        b.insert_after(Content::html("<li id='d'>d</li>"));
+        c.insert_before(Content::text("start"));
+        assert_eq!(ids(), vec!["c", "a", "b", "d"]);
+        assert_eq!(c.node_index_in_parent().unwrap(), 1);
+
+        // itself, ancestor or detached element
+        let ul = doc.select_first("ul").unwrap();
+        a.insert_after(&a);
+        a.insert_before(&ul);
+        assert_eq!(ids(), vec!["c", "a", "b", "d"]);
+        let el = doc.create_element("li");
+        el.insert_after(&a);
+        assert!(a.parent().unwrap() == ul);
+    }
+
+    #[test]
+    fn test_set_text_and_inner_html() {
+        let doc = Document::from("<div><p>one <b>two</b></p><template><i>t</i></template></div>");
+        let p = doc.select_first("p").unwrap();
+        let b = doc.select_first("b").unwrap();
+
+        p.set_text("<i>escaped</i> & kept");
+        assert!(b.parent().is_none());
+        assert_eq!(p.text().unwrap(), "<i>escaped</i> & kept");
+        assert!(doc
+            .html()
+            .contains("<p>&lt;i&gt;escaped&lt;/i&gt; &amp; kept</p>"));
+
+        p.set_inner_html("<a href='/x'>x</a> tail");
+        assert_eq!(doc.select("p > a").len(), 1);
+        assert_eq!(p.text().unwrap(), " tail");
+        p.set_inner_html("");
+        assert!(p.children().is_empty());
+        assert!(p.text().unwrap().is_empty());
+
+        let template = doc.select_first("template").unwrap();
+        template.set_inner_html("<li>one</li><li>two</li>");
+        assert!(doc.select("li").is_empty());
+        let contents = template.template_contents().unwrap();
+        assert_eq!(contents.select("li").len(), 2);
+        assert!(contents.select("i").is_empty());
+    }
+
+    #[test]
+    fn test_wrap_unwrap() {
+        let doc = Document::from("<div><span><span>one</span></span> two</div>");
+        let div = doc.select_first("div").unwrap();
+
+        for span in doc.select("span") {
+            span.unwrap();
+        }
+        assert!(doc.select("span").is_empty());
+        assert_eq!(div.text().unwrap(), "one two");
+
+        let doc = Document::from("<table><tr><td>a</td><td>b</td></tr></table>");
+        let td = doc.select("td");
+        td[1].wrap("text<td class='w'><div><b></b><i></i></div></td><td>ignored</td>");
+        assert_eq!(
+            doc.select_first("tr").unwrap().into_document().html(),
+            r#"<tr><td>a</td><td class="w"><div><b><td>b</td></b><i></i></div></td></tr>"#
+        );
+
+        // without wrapper element or parent nothing changes
+        td[0].wrap("only text");
+        assert_eq!(td[0].index_in_parent().unwrap(), 0);
+        let el = doc.create_element("p");
+        el.wrap("<div></div>");
+        el.unwrap();
+        assert!(el.parent().is_none());
+    }
+
+    #[test]
+    fn test_sanitizer() {
+        let html = "<html><head><title>t</title><style>p {}</style></head><body>\
+            <div><p class='a' onmouseover='x()' OnClick='y()'>one<span>two</span></p>\
+            <a href=' java\tscript:alert(1)'>js</a><a href='HTTPS://example.com'>ok</a>\
+            <a href='data:text/html,x'>data</a><a href='./rel:ative'>rel</a>\
+            <img srcset='/a.png 1x, javascript:x 2x'><img srcset='/a.png 1x, /b.png 2x'>\
+            <template><script>x()</script><p>t</p></template><svg><script>x()</script></svg>\
+            <!-- comment --></div></body></html>";
+        let doc = Document::from(html);
+        let sanitizer = Sanitizer::new()
+            .allow_tags(&["P", "a", "img", "template"])
+            .allow_attrs(&["href", "srcset", "class", "onclick"]);
+
+        let clean = sanitizer.sanitize(&doc);
+        assert_eq!(
+            clean.html(),
+            "<p class=\"a\">onetwo</p><a>js</a><a href=\"HTTPS://example.com\">ok</a>\
+             <a>data</a><a href=\"./rel:ative\">rel</a><img><img srcset=\"/a.png 1x, /b.png 2x\">\
+             <template><p>t</p></template>"
+        );
+        assert_eq!(doc.select("script").len(), 1);
+
+        let div = doc.select_first("div").unwrap();
+        Sanitizer::new().clean_element(&div);
+        assert!(doc.select("div")[0] == div);
+        assert_eq!(div.text().unwrap(), "onetwojsokdatarel");
+        assert!(div.children().is_empty());
+
+        Sanitizer::new().clean(&doc);
+        assert_eq!(doc.html(), "onetwojsokdatarel");
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let doc = Document::from("<ul><li>1</li><li>2</li></ul><a href='/x?utm=1'>x</a><a>y</a>");
+
+        let mut seen = 0;
+        let doc2 = doc.rewrite("li", |el| {
+            seen += 1;
+            el.insert_after(Content::html("<li>new</li>"));
+        });
+        assert_eq!(seen, 2);
+        assert_eq!(doc2.select("li").len(), 4);
+        assert_eq!(doc.select("li").len(), 2);
+
+        let doc3 = doc2.rewrite("a", |el| -> Option<()> {
+            let href = el.attr("href")?;
+            el.set_attr("href", href.split('?').next()?);
+            None
+        });
+        let links = doc3.select("a");
+        assert_eq!(links[0].attr("href").unwrap(), "/x");
+        assert!(links[1].attr("href").is_none());
+        assert!(doc3.rewrite("table", |el| el.remove()).html() == doc3.html());
+    }
+
+    #[test]
+    fn test_import_adopt() {
+        let page1 = DocumentBuilder::new()
+            .base_url("https://one.example/a/")
+            .unwrap()
+            .parse("<div class='post'><a href='x'>one</a></div>");
+        let page2 = Document::from("<div class='post'>\n<a href='y'>two</a></div>");
+        let output = DocumentBuilder::new()
+            .base_url("https://out.example/")
+            .unwrap()
+            .parse("<main></main>");
+        let main = output.select_first("main").unwrap();
+
+        let copy = output.import(&page1.select_first(".post").unwrap());
+        assert!(copy.parent().is_none());
+        main.append_child(&copy);
+        let adopted = output.adopt(&page2.select_first(".post").unwrap());
+        assert!(page2.select(".post").is_empty());
+        assert!(adopted.source_location().is_none());
+        main.append_child(&adopted);
+
+        let links = output.select("main > .post > a");
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].abs_attr("href").unwrap(), "https://out.example/x");
+        assert_eq!(links[1].abs_attr("href").unwrap(), "https://out.example/y");
+        assert!(links[1].parent().unwrap().parent().unwrap() == main);
+
+        // copy is independent from the original
+        copy.set_attr("class", "copy");
+        assert_eq!(page1.select(".post").len(), 1);
+    }
+
+    #[test]
+    fn test_absolutize_urls() {
+        let doc = Document::from(
+            "<head><base href='/static/'></head><a href=' img/x.png '>x</a><a href=''>empty</a>\
+             <a href='#top'>top</a><a href='http://[bad'>bad</a><form action='send'></form>\
+             <video poster='//cdn.example/p.jpg'><source src='v.mp4'></video><p title='t.html'></p>",
+        );
+        doc.absolutize_urls("https://example.com/dir/page.html")
+            .unwrap();
+
+        let hrefs: Vec<_> = doc
+            .select("a")
+            .iter()
+            .map(|a| a.attr("href").unwrap())
+            .collect();
+        assert_eq!(
+            hrefs,
+            vec![
+                "https://example.com/static/img/x.png",
+                "",
+                "https://example.com/static/#top",
+                "http://[bad"
+            ]
+        );
+        assert_eq!(
+            doc.select_first("form").unwrap().attr("action").unwrap(),
+            "https://example.com/static/send"
+        );
+        assert_eq!(
+            doc.select_first("video").unwrap().attr("poster").unwrap(),
+            "https://cdn.example/p.jpg"
+        );
+        assert_eq!(
+            doc.select_first("source").unwrap().attr("src").unwrap(),
+            "https://example.com/static/v.mp4"
+        );
+        assert_eq!(
+            doc.select_first("p").unwrap().attr("title").unwrap(),
+            "t.html"
+        );
+        assert!(matches!(
+            doc.absolutize_urls("/relative"),
+            Err(Error::InvalidUrl(_))
+        ));
+    }
+
+    #[test]
+    fn test_strip() {
+        let doc = Document::from(
+            "<head><style>p {}</style></head><div class='ad'><div class='ad'>x</div></div>\
+             <article><p>one</p><aside><p>note</p></aside><script>s()</script></article>",
+        );
+
+        let clean = doc.strip(" script ,, .ad, article > aside,").unwrap();
+        assert!(clean.select("script").is_empty());
+        assert!(clean.select(".ad").is_empty());
+        assert!(clean.select("aside").is_empty());
+        assert_eq!(clean.select("style").len(), 1);
+        assert_eq!(clean.select("p").len(), 1);
+        assert_eq!(doc.select("p").len(), 2);
+        assert!(doc.strip("").unwrap().html() == doc.html());
+
+        let doc = Document::from("<a title='a,b'>x</a><a title='c'>y</a><p>z</p>");
+        let clean = doc.strip("a[title=\"a,b\"], p").unwrap();
+        assert_eq!(clean.select("a").len(), 1);
+        assert!(clean.select("p").is_empty());
+        assert!(matches!(
+            doc.strip("p, a:hover"),
+            Err(Error::InvalidSelector(_))
+        ));
+    }
+
+    #[test]
+    fn test_pretty_html() {
+        let text = "lorem ipsum dolor sit amet ".repeat(5);
+        let html = format!(
+            "<!DOCTYPE html><div class=\"a&quot;\"><p>{}<a href='/x'>link</a>,   end</p>\
+             text &amp; <i>more</i><!-- c --><hr><textarea>\n a  b</textarea><br></div>\
+             <template><p>t</p></template><script>if (a < b) {{}}</script>",
+            text
+        );
+        let doc = DocumentBuilder::new().drop_doctype(false).parse(&html);
+
+        assert_eq!(
+            doc.to_pretty_html(1),
+            "<!DOCTYPE html>\n<html>\n <head></head>\n <body>\n  <div class=\"a&quot;\">\n   <p>\n    \
+             lorem ipsum dolor sit amet lorem ipsum dolor sit amet lorem ipsum dolor sit\n    \
+             amet lorem ipsum dolor sit amet lorem ipsum dolor sit amet\n    \
+             <a href=\"/x\">link</a>, end\n   </p>\n   text &amp; <i>more</i><!-- c -->\n   <hr>\n   \
+             <textarea> a  b</textarea><br>\n  </div>\n  <template>\n   <p>t</p>\n  \
+             </template>\n  <script>if (a < b) {}</script>\n </body>\n</html>\n"
+        );
+
+        let fragment = Document::parse_fragment("  <b>x</b>  ", "div");
+        assert_eq!(fragment.to_pretty_html(4), "<b>x</b>\n");
+        assert_eq!(Document::parse_fragment(" ", "div").to_pretty_html(4), "");
+    }
+
+    #[test]
+    fn test_minified_html() {
+        let doc = Document::from(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n  <title>T</title>\n</head>\n<body>\n\
+             <p>one  <b>two</b> <!-- c -->three</p>\n<p>four</p>\n\
+             <table>\n<tr><td>a</td><td title=\"x y\">b</td></tr>\n</table>\n\
+             <pre>  keep\n  this</pre>\n<input disabled value=\"\">\n</body>\n</html>",
+        );
+        let minified = doc.to_minified_html();
+
+        assert_eq!(
+            minified,
+            "<html lang=en><title>T</title><p>one <b>two</b> three\
+             <p>four<table><tbody><tr><td>a<td title=\"x y\">b</table>\
+             <pre>  keep\n  this</pre><input disabled value>"
+        );
+        assert_eq!(
+            Document::from(minified.as_str()).to_minified_html(),
+            minified
+        );
+        assert_eq!(
+            Document::from(minified.as_str()).select("td")[1].attr("title"),
+            Some("x y".to_string())
+        );
+
+        let doc = Document::from("<a href=\"#\"><p>text</p></a> <span>x</span>");
+        assert_eq!(
+            doc.to_minified_html(),
+            "<a href=#><p>text</p></a> <span>x</span>"
+        );
+    }
+
+    #[test]
+    fn test_html_with_escaping() {
+        let html = "<!-- ünï --><title>Ça</title><style>p::after { content: \"é\" }</style>\
+                    <p data-x=\"a&quot;b ñ\">x &lt; y\u{a0}ß<br>😀</p><template><i>é</i></template>";
+        let doc = Document::from(html);
+
+        assert_eq!(doc.html_with(Escaping::default()), doc.html());
+
+        let ascii = doc.html_with(Escaping::Ascii);
+        assert_eq!(
+            ascii,
+            "<!-- ünï --><html><head><title>&#xC7;a</title>\
+             <style>p::after { content: \"é\" }</style></head><body>\
+             <p data-x=\"a&quot;b &#xF1;\">x &lt; y&nbsp;&#xDF;<br>&#x1F600;</p>\
+             <template><i>&#xE9;</i></template></body></html>"
+        );
+
+        let reparsed = Document::from(ascii.as_str());
+        assert_eq!(reparsed.html(), doc.html());
+    }
+
+    #[cfg(feature = "encoding_rs")]
+    #[test]
+    fn test_to_bytes() {
+        use encoding_rs::{SHIFT_JIS, UTF_16LE, UTF_8, WINDOWS_1252};
+
+        let doc = Document::from("<title>café</title><p>日本</p>");
+        let bytes = doc.to_bytes(WINDOWS_1252);
+        assert_eq!(
+            bytes,
+            b"<html><head><meta charset=\"windows-1252\"><title>caf\xe9</title></head>\
+              <body><p>&#26085;&#26412;</p></body></html>"
+                .to_vec()
+        );
+        assert!(doc.select("meta").is_empty());
+
+        let roundtrip = Document::from_bytes(&bytes);
+        assert_eq!(roundtrip.title(), Some("café".to_string()));
+        assert_eq!(roundtrip.select_first("p").unwrap().text().unwrap(), "日本");
+
+        let doc = Document::from(
+            "<meta http-equiv=Content-Type content=\"text/html; charset=iso-8859-1\"><p>日本</p>",
+        );
+        let bytes = doc.to_bytes(SHIFT_JIS);
+        let roundtrip = Document::from_bytes(&bytes);
+        assert_eq!(
+            roundtrip.select_first("meta").unwrap().attr("content"),
+            Some("text/html; charset=Shift_JIS".to_string())
+        );
+        assert_eq!(roundtrip.select_first("p").unwrap().text().unwrap(), "日本");
+
+        assert_eq!(doc.to_bytes(UTF_16LE), doc.to_bytes(UTF_8));
+    }
+
+    #[test]
+    fn test_transaction() {
+        let html = "<div id='a' class='x'><p>one</p><p>two <b>bold</b></p></div><ul id='b'><li>item</li></ul>";
+        let doc = Document::from(html);
+        let original = doc.html();
+        let first = doc.select_first("p").unwrap();
+        let list = doc.select_first("#b").unwrap();
+
+        let tx = doc.begin();
+        first.set_text("changed");
+        first.set_attr("title", "t");
+        doc.select_first("#a").unwrap().add_class("y z");
+        doc.select_first("b").unwrap().unwrap();
+        list.append_child(&first);
+        list.prepend_child(Content::html("<li>new</li>"));
+        doc.select_first("li").unwrap().wrap("<span></span>");
+        doc.select("p")[0].replace_with(Content::text("text"));
+        list.insert_before(doc.create_element("hr"));
+        Sanitizer::new().allow_tags(&["ul", "li", "p"]).clean(&doc);
+        doc.absolutize_urls("https://example.com/").unwrap();
+        assert!(doc.html() != original);
+        tx.rollback();
+
+        assert_eq!(doc.html(), original);
+        assert!(first.parent().unwrap().id() == Some("a".to_string()));
+        assert_eq!(first.text().unwrap(), "one");
+        assert_eq!(
+            doc.select("li")[0].parent().unwrap().id(),
+            Some("b".to_string())
+        );
+
+        {
+            let _tx = doc.begin();
+            first.remove();
+        }
+        assert_eq!(doc.html(), original);
+
+        let outer = doc.begin();
+        first.remove();
+        let inner = doc.begin();
+        list.remove();
+        inner.rollback();
+        assert_eq!(doc.select("ul").len(), 1);
+        let inner = doc.begin();
+        doc.select_first("b").unwrap().remove();
+        inner.commit();
+        assert!(doc.select("b").is_empty());
+        outer.rollback();
+        assert_eq!(doc.html(), original);
+
+        // inner transactions end with the outer one
+        let outer = doc.begin();
+        first.remove();
+        let inner = doc.begin();
+        list.remove();
+        outer.rollback();
+        assert_eq!(doc.html(), original);
+        first.set_text("kept");
+        drop(inner);
+        assert_eq!(first.text().unwrap(), "kept");
+        first.set_text("one");
+
+        let outer = doc.begin();
+        let inner = doc.begin();
+        list.remove();
+        outer.commit();
+        inner.rollback();
+        assert!(doc.select("ul").is_empty());
+        doc.select_first("body").unwrap().append_child(&list);
+        assert_eq!(doc.html(), original);
+
+        let tx = doc.begin();
+        first.remove();
+        tx.commit();
+        assert_eq!(doc.select("p").len(), 1);
+        assert!(first.parent().is_none());
+        // moves between documents are undone by the document they were made in
+        let page = Document::from("<article><h1>Part 1</h1></article><p>x</p>");
+        let out = Document::from("<main></main>");
+        let main = out.select_first("main").unwrap();
+        let tx = page.begin();
+        let out_tx = out.begin();
+        main.append_child(out.adopt(&page.select_first("article").unwrap()));
+        main.append_child(page.select_first("p").unwrap());
+        assert!(page.select("article").is_empty());
+        assert!(page.select("p").is_empty());
+        out_tx.rollback();
+        assert!(out.select("main > *").is_empty());
+        assert!(page.select("article").is_empty());
+        tx.rollback();
+        assert_eq!(page.select("article > h1").len(), 1);
+        assert_eq!(page.select("p").len(), 1);
+        assert!(out.select("main > *").is_empty());
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_replace_text() {
+        use regex::Regex;
+
+        let doc = Document::from(
+            "<p>Call 555-0100 <span>or 555-0199</span> today</p><p>Keep 555-0111</p>",
+        );
+        let p = doc.select_first("p").unwrap();
+        let phone = Regex::new(r"\d{3}-\d{4}").unwrap();
+
+        assert_eq!(p.replace_text(&phone, "XXX-XXXX"), 2);
+        assert_eq!(
+            p.descendant_text_nodes(),
+            vec!["Call XXX-XXXX ", "or XXX-XXXX", " today"]
+        );
+        assert_eq!(doc.select("p")[1].text().unwrap(), "Keep 555-0111");
+        assert_eq!(p.replace_text(&phone, "XXX-XXXX"), 0);
+
+        let tx = doc.begin();
+        assert_eq!(p.replace_text(&Regex::new("XXX-XXXX ?").unwrap(), ""), 2);
+        assert_eq!(p.descendant_text_nodes(), vec!["Call ", "or ", " today"]);
+        tx.rollback();
+        assert_eq!(
+            p.descendant_text_nodes(),
+            vec!["Call XXX-XXXX ", "or XXX-XXXX", " today"]
+        );
+
+        let span = doc.select_first("span").unwrap();
+        assert_eq!(span.replace_text(&Regex::new(".+").unwrap(), ""), 1);
+        assert!(span.descendant_text_nodes().is_empty());
+        assert_eq!(doc.select("span").len(), 1);
+        let doc = Document::from(
+            "<div>a1 <script>var a1;</script><textarea>a1</textarea><style>a1{}</style></div>",
+        );
+        let div = doc.select_first("div").unwrap();
+        assert_eq!(div.replace_text(&Regex::new(r"\d*").unwrap(), "#"), 1);
+        assert_eq!(
+            div.descendant_text_nodes(),
+            vec!["a# ", "var a1;", "a1", "a1{}"]
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let doc = Document::from(
+            "<div>one two one<script>var one = 1;</script><p>none <b>one</b></p></div>",
+        );
+        let div = doc.select_first("div").unwrap();
+
+        assert_eq!(
+            div.highlight("one", "<span class='hl'><mark></mark></span>"),
+            4
+        );
+        assert_eq!(doc.select("span.hl > mark").len(), 4);
+        assert_eq!(doc.select("script")[0].text().unwrap(), "var one = 1;");
+        assert_eq!(div.text_nodes(), vec![" two "]);
+        assert_eq!(doc.select("p")[0].text_nodes(), vec!["n", " "]);
+        assert_eq!(
+            div.descendant_text_nodes().concat(),
+            "one two onevar one = 1;none one"
+        );
+
+        let tx = doc.begin();
+        assert_eq!(div.highlight("two", "<em></em>"), 1);
+        tx.rollback();
+        assert!(doc.select("em").is_empty());
+
+        assert_eq!(div.highlight("", "<mark></mark>"), 0);
+        assert_eq!(div.highlight("two", "no element"), 0);
+        assert_eq!(div.highlight("missing", "<mark></mark>"), 0);
+    }
+
+    #[test]
+    fn test_selection() {
+        let doc = Document::from(
+            "<div class='a'><p class='x'>1</p><p>2</p></div><div class='b'><p class='x'>3</p></div>\
+             <section><div class='a'><i>4</i></div></section>",
+        );
+        let texts = |sel: &Selection| sel.map(|el| el.descendant_text_nodes().concat());
+
+        let divs = doc.select("div");
+        assert_eq!(divs.len(), 3);
+        assert_eq!(texts(&divs.filter(".a")), vec!["12", "4"]);
+        assert_eq!(texts(&divs.filter("section > div")), vec!["4"]);
+        assert_eq!(texts(&divs.not(".a")), vec!["3"]);
+        assert_eq!(texts(&divs.eq(1)), vec!["3"]);
+        assert!(divs.eq(usize::MAX).is_empty());
+        assert_eq!(texts(&divs.slice(1..)), vec!["3", "4"]);
+        assert_eq!(texts(&divs.slice(..=0)), vec!["12"]);
+        assert!(divs.slice(5..).is_empty());
+        assert_eq!(texts(&divs.find("p").filter(".x")), vec!["1", "3"]);
+
+        let nested = doc.select("section").find("div").find("i");
+        assert_eq!(texts(&nested), vec!["4"]);
+        let twice = Selection::from(vec![divs[0].clone(), divs[0].clone()]).find("p");
+        assert_eq!(twice.len(), 2);
+
+        let vec: Vec<Element> = divs.clone().into();
+        assert_eq!(vec.len(), 3);
+        assert_eq!(divs.into_vec().len(), 3);
+        let collected: Selection = vec.into_iter().filter(|el| el.has_class("b")).collect();
+        assert_eq!(collected.len(), 1);
+    }
+
+    #[test]
+    fn test_select_iter() {
+        let doc = Document::from(
+            "<div id='a'><ul><li class='x'>1</li><li>2<ul><li class='x'>3</li></ul></li></ul></div>\
+             <ul><li class='x'>4</li></ul>",
+        );
+        let texts = |els: Vec<Element>| -> Vec<String> {
+            els.iter().map(|el| el.text().unwrap()).collect()
+        };
+
+        for selector in &[
+            "li",
+            "ul > li",
+            "ul li.x",
+            "div li",
+            "#a ul ul > li",
+            "li ul li",
+        ] {
+            let expected = doc.select(selector).into_vec();
+            assert_eq!(
+                texts(doc.select_iter(selector).collect()),
+                texts(expected),
+                "{}",
+                selector
+            );
+        }
+
+        let div = doc.select_first("#a").unwrap();
+        assert_eq!(texts(div.select_iter("li.x").collect()), vec!["1", "3"]);
+        for selector in &["li", "ul > li", "ul ul li", "li ul > li.x"] {
+            let expected = div.select(selector).into_vec();
+            assert_eq!(
+                texts(div.select_iter(selector).collect()),
+                texts(expected),
+                "{}",
+                selector
+            );
+        }
+        assert!(div.select_iter("div li").next().is_none());
+        assert!(div.select_iter("div > ul").next().is_none());
+        assert_eq!(div.select_iter("ul > li").count(), 3);
+    }
+
+    #[test]
+    fn test_selection_access() {
+        let doc = Document::from("<li>a</li><li>b</li><li>c</li>");
+        let items = doc.select("li");
+
+        assert_eq!(items.len(), 3);
+        assert!(!items.is_empty());
+        assert!(doc.select("p").is_empty());
+        assert_eq!(items[2].text().unwrap(), "c");
+        assert_eq!(items.get(0).unwrap().text().unwrap(), "a");
+        assert!(items.get(3).is_none());
+
+        let mut texts = vec![];
+        for el in &items {
+            texts.push(el.text().unwrap());
+        }
+        assert_eq!(texts, vec!["a", "b", "c"]);
+        assert_eq!(
+            items.iter().filter(|el| el.text().unwrap() != "b").count(),
+            2
+        );
+        assert_eq!(items.into_iter().last().unwrap().text().unwrap(), "c");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_selection_index_out_of_bounds() {
+        let doc = Document::from("<li>a</li>");
+        doc.select("li")[1].text();
+    }
+
+    #[test]
+    fn test_selection_texts_attrs() {
+        let doc = Document::from(
+            "<div><a href='/a' title=''>A <b>bold</b></a><a>B</a><a href='/c'>C</a></div>",
+        );
+        let links = doc.select("a");
+
+        assert_eq!(links.texts(), vec!["A ", "B", "C"]);
+        assert_eq!(links.attrs("href"), vec!["/a", "/c"]);
+        assert_eq!(links.attrs("title"), vec![""]);
+        assert!(links.attrs("rel").is_empty());
+        assert!(doc.select("p").texts().is_empty());
+    }
+
+    #[test]
+    fn test_select_unique_document_order() {
+        let doc = Document::from(
+            "<div id='a'><div id='b'><p id='1'>1</p><div id='c'><p id='2'>2</p></div></div>\
+             <p id='3'>3</p></div><p id='4'>4</p>",
+        );
+        let ids = |sel: Selection| -> Vec<String> { sel.attrs("id") };
+
+        assert_eq!(ids(doc.select("div")), vec!["a", "b", "c"]);
+        assert_eq!(ids(doc.select("div p")), vec!["1", "2", "3"]);
+        assert_eq!(ids(doc.select("div div p")), vec!["1", "2", "3"]);
+        assert_eq!(ids(doc.select("div > p")), vec!["1", "2", "3"]);
+        assert_eq!(ids(doc.select("div div > p")), vec!["1", "2", "3"]);
+        assert_eq!(ids(doc.select("div div")), vec!["a", "b", "c"]);
+        assert_eq!(ids(doc.select("p")), vec!["1", "2", "3", "4"]);
+
+        let a = doc.select_first("#a").unwrap();
+        assert_eq!(ids(a.select("div p")), vec!["1", "2"]);
+        assert_eq!(ids(a.select("div > p")), vec!["1", "2"]);
+        assert_eq!(ids(a.select("p")), vec!["1", "2", "3"]);
+
+        for selector in &["div", "div p", "div div > p", "p", "div > div"] {
+            assert_eq!(
+                ids(doc.select(selector)),
+                doc.select_iter(selector)
+                    .map(|el| el.attr("id").unwrap())
+                    .collect::<Vec<_>>(),
+                "{}",
+                selector
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        let doc = Document::from("<div><a href='/a' target='_blank'>a</a><a>b</a></div>");
+
+        for selector in &[
+            "a:hover",
+            "a[=\"x\"]",
+            "a[^=\"x\"]",
+            "a[]",
+            "li:nth-child(x)",
+        ] {
+            let res = doc.try_select(selector);
+            assert!(
+                matches!(res, Err(Error::InvalidSelector(_))),
+                "{}",
+                selector
+            );
+            assert!(doc.select(selector).is_empty());
+            assert!(doc.select_first(selector).is_none());
+            assert_eq!(doc.select_iter(selector).count(), 0);
+        }
+        assert_eq!(
+            Error::InvalidSelector(":hover".to_string()).to_string(),
+            "invalid selector \":hover\""
+        );
+
+        let div = doc.select_first("div").unwrap();
+        assert!(div.try_select("a:first").is_err());
+        assert_eq!(div.try_select("a[target]").unwrap().len(), 1);
+        assert!(div.select("a").filter("a:first").is_empty());
+        assert_eq!(div.select("a").not("a:first").len(), 2);
+        assert_eq!(div.next_until("a:first").len(), 0);
+    }
+
+    #[test]
+    fn test_try_from_bytes() {
+        let doc = Document::try_from_bytes("<p>привет</p>".as_bytes()).unwrap();
+        assert_eq!(doc.select_first("p").unwrap().text().unwrap(), "привет");
+
+        let doc = Document::try_from(&b"<ul><li>one</li><li>two</li></ul>"[..]).unwrap();
+        assert_eq!(doc.select("li").len(), 2);
+
+        let res = Document::try_from_bytes(b"<p>\xff\xfe</p>");
+        match res {
+            Err(Error::InvalidUtf8(err)) => assert_eq!(err.valid_up_to(), 3),
+            _ => panic!("expected invalid utf-8 error"),
+        }
+    }
+
+    #[test]
+    fn test_first_last_nth_exists() {
+        let doc = Document::from(
+            "<ul class='a'><li>1</li><li>2<ul><li>3</li></ul></li></ul><ul><li>4</li></ul>",
+        );
+
+        for selector in &[
+            "li",
+            "ul li",
+            "ul > li",
+            "ul ul li",
+            ".a li",
+            "li:nth-child(2)",
+            "ul >",
+            "p",
+        ] {
+            let all = doc.select(selector);
+            assert!(doc.first(selector) == all.first().cloned(), "{}", selector);
+            assert!(doc.last(selector) == all.last().cloned(), "{}", selector);
+            for n in 0..5 {
+                assert!(doc.nth(selector, n) == all.get(n).cloned(), "{}", selector);
+            }
+            assert_eq!(doc.exists(selector), !all.is_empty(), "{}", selector);
+
+            let ul = doc.first("ul").unwrap();
+            let all = ul.select(selector);
+            assert!(ul.first(selector) == all.first().cloned(), "{}", selector);
+            assert!(ul.last(selector) == all.last().cloned(), "{}", selector);
+            for n in 0..5 {
+                assert!(ul.nth(selector, n) == all.get(n).cloned(), "{}", selector);
+            }
+            assert_eq!(ul.exists(selector), !all.is_empty(), "{}", selector);
+        }
+
+        assert_eq!(doc.last("li").unwrap().text().unwrap(), "4");
+        assert_eq!(doc.last("ul").unwrap().select("li").texts(), vec!["4"]);
+        assert!(doc.last("a:hover").is_none());
+        assert!(!doc.exists("a:hover"));
+    }
+
+    #[test]
+    fn test_select_with_limit() {
+        let doc = Document::from(
+            "<div><p>1</p><section><p>2</p><p>3</p></section></div><p>4</p><p>5</p>",
+        );
+
+        for limit in 0..7 {
+            let all = doc.select("p");
+            let limited = doc.select_with_limit("p", limit);
+            assert_eq!(limited.len(), limit.min(5));
+            assert_eq!(limited.texts(), all.slice(..limit).texts());
+        }
+        assert_eq!(doc.select_with_limit("div p", 2).texts(), vec!["1", "2"]);
+
+        let section = doc.first("section").unwrap();
+        assert_eq!(section.select_with_limit("p", 5).texts(), vec!["2", "3"]);
+        assert!(section.select_with_limit("div p", 5).is_empty());
+    }
+
+    #[test]
+    fn test_select_count() {
+        let doc = Document::from(
+            "<div class='a'><p>1</p><div><p>2</p><p class='x'>3</p></div></div><p class='x'>4</p>",
+        );
+
+        for selector in &[
+            "p",
+            "div p",
+            "div div p",
+            "div > p",
+            ".x",
+            "div >",
+            "p:nth-child(2)",
+            "a",
+        ] {
+            assert_eq!(
+                doc.select_count(selector),
+                doc.select(selector).len(),
+                "{}",
+                selector
+            );
+
+            let div = doc.first("div").unwrap();
+            assert_eq!(
+                div.select_count(selector),
+                div.select(selector).len(),
+                "{}",
+                selector
+            );
+        }
+        assert_eq!(doc.select_count("a:hover"), 0);
+    }
+
+    #[test]
+    fn test_selection_filter_by() {
+        let doc = Document::from(
+            "<ul><li class='a'>one</li><li>two</li><li class='a'>three</li><li>four</li></ul>",
+        );
+
+        let long = doc
+            .select("li")
+            .filter_by(|li| li.text().unwrap().len() > 3);
+        assert_eq!(long.texts(), vec!["three", "four"]);
+
+        let mut calls = 0;
+        let odd = doc.select("li").filter_by(|_| {
+            calls += 1;
+            calls % 2 == 1
+        });
+        assert_eq!(calls, 4);
+        assert_eq!(odd.texts(), vec!["one", "three"]);
+
+        let chained = doc
+            .select("li")
+            .filter(".a")
+            .filter_by(|li| li.text().unwrap().starts_with('t'));
+        assert_eq!(chained.texts(), vec!["three"]);
+        assert!(doc.select("li").filter_by(|_| false).is_empty());
+    }
+
+    #[test]
+    fn test_selection_parents_children() {
+        let doc = Document::from(
+            "<div id='a'><ul><li>1</li><li>2<ul><li>3</li></ul></li></ul></div><ol><li>4</li></ol>",
+        );
+        let items = doc.select("li");
+
+        let lists = items.parents(Some("ul"));
+        assert_eq!(lists.len(), 2);
+        assert!(lists[0] == doc.first("ul").unwrap());
+        assert!(lists[1] == doc.first("li ul").unwrap());
+        assert_eq!(items.parents(Some("ol")).len(), 1);
+        assert_eq!(items.parents(Some("#a")).len(), 1);
+        assert_eq!(items.parents(Some("div li")).texts(), vec!["2"]);
+
+        let tags = items.parents(None).map(|el| el.tag().unwrap());
+        assert_eq!(tags, vec!["ul", "div", "body", "html", "ul", "li", "ol"]);
+        assert!(doc.select("html").parents(None).is_empty());
+
+        let children = doc.select("ul").children(None);
+        assert_eq!(children.len(), 3);
+        assert_eq!(children.texts(), vec!["1", "2", "3"]);
+        assert_eq!(
+            doc.select("ul").children(Some("li:nth-child(2)")).texts(),
+            vec!["2"]
+        );
+        assert!(doc.select("li").children(Some("li")).is_empty());
+
+        let twice: Selection = doc
+            .select("ol")
+            .into_iter()
+            .chain(doc.select("ol"))
+            .collect();
+        assert_eq!(twice.children(None).len(), 1);
+        assert_eq!(twice.parents(None).len(), 2);
+    }
+
+    #[test]
+    fn test_select_records() {
+        let doc = Document::from(
+            "<div class='item'><h2>One</h2><span class='price'>1</span></div>
+             <div class='item'><h2>Two</h2></div>
+             <div class='item'><span class='price'>3</span><a href='/three'>more</a></div>
+             <span class='price'>outside</span>",
+        );
+
+        let records = doc.select_records(
+            ".item",
+            &[("title", "h2"), ("price", ".price"), ("link", "a[href]")],
+        );
+        let text = |record: &HashMap<&str, Option<Element>>, field: &str| {
+            record[field].as_ref().map(|el| el.text().unwrap())
+        };
+
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|record| record.len() == 3));
+        assert_eq!(text(&records[0], "title"), Some("One".to_string()));
+        assert_eq!(text(&records[0], "price"), Some("1".to_string()));
+        assert_eq!(text(&records[0], "link"), None);
+        assert_eq!(text(&records[1], "title"), Some("Two".to_string()));
+        assert_eq!(text(&records[1], "price"), None);
+        assert_eq!(text(&records[2], "title"), None);
+        assert_eq!(text(&records[2], "price"), Some("3".to_string()));
+        assert_eq!(
+            records[2]["link"].as_ref().unwrap().attr("href").unwrap(),
+            "/three"
+        );
+
+        assert!(doc
+            .select_records(".missing", &[("title", "h2")])
+            .is_empty());
+        assert!(doc.select_records(".item", &[])[0].is_empty());
+    }
+
+    #[test]
+    fn test_select_indexed() {
+        let doc = Document::from(
+            "<div id='main' class='box'><p class='a b'>1</p><div class='box'><p class='a'>2</p></div></div>
+             <p id=' x ' class='b'>3</p><p id='y z'>5</p><section class='box'><p class='b a a'>4</p></section>",
+        );
+
+        let selectors = [
+            ".a",
+            ".b",
+            ".a.b",
+            "p.a",
+            ".box .a",
+            ".box > .a",
+            "div .box p.a",
+            "#main",
+            "#x",
+            "#x.b",
+            "#y",
+            "#main .a",
+            ".box",
+            "div.box",
+            ".missing",
+            "#missing",
+            ".a:nth-child(1)",
+        ];
+        for selector in selectors.iter() {
+            let walked: Vec<_> = doc.select_iter(selector).collect();
+            let indexed = doc.select(selector);
+            assert_eq!(indexed.len(), walked.len(), "{}", selector);
+            assert!(
+                indexed.iter().zip(walked.iter()).all(|(a, b)| a == b),
+                "{}",
+                selector
+            );
+            assert!(
+                doc.select_first(selector) == walked.first().cloned(),
+                "{}",
+                selector
+            );
+            assert_eq!(doc.select_count(selector), walked.len(), "{}", selector);
         }
+
+        // index is rebuilt after changes
+        assert_eq!(doc.select(".a").texts(), vec!["1", "2", "4"]);
+        doc.select_first("#x").unwrap().add_class("a");
+        assert_eq!(doc.select(".a").texts(), vec!["1", "2", "3", "4"]);
+        doc.select_first("section").unwrap().remove();
+        assert_eq!(doc.select(".a").texts(), vec!["1", "2", "3"]);
+        doc.select_first("#main")
+            .unwrap()
+            .append_child(Content::html("<b class='a'>5</b>"));
+        assert_eq!(doc.select(".a").texts(), vec!["1", "2", "5", "3"]);
+
+        let tx = doc.begin();
+        doc.select_first("#main").unwrap().remove_attr("id");
+        assert!(doc.select_first("#main").is_none());
+        tx.rollback();
+        assert_eq!(doc.select_count("#main"), 1);
     }
 
-    /// Get text
-    ///
-    /// # Example
-    /// ```
-    /// use crabquery::Document;
-    ///
-    /// let doc = Document::from("<a class='link'>hi there</a>");
-    /// let sel = doc.select("a");
-    /// let el = sel.first().unwrap();
-    ///
-    /// assert_eq!(el.text().unwrap(), "hi there");
-    /// ```
-    pub fn text(&self) -> Option<String> {
-        let mut res = "".to_string();
-        let children = self.handle.children.borrow();
+    #[test]
+    fn test_attr_text_ref() {
+        let doc =
+            Document::from("<a href='/x' title=''>one <b>two</b> three</a><p>four</p><p></p>");
+        let a = doc.select_first("a").unwrap();
 
-        for child in children.iter() {
-            if let NodeData::Text { ref contents } = child.data {
-                res.push_str(&contents.borrow().to_string().as_str());
-            }
+        assert_eq!(a.attr_ref("href").as_deref(), Some("/x"));
+        assert_eq!(a.attr_ref("title").as_deref(), Some(""));
+        assert!(a.attr_ref("missing").is_none());
+        assert!(doc.select_first("b").unwrap().attr_ref("href").is_none());
+
+        let elements = doc.select("a").into_iter().chain(doc.select("p"));
+        for el in elements {
+            assert_eq!(&*el.text_ref(), el.text().unwrap());
         }
+        assert_eq!(&*a.text_ref(), "one  three");
+        assert_eq!(&*doc.select("p")[1].text_ref(), "");
 
-        Some(res)
+        let href = a.attr_ref("href").unwrap();
+        assert_eq!(href.len(), 2);
+        drop(href);
+        a.set_attr("href", "/y");
+        assert_eq!(a.attr_ref("href").as_deref(), Some("/y"));
     }
 
-    /// Get children elements
-    ///
-    /// # Example
-    /// ```
-    /// use crabquery::Document;
-    ///
-    /// let doc = Document::from("<a class='link'><span>hi there</span></a>");
-    /// let sel = doc.select("a");
-    /// let el = sel.first().unwrap();
-    ///
-    /// assert_eq!(el.children().first().unwrap().text().unwrap(), "hi there");
-    /// ```
-    pub fn children(&self) -> Vec<Element> {
-        self.handle
-            .children
-            .borrow()
-            .iter()
-            .filter(|n| {
-                if let NodeData::Element { .. } = n.data {
-                    true
-                } else {
-                    false
-                }
-            })
-            .map(Element::from)
-            .collect::<Vec<_>>()
-    }
+    #[test]
+    fn test_selector_cache() {
+        let first = Selector::compile("ul > li.item");
+        let again = Selector::compile("ul > li.item");
+        assert!(Rc::ptr_eq(&first, &again));
+        assert_eq!(*first, Selector::from("ul > li.item"));
 
-    /// Get parent element
-    ///
-    /// # Example
-    /// ```
-    /// use crabquery::Document;
-    ///
-    /// let doc = Document::from("<a class='link'><span>hi there</span></a>");
-    /// let sel = doc.select("span");
-    /// let el = sel.first().unwrap();
-    ///
-    /// assert_eq!(el.parent().unwrap().tag().unwrap(), "a");
-    /// ```
-    pub fn parent(&self) -> Option<Element> {
-        if let Some(parent) = self.handle.parent.take() {
-            let wrapper = parent.upgrade().map(Element::from);
-            self.handle.parent.set(Some(parent));
+        assert!(Selector::cached("li:hover").is_err());
+        assert!(Selector::compile("li:hover").matchers.is_empty());
 
-            return wrapper;
+        let old = Selector::compile("p.old");
+        for i in 0..SELECTOR_CACHE_SIZE {
+            Selector::compile(&format!("li:nth-child({})", i));
+            // keep the first one recently used
+            Selector::compile("ul > li.item");
         }
-
-        None
+        assert!(Rc::ptr_eq(&first, &Selector::compile("ul > li.item")));
+        assert!(!Rc::ptr_eq(&old, &Selector::compile("p.old")));
+        SELECTOR_CACHE.with(|cache| assert_eq!(cache.borrow().len(), SELECTOR_CACHE_SIZE));
     }
 
-    /// Select child elements using given css selector
-    ///
-    /// # Example
-    /// ```
-    /// use crabquery::Document;
-    ///
-    /// let doc = Document::from("<span><a class='link'>hi there</a></span>");
-    /// let sel = doc.select("span");
-    /// let el = sel.first().unwrap();
-    /// let sel = el.select("a");
-    /// let a = sel.first().unwrap();
-    ///
-    /// assert_eq!(a.attr("class").unwrap(), "link");
-    /// ```
-    pub fn select(&self, selector: &str) -> Vec<Element> {
-        let sel = Selector::from(selector);
-        sel.find(self.handle.children.borrow())
-    }
-} //}}}
+    #[test]
+    fn test_select_nested_deep() {
+        // every step used to walk the subtrees of all nested matches again
+        let html = format!("{}<p>x</p>{}", "<div>".repeat(400), "</div>".repeat(400));
+        let doc = Document::from(html.as_str());
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(doc.select("div div div p").len(), 1);
+        for sel in ["div div div", "div > div div", "div div > div"].iter() {
+            assert_eq!(doc.select(sel).len(), doc.select_count(sel));
+        }
+        let div = doc.select_first("div").unwrap();
+        assert_eq!(div.select("div div p").len(), 1);
+    }
 
-    // Matcher tests{{{
     #[test]
-    fn test_matcher_tag() {
-        let m = Matcher::from("a");
-        assert_eq!(m.tag, vec!["a".to_string()],);
+    fn test_select_trailing_child() {
+        let doc = Document::from("<p>a<b>x</b> c <i>y</i></p><div><p>z</p></div>");
+        let div = doc.select_first("div").unwrap();
+
+        for sel in ["p >", "div >", "div > p >", "span >"].iter() {
+            let all = doc.select(sel);
+            assert!(all.iter().all(|el| el.tag().is_some()));
+            assert_eq!(all.len(), doc.select_count(sel));
+            assert_eq!(all.len(), doc.select_iter(sel).count());
+            assert_eq!(all.len(), doc.select_with_limit(sel, 10).len());
+            assert!(all.first() == doc.select_first(sel).as_ref());
+            assert_eq!(!all.is_empty(), doc.exists(sel));
+            assert_eq!(div.select(sel).len(), div.select_count(sel));
+        }
+        assert_eq!(doc.select("p >").len(), 2);
     }
 
     #[test]
-    fn test_matcher_complex() {
-        let m = Matcher::from("a.link.another_class#idofel.klass");
-        assert_eq!(m.tag, vec!["a".to_string()]);
+    fn test_keep_only() {
+        let html = "<!DOCTYPE html><title>t</title><div id='nav'><a href='/'>home</a></div>\
+            <div id='content'><!-- c --><p>one <a href='/one'>1</a></p><template><b>t</b></template></div>\
+            <ul><li><a href='/two' class='x'>2</a> text</li><li>three</li></ul><footer>f</footer>";
+
+        let doc = DocumentBuilder::new()
+            .drop_doctype(false)
+            .keep_only(&["#content", "li > a.x"])
+            .parse(html);
         assert_eq!(
-            m.class,
-            vec![
-                "link".to_string(),
-                "another_class".to_string(),
-                "klass".to_string()
-            ]
+            doc.html(),
+            "<!DOCTYPE html><html><body><div id=\"content\"><!-- c --><p>one <a href=\"/one\">1</a></p>\
+             <template><b>t</b></template></div><ul><li><a href=\"/two\" class=\"x\">2</a></li></ul></body></html>"
         );
-        assert_eq!(m.id, vec!["idofel".to_string()]);
-    }
 
-    #[test]
-    fn test_matcher_direct_match() {
-        let m = Matcher::from(">");
-        assert_eq!(m.direct_match, true);
+        let a = doc.select_first("li > a").unwrap();
+        assert_eq!(
+            &html[a.source_range().unwrap()],
+            "<a href='/two' class='x'>2</a>"
+        );
+        let content = doc.select_first("#content").unwrap();
+        assert!(html[content.source_range().unwrap()].ends_with("</template></div>"));
+
+        let doc = DocumentBuilder::new().keep_only(&["table"]).parse(html);
+        assert_eq!(doc.html(), "");
+
+        let mut sink = DocumentBuilder::new().keep_only(&["p"]).sink();
+        for chunk in html.as_bytes().chunks(7) {
+            sink.feed_bytes(chunk);
+        }
+        assert_eq!(
+            sink.finish().html(),
+            "<html><body><div id=\"content\"><p>one <a href=\"/one\">1</a></p></div></body></html>"
+        );
     }
 
     #[test]
-    fn test_matcher_data_attribute_present() {
-        let m = Matcher::from("a[target]");
-        let mut attr = HashMap::new();
-        attr.insert("target".to_string(), AttributeSpec::Present);
-        assert_eq!(m.attribute, attr);
+    fn test_parse_limits() {
+        let html = "<div><p>one</p><!-- c --><p>two <b>three</b></p></div>";
+
+        let builder = DocumentBuilder::new()
+            .max_input_size(html.len())
+            .max_depth(5)
+            .max_nodes(8);
+        assert!(builder.try_parse(html).is_ok());
+
+        let res = DocumentBuilder::new()
+            .max_input_size(html.len() - 1)
+            .try_parse(html);
+        assert!(matches!(
+            res,
+            Err(Error::LimitExceeded(Limit::InputSize(_)))
+        ));
+
+        let res = DocumentBuilder::new().max_depth(4).try_parse(html);
+        assert!(matches!(res, Err(Error::LimitExceeded(Limit::Depth(4)))));
+        let doc = DocumentBuilder::new().max_depth(4).parse(html);
+        assert_eq!(doc.select("p").len(), 2);
+        assert_eq!(doc.select("b").len(), 0);
+
+        let res = DocumentBuilder::new().max_nodes(7).try_parse(html);
+        assert!(matches!(res, Err(Error::LimitExceeded(Limit::Nodes(7)))));
+
+        let deep = "<div>".repeat(100_000);
+        let err = DocumentBuilder::new()
+            .max_depth(100)
+            .parse_reader(deep.as_bytes())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "depth limit of 100 exceeded");
     }
 
     #[test]
-    fn test_matcher_data_attribute_exact() {
-        let m = Matcher::from("a[target=\"_blank\"]");
-        let mut attr = HashMap::new();
-        attr.insert(
-            "target".to_string(),
-            AttributeSpec::Exact("_blank".to_string()),
+    fn test_select_stream() {
+        let select = |html: &str, selector: &str| -> Vec<String> {
+            SelectStream::new(html.as_bytes(), selector)
+                .map(|el| el.unwrap().attr("id").unwrap())
+                .collect()
+        };
+
+        let html = "<div id='1'><p id='2'>a<div id='3'><span id='4'></span></div></div>";
+        assert_eq!(select(html, "div"), vec!["1", "3"]);
+        assert_eq!(select(html, "div > span"), vec!["4"]);
+        assert_eq!(select(html, "div span"), vec!["4"]);
+        assert_eq!(select(html, "p span"), Vec::<String>::new());
+        assert_eq!(select(html, "div > div"), vec!["3"]);
+
+        let html = "<ul id='1'><li id='2'>a<li id='3'><img id='4'><b id='5'></b></ul>";
+        assert_eq!(select(html, "ul > li"), vec!["2", "3"]);
+        assert_eq!(select(html, "li:nth-child(2) > b"), vec!["5"]);
+        assert_eq!(select(html, "li b:nth-child(2)"), vec!["5"]);
+
+        let html = "<script id='1'>'<a id=\"x\">'</script><svg id='2'><a id='3'/></svg><a id='4'>";
+        assert_eq!(select(html, "a"), vec!["3", "4"]);
+        assert_eq!(select(html, "svg > a"), vec!["3"]);
+
+        let html = "<div>\n<a id='1' href='/x'>x</a>\n</div>";
+        let els: Vec<_> = SelectStream::new(html.as_bytes(), "[href]")
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(els.len(), 1);
+        assert_eq!(els[0].tag(), "a");
+        assert_eq!(els[0].source_location().line, 2);
+        assert_eq!(
+            els[0].attrs(),
+            vec![
+                ("id".to_string(), "1".to_string()),
+                ("href".to_string(), "/x".to_string())
+            ]
         );
-        assert_eq!(m.attribute, attr);
     }
 
     #[test]
-    fn test_matcher_data_attribute_starts() {
-        let m = Matcher::from("a[target^=\"_blank\"]");
-        let mut attr = HashMap::new();
-        attr.insert(
-            "target".to_string(),
-            AttributeSpec::Starts("_blank".to_string()),
+    fn test_el_has_attr() {
+        let doc = Document::from(
+            "<input type='checkbox' checked disabled='false'><input type='text' value=''>",
         );
-        assert_eq!(m.attribute, attr);
+        let sel = doc.select("input");
+        assert!(sel[0].has_attr("checked"));
+        assert!(sel[0].bool_attr("checked"));
+        assert!(sel[0].bool_attr("disabled"));
+        assert!(!sel[1].has_attr("checked"));
+        assert!(!sel[1].bool_attr("disabled"));
+        assert!(sel[1].has_attr("value"));
+        assert_eq!(sel[1].attr("value").unwrap(), "");
     }
 
     #[test]
-    fn test_matcher_data_attribute_ends() {
-        let m = Matcher::from("a[target$=\"_blank\"]");
-        let mut attr = HashMap::new();
-        attr.insert(
-            "target".to_string(),
-            AttributeSpec::Ends("_blank".to_string()),
+    fn test_el_abs_attr() {
+        let doc = Document::from(
+            "<head><base href='https://example.com/blog/post/'></head>
+             <a href='/root'>root</a>
+             <a href='sibling.html#top'>sibling</a>
+             <a href='//cdn.example.com/x.js'>protocol</a>
+             <a href=' https://other.org/ '>absolute</a>
+             <img src='../img.png'>
+             <a>none</a>",
         );
-        assert_eq!(m.attribute, attr);
+        let sel = doc.select("a");
+        let urls: Vec<_> = sel.iter().map(|el| el.abs_attr("href")).collect();
+        assert_eq!(
+            urls,
+            vec![
+                Some("https://example.com/root".to_string()),
+                Some("https://example.com/blog/post/sibling.html#top".to_string()),
+                Some("https://cdn.example.com/x.js".to_string()),
+                Some("https://other.org/".to_string()),
+                None,
+            ]
+        );
+        let el = doc.select_first("img").unwrap();
+        assert_eq!(
+            el.abs_attr("src").unwrap(),
+            "https://example.com/blog/img.png"
+        );
+
+        let doc = Document::from("<a href='/root'>one</a><a href='http://a.com/b'>two</a>");
+        let sel = doc.select("a");
+        assert_eq!(sel[0].abs_attr("href"), None);
+        assert_eq!(sel[1].abs_attr("href").unwrap(), "http://a.com/b");
     }
 
     #[test]
-    fn test_matcher_data_attribute_contains() {
-        let m = Matcher::from("a[target*=\"_blank\"]");
-        let mut attr = HashMap::new();
-        attr.insert(
-            "target".to_string(),
-            AttributeSpec::Contains("_blank".to_string()),
+    fn test_el_attr_parse() {
+        let doc = Document::from(
+            "<input data-count='12' data-price=' 9.5' data-flag='true' data-bad='x1'>",
         );
-        assert_eq!(m.attribute, attr);
+        let el = doc.select_first("input").unwrap();
+        assert_eq!(el.attr_parse::<i64>("data-count"), Some(Ok(12)));
+        assert_eq!(el.attr_parse::<f64>("data-price"), Some(Ok(9.5)));
+        assert_eq!(el.attr_parse::<bool>("data-flag"), Some(Ok(true)));
+        assert!(matches!(el.attr_parse::<u8>("data-bad"), Some(Err(_))));
+        assert_eq!(el.attr_parse::<u8>("data-none"), None);
+
+        assert_eq!(el.attr_or("data-count", 0u16), 12);
+        assert_eq!(el.attr_or("data-bad", 7u16), 7);
+        assert!(!el.attr_or("data-none", false));
     }
 
-    //}}}
+    #[test]
+    fn test_el_dataset() {
+        let doc = Document::from(
+            "<a data-id='1' data-product-name='crab' data-x-2='two' href='/'>hi there</a>",
+        );
+        let sel = doc.select("a");
+        let el = sel.first().unwrap();
 
-    // // Selector tests{{{
-    // #[test]
-    // fn test_selector_parse_simple() {
-    //     let sel = Selector::from("a");
-    //     assert_eq!(
-    //         sel,
-    //         Selector {
-    //             css: "a".to_string(),
-    //             next: None
-    //         }
-    //     );
-    // }
+        let data = el.dataset();
+        assert_eq!(data.len(), 3);
+        assert_eq!(data.get("id"), Some(&"1".to_string()));
+        assert_eq!(data.get("productName"), Some(&"crab".to_string()));
+        assert_eq!(data.get("x-2"), Some(&"two".to_string()));
 
-    // #[test]
-    // fn test_selector_parse_simple_with_class() {
-    //     let sel = Selector::from("a.link");
-    //     assert_eq!(
-    //         sel,
-    //         Selector {
-    //             css: "a.link".to_string(),
-    //             next: None
-    //         }
-    //     );
-    // } //}}}
+        let data = el.dataset_raw();
+        assert_eq!(data.get("product-name"), Some(&"crab".to_string()));
+        assert!(!data.contains_key("href"));
+    }
 
-    // Element tests{{{
     #[test]
-    fn test_el_tag() {
-        let doc = Document::from("<a class='link'>hi there</a>");
+    fn test_el_id() {
+        let doc = Document::from("<a id=' linkmain '>one</a><a id='  '>two</a><a>three</a>");
         let sel = doc.select("a");
-        let el = sel.first().unwrap();
-        assert_eq!(el.tag(), Some("a".to_string()));
+        assert_eq!(sel[0].id(), Some("linkmain".to_string()));
+        assert_eq!(sel[1].id(), None);
+        assert_eq!(sel[2].id(), None);
+
+        let sel = doc.select("a#linkmain");
+        assert_eq!(sel.len(), 1);
+
+        let doc = Document::from("<p id='a b'>one</p><p id=' a '>two</p>");
+        let p = doc.select_first("#a").unwrap();
+        assert_eq!(p.id().unwrap(), "a");
+        assert_eq!(doc.select("#a").len(), 1);
+        assert_eq!(doc.select("p#a").len(), 1);
+        assert_eq!(doc.select_count("#a"), 1);
+        assert!(doc.select("#b").is_empty());
+        assert_eq!(doc.select("p")[0].id().unwrap(), "a b");
     }
 
     #[test]
-    fn test_el_attr_class() {
-        let doc = Document::from("<a class='link'>hi there</a>");
-        let sel = doc.select("a");
-        let el = sel.first().unwrap();
-        assert_eq!(el.attr("class"), Some("link".to_string()));
+    fn test_el_namespace() {
+        let doc = Document::from(
+            "<div><svg><title>t</title><a>svg</a></svg><math><mi>x</mi></math><title>h</title></div>",
+        );
+        let svg = "http://www.w3.org/2000/svg";
+        let html = "http://www.w3.org/1999/xhtml";
+
+        let titles = doc.select("title");
+        assert_eq!(titles.len(), 2);
+        let namespaces: Vec<_> = titles.iter().map(|el| el.namespace().unwrap()).collect();
+        assert!(namespaces.contains(&svg.to_string()));
+        assert!(namespaces.contains(&html.to_string()));
+
+        assert_eq!(doc.select_first("div").unwrap().namespace().unwrap(), html);
+        assert_eq!(doc.select_first("svg").unwrap().namespace().unwrap(), svg);
+        assert_eq!(doc.select_first("a").unwrap().qual_name().unwrap(), "a");
+        assert_eq!(
+            doc.select_first("mi").unwrap().namespace().unwrap(),
+            "http://www.w3.org/1998/Math/MathML"
+        );
+
+        let mi = doc.select_first("mi").unwrap();
+        let el = Element::from(&mi.handle.children.borrow()[0]);
+        assert!(el.namespace().is_none());
+        assert!(el.qual_name().is_none());
     }
 
     #[test]
-    fn test_el_attr_id() {
-        let doc = Document::from("<a class='link' id=linkilink>hi there</a>");
+    fn test_el_classes() {
+        let doc = Document::from("<a class=' link   button '>hi there</a><span>no class</span>");
         let sel = doc.select("a");
         let el = sel.first().unwrap();
-        assert_eq!(el.attr("id"), Some("linkilink".to_string()));
+        assert_eq!(el.classes(), vec!["link".to_string(), "button".to_string()]);
+        assert!(el.has_class("link"));
+        assert!(el.has_class("button"));
+        assert!(!el.has_class("link button"));
+
+        let sel = doc.select("span");
+        let el = sel.first().unwrap();
+        assert!(el.classes().is_empty());
+        assert!(!el.has_class("link"));
     }
 
     #[test]
     fn test_el_attr_double_id() {
+        // id is matched as a whole, not as a list of words
         let doc = Document::from("<a class='link' id='linkone linkmain'>hi there</a>");
-        let sel = doc.select("a#linkone#linkmain");
+        assert!(doc.select("a#linkone#linkmain").is_empty());
+        assert!(doc.select("#linkone").is_empty());
+        let sel = doc.select("a.link");
         let el = sel.first().unwrap();
         assert_eq!(el.attr("class"), Some("link".to_string()));
+        assert_eq!(el.id().unwrap(), "linkone linkmain");
     }
 
     #[test]
@@ -800,6 +8001,21 @@ mod tests {
         assert_eq!(el.text().unwrap(), "text hi there".to_string());
     }
 
+    #[test]
+    fn test_el_text_nodes() {
+        let doc = Document::from("<div>one<span>two<b>three</b></span><!--c-->four<p></p></div>");
+        let el = doc.select_first("div").unwrap();
+        assert_eq!(el.text_nodes(), vec!["one", "four"]);
+        assert_eq!(
+            el.descendant_text_nodes(),
+            vec!["one", "two", "three", "four"]
+        );
+
+        let el = doc.select_first("p").unwrap();
+        assert!(el.text_nodes().is_empty());
+        assert!(el.descendant_text_nodes().is_empty());
+    }
+
     #[test]
     fn test_el_children() {
         let doc = Document::from(
@@ -815,6 +8031,81 @@ mod tests {
         assert_eq!(el.children().first().unwrap().text().unwrap(), "one");
     }
 
+    #[test]
+    fn test_el_subtree_stats() {
+        let doc = Document::from(
+            "<div>
+               text
+               <ul><li><a>one</a></li><li>two</li></ul>
+               <p>three</p>
+             </div>",
+        );
+        let el = doc.select_first("div").unwrap();
+        assert_eq!(el.depth(), 3);
+        assert_eq!(el.descendant_count(), 5);
+
+        let el = doc.select_first("p").unwrap();
+        assert_eq!(el.depth(), 0);
+        assert_eq!(el.descendant_count(), 0);
+    }
+
+    #[test]
+    fn test_el_document_order_traversal() {
+        let doc = Document::from(
+            "<div id='a'>
+               <p id='b'><span id='c'>one</span></p>
+               text
+               <p id='d'></p>
+             </div>
+             <section id='e'><b id='f'>two</b></section>",
+        );
+
+        let ids = ["html", "head", "body", "a", "b", "c", "d", "e", "f"];
+        let mut el = doc.select_first("html").unwrap();
+        let mut forward = vec![el.tag().unwrap()];
+        while let Some(next) = el.next_in_document() {
+            forward.push(next.id().or_else(|| next.tag()).unwrap());
+            el = next;
+        }
+        assert_eq!(forward, ids);
+
+        let mut backward = vec![el.id().unwrap()];
+        while let Some(prev) = el.prev_in_document() {
+            backward.push(prev.id().or_else(|| prev.tag()).unwrap());
+            el = prev;
+        }
+        backward.reverse();
+        assert_eq!(backward, ids);
+    }
+
+    #[test]
+    fn test_el_children_iter() {
+        let doc = Document::from(
+            "<div>
+            <span>one</span>
+            text
+            <span>two</span><!--c-->
+            <span>three</span>
+            </div>",
+        );
+        let el = doc.select_first("div").unwrap();
+
+        let mut iter = el.children_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.next().unwrap().text().unwrap(), "one");
+        assert_eq!(iter.len(), 2);
+
+        let texts: Vec<_> = (&el).into_iter().map(|c| c.text().unwrap()).collect();
+        assert_eq!(texts, vec!["one", "two", "three"]);
+
+        let mut count = 0;
+        for child in &el {
+            assert!(child == el.children()[count]);
+            count += 1;
+        }
+        assert_eq!(count, 3);
+    }
+
     #[test]
     fn test_el_parent() {
         let doc = Document::from(
@@ -828,6 +8119,89 @@ mod tests {
         assert_eq!(el.parent().unwrap().tag().unwrap(), "div");
     }
 
+    #[test]
+    fn test_el_relationship() {
+        let doc = Document::from(
+            "<div class='card'>
+               <p><span class='price'>10</span></p>
+             </div>
+             <div class='other'><span>20</span></div>",
+        );
+        let card = doc.select_first(".card").unwrap();
+        let other = doc.select_first(".other").unwrap();
+        let price = doc.select_first(".price").unwrap();
+
+        assert!(card.contains(&price));
+        assert!(card.is_ancestor_of(&price));
+        assert!(price.is_descendant_of(&card));
+        assert!(!other.contains(&price));
+        assert!(!price.is_descendant_of(&other));
+        assert!(!price.contains(&card));
+        assert!(!card.is_descendant_of(&card));
+    }
+
+    #[test]
+    fn test_el_css_path() {
+        let doc = Document::from(
+            "<div id='main'>
+               <p>intro</p>
+               <ul class='list'>
+                 <li>one</li>
+                 <li id='second item'>two</li>
+                 <li><a href='/'>three</a></li>
+               </ul>
+             </div>
+             <div><a href='/'>four</a></div>",
+        );
+
+        for el in ["a", "li", "div", "p", "ul"]
+            .iter()
+            .flat_map(|s| doc.select(s))
+        {
+            let path = el.css_path();
+            let found = doc.select(&path);
+            assert_eq!(found.len(), 1, "{}", path);
+            assert!(found[0] == el, "{}", path);
+        }
+
+        let el = doc.select_first("div#main li > a").unwrap();
+        assert_eq!(
+            el.css_path(),
+            "html > body > div#main:nth-child(1) > ul > li:nth-child(3) > a"
+        );
+
+        let el = doc.select_first("li[id^=\"second\"]").unwrap();
+        assert_eq!(
+            el.css_path(),
+            "html > body > div#main:nth-child(1) > ul > li:nth-child(2)"
+        );
+    }
+
+    #[test]
+    fn test_matcher_nth_child() {
+        let doc = Document::from("<ul><li>one</li><!-- c -->text<li>two</li><li>three</li></ul>");
+        let sel = doc.select("li:nth-child(2)");
+        assert_eq!(sel.len(), 1);
+        assert_eq!(sel[0].text().unwrap(), "two");
+        assert!(doc.select("li:nth-child(4)").is_empty());
+        assert_eq!(doc.select("ul > :nth-child(3)")[0].text().unwrap(), "three");
+    }
+
+    #[test]
+    fn test_el_index_in_parent() {
+        let doc =
+            Document::from("<div><!--c--><span>one</span>text<span>two</span><p>three</p></div>");
+        let sel = doc.select("span");
+        assert_eq!(sel[0].index_in_parent(), Some(0));
+        assert_eq!(sel[0].node_index_in_parent(), Some(1));
+        assert_eq!(sel[1].index_in_parent(), Some(1));
+        assert_eq!(sel[1].node_index_in_parent(), Some(3));
+
+        let el = doc.select_first("p").unwrap();
+        assert_eq!(el.index_in_parent(), Some(2));
+        assert_eq!(el.node_index_in_parent(), Some(4));
+    }
+
     #[test]
     fn test_attribute_selection_multiple_els() {
         let doc = Document::from(
@@ -0,0 +1,201 @@
+//! Indented human readable serialization
+//!
+use html5ever::serialize::TraversalScope;
+use markup5ever_arcdom::{Handle, NodeData};
+use std::sync::Arc;
+
+use super::serialize::{children, end_tag, escape_text, start_tag, to_html, BLOCK, VERBATIM, VOID};
+
+/// Maximum line width text is wrapped at, unless a single word is longer
+const WIDTH: usize = 80;
+
+enum Op {
+    /// Line at given depth
+    Line(String, usize),
+    Block(Handle, usize),
+    /// Consecutive inline nodes wrapped into lines
+    Inline(Vec<Handle>, usize),
+}
+
+/// Serialize children of the node with one block element per line, indented by its depth
+pub(crate) fn to_pretty_html(handle: &Handle, indent: usize) -> String {
+    let mut lines: Vec<String> = vec![];
+    let mut stack = child_ops(&children(handle), 0);
+
+    while let Some(op) = stack.pop() {
+        let (handle, depth) = match op {
+            Op::Line(line, depth) => {
+                lines.push(format!("{}{}", " ".repeat(depth * indent), line));
+                continue;
+            }
+            Op::Inline(nodes, depth) => {
+                let width = WIDTH.saturating_sub(depth * indent);
+                for line in wrap(&words(&nodes), width) {
+                    lines.push(format!("{}{}", " ".repeat(depth * indent), line));
+                }
+                continue;
+            }
+            Op::Block(handle, depth) => (handle, depth),
+        };
+
+        if !matches!(handle.data, NodeData::Element { .. }) || is_verbatim(&handle) {
+            stack.push(Op::Line(
+                to_html(&handle, TraversalScope::IncludeNode),
+                depth,
+            ));
+            continue;
+        }
+
+        let mut start = String::new();
+        start_tag(&handle, &mut start);
+        let mut end = String::new();
+        end_tag(&handle, &mut end);
+
+        let kids = children(&handle);
+        if kids.iter().any(is_block) {
+            stack.push(Op::Line(end, depth));
+            stack.extend(child_ops(&kids, depth + 1));
+            stack.push(Op::Line(start, depth));
+            continue;
+        }
+
+        let width = WIDTH.saturating_sub(depth * indent);
+        let content = wrap(&words(&kids), width.saturating_sub(indent));
+        match content.len() {
+            0 => stack.push(Op::Line(start + &end, depth)),
+            1 if start.len() + content[0].len() + end.len() <= width => {
+                stack.push(Op::Line(start + &content[0] + &end, depth))
+            }
+            _ => {
+                if !end.is_empty() {
+                    stack.push(Op::Line(end, depth));
+                }
+                stack.extend(
+                    content
+                        .into_iter()
+                        .rev()
+                        .map(|line| Op::Line(line, depth + 1)),
+                );
+                stack.push(Op::Line(start, depth));
+            }
+        }
+    }
+
+    let mut out = lines.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Operations for child nodes grouping consecutive inline nodes, in reverse order
+fn child_ops(nodes: &[Handle], depth: usize) -> Vec<Op> {
+    let mut ops = vec![];
+    let mut inline = vec![];
+
+    for node in nodes.iter() {
+        if is_block(node) {
+            if !inline.is_empty() {
+                ops.push(Op::Inline(std::mem::take(&mut inline), depth));
+            }
+            ops.push(Op::Block(Arc::clone(node), depth));
+        } else {
+            inline.push(Arc::clone(node));
+        }
+    }
+    if !inline.is_empty() {
+        ops.push(Op::Inline(inline, depth));
+    }
+    ops.reverse();
+
+    ops
+}
+
+fn is_block(handle: &Handle) -> bool {
+    match handle.data {
+        NodeData::Element { ref name, .. } => BLOCK.contains(&&*name.local),
+        NodeData::Doctype { .. } => true,
+        _ => false,
+    }
+}
+
+fn is_verbatim(handle: &Handle) -> bool {
+    match handle.data {
+        NodeData::Element { ref name, .. } => VERBATIM.contains(&&*name.local),
+        _ => false,
+    }
+}
+
+/// Split inline nodes into words which lines can be broken between,
+/// whitespace of text is collapsed and tags stick to the text around them
+fn words(nodes: &[Handle]) -> Vec<String> {
+    enum Item {
+        Open(Handle),
+        Close(Handle),
+    }
+
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut stack: Vec<_> = nodes.iter().rev().cloned().map(Item::Open).collect();
+
+    while let Some(item) = stack.pop() {
+        let handle = match item {
+            Item::Open(handle) => handle,
+            Item::Close(handle) => {
+                end_tag(&handle, &mut word);
+                continue;
+            }
+        };
+
+        match handle.data {
+            NodeData::Text { ref contents } => {
+                let text = contents.borrow();
+                let mut parts = text.split(|c: char| c.is_ascii_whitespace());
+                if let Some(first) = parts.next() {
+                    escape_text(first, &mut word);
+                }
+                for part in parts {
+                    if !word.is_empty() {
+                        words.push(std::mem::take(&mut word));
+                    }
+                    escape_text(part, &mut word);
+                }
+            }
+            NodeData::Element { ref name, .. } if !is_verbatim(&handle) => {
+                start_tag(&handle, &mut word);
+                if !VOID.contains(&&*name.local) {
+                    stack.push(Item::Close(Arc::clone(&handle)));
+                    stack.extend(children(&handle).into_iter().rev().map(Item::Open));
+                }
+            }
+            _ => word.push_str(&to_html(&handle, TraversalScope::IncludeNode)),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Greedily fill lines of given width with words separated by spaces
+fn wrap(words: &[String], width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
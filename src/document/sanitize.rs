@@ -0,0 +1,260 @@
+//! Allowlist based cleaning of untrusted documents
+//!
+use markup5ever_arcdom::{Handle, NodeData};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+
+use super::{Context, Document, Element};
+
+/// Elements removed together with their content when not allowed,
+/// other elements which are not allowed are replaced with their children
+const DROP_CONTENT: &[&str] = &[
+    "script",
+    "style",
+    "template",
+    "iframe",
+    "frame",
+    "frameset",
+    "object",
+    "embed",
+    "applet",
+    "noscript",
+    "noembed",
+    "noframes",
+    "xmp",
+    "plaintext",
+    "title",
+    "head",
+];
+
+/// Attributes holding urls checked against allowed schemes
+const URL_ATTRS: &[&str] = &[
+    "href",
+    "src",
+    "action",
+    "formaction",
+    "cite",
+    "poster",
+    "background",
+    "longdesc",
+    "manifest",
+    "data",
+    "codebase",
+];
+
+/// Allowlist based sanitizer removing everything that was not explicitly allowed:
+/// tags which are not allowed are replaced with their content (`<script>`, `<style>`
+/// and similar are removed with it), attributes which are not allowed are dropped,
+/// comments and processing instructions are removed. Event handler attributes (`on*`)
+/// are never kept and url attributes with schemes which are not allowed are dropped.
+///
+/// # Example
+/// ```
+/// use crabquery::{Document, Sanitizer};
+///
+/// let doc = Document::from(
+///     "<div onclick='steal()'><p>Hi <a href='javascript:alert(1)' title='x'>there</a></p>\
+///      <script>steal()</script><a href='https://example.com' class='ext'>link</a></div>",
+/// );
+/// let sanitizer = Sanitizer::new()
+///     .allow_tags(&["p", "a"])
+///     .allow_attrs(&["href", "title"]);
+///
+/// assert_eq!(
+///     sanitizer.sanitize(&doc).html(),
+///     "<p>Hi <a title=\"x\">there</a></p><a href=\"https://example.com\">link</a>"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct Sanitizer {
+    //{{{
+    tags: HashSet<String>,
+    attrs: HashSet<String>,
+    schemes: HashSet<String>,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sanitizer {
+    /// Create sanitizer allowing no tags and no attributes,
+    /// urls are allowed to be relative or to use `http`, `https` and `mailto` schemes
+    pub fn new() -> Self {
+        Self {
+            tags: HashSet::new(),
+            attrs: HashSet::new(),
+            schemes: ["http", "https", "mailto"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    /// Allow elements with given tag names
+    ///
+    /// # Arguments
+    /// * `tags` - tag names
+    pub fn allow_tags(mut self, tags: &[&str]) -> Self {
+        self.tags
+            .extend(tags.iter().map(|tag| tag.to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow attributes with given names on all allowed elements
+    ///
+    /// # Arguments
+    /// * `attrs` - attribute names
+    pub fn allow_attrs(mut self, attrs: &[&str]) -> Self {
+        self.attrs
+            .extend(attrs.iter().map(|attr| attr.to_ascii_lowercase()));
+        self
+    }
+
+    /// Replace schemes allowed in url attributes like `href` or `src`,
+    /// relative urls are always allowed
+    ///
+    /// # Arguments
+    /// * `schemes` - url schemes without colon, e.g. `https`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Sanitizer};
+    ///
+    /// let doc = Document::from("<a href='http://example.com'>one</a><a href='/two'>two</a>");
+    /// let sanitizer = Sanitizer::new()
+    ///     .allow_tags(&["a"])
+    ///     .allow_attrs(&["href"])
+    ///     .allow_url_schemes(&["https"]);
+    ///
+    /// assert_eq!(
+    ///     sanitizer.sanitize(&doc).html(),
+    ///     "<a>one</a><a href=\"/two\">two</a>"
+    /// );
+    /// ```
+    pub fn allow_url_schemes(mut self, schemes: &[&str]) -> Self {
+        self.schemes = schemes.iter().map(|s| s.to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Get sanitized copy of the document, original is not changed
+    ///
+    /// # Arguments
+    /// * `doc` - document to clean
+    pub fn sanitize(&self, doc: &Document) -> Document {
+        let copy = doc.clone();
+        self.clean(&copy);
+        copy
+    }
+
+    /// Sanitize the document in place, elements taken from it before
+    /// may end up detached
+    ///
+    /// # Arguments
+    /// * `doc` - document to clean
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::{Document, Sanitizer};
+    ///
+    /// let doc = Document::from("<p>text<img src='x' onerror='steal()'><!-- note --></p>");
+    /// Sanitizer::new().allow_tags(&["p", "img"]).allow_attrs(&["src"]).clean(&doc);
+    ///
+    /// assert_eq!(doc.html(), "<p>text<img src=\"x\"></p>");
+    /// ```
+    pub fn clean(&self, doc: &Document) {
+        self.clean_tree(&doc.doc.document, &doc.context);
+    }
+
+    /// Sanitize descendants of the element in place, the element itself is kept as is
+    ///
+    /// # Arguments
+    /// * `el` - element to clean
+    pub fn clean_element(&self, el: &Element) {
+        self.clean_tree(&el.handle, &el.context);
+    }
+
+    fn clean_tree(&self, root: &Handle, context: &Context) {
+        let mut stack = vec![Arc::clone(root)];
+
+        while let Some(parent) = stack.pop() {
+            context.record_children(&parent);
+            let mut queue: VecDeque<Handle> =
+                std::mem::take(&mut *parent.children.borrow_mut()).into();
+            let mut kept = vec![];
+
+            while let Some(node) = queue.pop_front() {
+                match node.data {
+                    NodeData::Element {
+                        ref name,
+                        ref attrs,
+                        ref template_contents,
+                        ..
+                    } => {
+                        let tag = name.local.to_ascii_lowercase().to_string();
+                        if self.tags.contains(&tag) {
+                            context.record_attrs(&node);
+                            attrs
+                                .borrow_mut()
+                                .retain(|attr| self.allowed_attr(&attr.name.local, &attr.value));
+                            if let Some(contents) = template_contents {
+                                stack.push(Arc::clone(contents));
+                            }
+                            stack.push(Arc::clone(&node));
+                            kept.push(node);
+                        } else if !DROP_CONTENT.contains(&tag.as_str()) {
+                            context.record_children(&node);
+                            let children = std::mem::take(&mut *node.children.borrow_mut());
+                            for child in children.into_iter().rev() {
+                                queue.push_front(child);
+                            }
+                        }
+                    }
+                    NodeData::Text { .. } | NodeData::Doctype { .. } => kept.push(node),
+                    _ => {}
+                }
+            }
+
+            for node in kept.iter() {
+                node.parent.set(Some(Arc::downgrade(&parent)));
+            }
+            *parent.children.borrow_mut() = kept;
+        }
+    }
+
+    fn allowed_attr(&self, name: &str, value: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        if name.starts_with("on") || !self.attrs.contains(&name) {
+            return false;
+        }
+
+        if URL_ATTRS.contains(&name.as_str()) {
+            return self.allowed_url(value);
+        }
+        if name == "srcset" {
+            return value
+                .split(',')
+                .filter_map(|candidate| candidate.split_whitespace().next())
+                .all(|url| self.allowed_url(url));
+        }
+
+        true
+    }
+
+    /// Check scheme of the url, browsers ignore whitespace and control characters in it
+    fn allowed_url(&self, url: &str) -> bool {
+        let url: String = url
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+            .collect();
+
+        match url.find([':', '/', '?', '#']) {
+            Some(i) if url[i..].starts_with(':') => {
+                self.schemes.contains(&url[..i].to_ascii_lowercase())
+            }
+            _ => true,
+        }
+    }
+} //}}}
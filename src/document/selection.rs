@@ -0,0 +1,406 @@
+//! Chainable list of elements returned by queries
+//!
+use std::collections::HashSet;
+use std::iter::FromIterator;
+use std::ops::{Bound, Deref, Index, RangeBounds};
+use std::slice;
+
+use super::{is_element, parent_handle, Element, Selector};
+
+/// Elements matched by `select`, in the order they were found. Can be indexed, iterated
+/// by value or by reference and dereferences to a slice of elements, so it works like
+/// the `Vec` it converts into. Refining methods return new selections so steps can be chained.
+///
+/// # Example
+/// ```
+/// use crabquery::Document;
+///
+/// let doc = Document::from("<p>one</p><p>two</p>");
+/// let paragraphs = doc.select("p");
+///
+/// assert_eq!(paragraphs.len(), 2);
+/// assert_eq!(paragraphs[1].text().unwrap(), "two");
+/// for p in &paragraphs {
+///     assert!(p.text().is_some());
+/// }
+/// let texts: Vec<_> = paragraphs.into_iter().map(|p| p.text().unwrap()).collect();
+/// assert_eq!(texts, vec!["one", "two"]);
+/// ```
+///
+/// ```
+/// use crabquery::Document;
+///
+/// let doc = Document::from(
+///     "<ul class='menu'><li><a href='/'>Home</a></li><li class='off'><a href='/a'>A</a></li>\
+///      <li><a href='/b'>B</a></li></ul><ul><li><a href='/c'>C</a></li></ul>",
+/// );
+/// let links = doc
+///     .select("li")
+///     .not(".off")
+///     .slice(..2)
+///     .find("a")
+///     .map(|a| a.attr("href").unwrap());
+///
+/// assert_eq!(links, vec!["/", "/b"]);
+/// ```
+#[derive(Clone, Default)]
+pub struct Selection {
+    //{{{
+    elements: Vec<Element>,
+}
+
+impl Selection {
+    /// Get number of elements
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Check if nothing was matched
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Get element at given position, `None` when there is none
+    ///
+    /// # Arguments
+    /// * `n` - position counting from zero
+    pub fn get(&self, n: usize) -> Option<&Element> {
+        self.elements.get(n)
+    }
+
+    /// Iterate over elements
+    pub fn iter(&self) -> slice::Iter<'_, Element> {
+        self.elements.iter()
+    }
+
+    /// Keep elements matching given css selector, the selector is applied to the whole
+    /// document, so `ul > li` keeps list items with `ul` parent
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p class='a'>one</p><p>two</p><div class='a'>three</div>");
+    /// let sel = doc.select(".a").filter("p");
+    ///
+    /// assert_eq!(sel.len(), 1);
+    /// assert_eq!(sel[0].text().unwrap(), "one");
+    /// ```
+    pub fn filter(&self, selector: &str) -> Selection {
+        let sel = Selector::compile(selector);
+        self.retain(|el| sel.matches(&el.handle))
+    }
+
+    /// Keep elements not matching given css selector
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p class='ad'>buy</p><p>text</p>");
+    /// let sel = doc.select("p").not(".ad");
+    ///
+    /// assert_eq!(sel.len(), 1);
+    /// assert_eq!(sel[0].text().unwrap(), "text");
+    /// ```
+    pub fn not(&self, selector: &str) -> Selection {
+        let sel = Selector::compile(selector);
+        self.retain(|el| !sel.matches(&el.handle))
+    }
+
+    /// Keep elements for which given function returns `true`
+    ///
+    /// # Arguments
+    /// * `predicate` - function called with every element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<span>10 €</span><span>12 $</span><span>8 €</span>");
+    /// let euro = doc.select("span").filter_by(|el| el.text().unwrap().contains('€'));
+    ///
+    /// assert_eq!(euro.texts(), vec!["10 €", "8 €"]);
+    /// ```
+    pub fn filter_by<F>(&self, predicate: F) -> Selection
+    where
+        F: FnMut(&Element) -> bool,
+    {
+        self.retain(predicate)
+    }
+
+    /// Get selection with only the element at given position, empty when there is none
+    ///
+    /// # Arguments
+    /// * `n` - position counting from zero
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<li>one</li><li>two</li>");
+    ///
+    /// assert_eq!(doc.select("li").eq(1)[0].text().unwrap(), "two");
+    /// assert!(doc.select("li").eq(2).is_empty());
+    /// ```
+    pub fn eq(&self, n: usize) -> Selection {
+        self.slice(n..n.saturating_add(1))
+    }
+
+    /// Get selection with elements at positions in given range,
+    /// positions past the end are ignored
+    ///
+    /// # Arguments
+    /// * `range` - range of positions, like `1..3` or `2..`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<li>1</li><li>2</li><li>3</li><li>4</li>");
+    ///
+    /// assert_eq!(doc.select("li").slice(1..3).map(|li| li.text().unwrap()), vec!["2", "3"]);
+    /// assert_eq!(doc.select("li").slice(3..10).len(), 1);
+    /// ```
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> Selection {
+        let len = self.elements.len();
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i.saturating_add(1),
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => len,
+        };
+        let end = end.min(len);
+
+        if start >= end {
+            return Selection::default();
+        }
+        Selection::from(self.elements[start..end].to_vec())
+    }
+
+    /// Select descendants of all elements matching given css selector,
+    /// elements found from several elements are included once
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div class='card'><b>one</b></div><div class='card'><b>two</b></div>");
+    /// let sel = doc.select(".card").find("b");
+    ///
+    /// assert_eq!(sel.map(|b| b.text().unwrap()), vec!["one", "two"]);
+    /// ```
+    pub fn find(&self, selector: &str) -> Selection {
+        let mut seen = HashSet::new();
+        self.elements
+            .iter()
+            .flat_map(|el| el.select(selector))
+            .filter(|el| seen.insert(el.clone()))
+            .collect()
+    }
+
+    /// Select ancestor elements of all elements, optionally only ones matching given
+    /// css selector. Every ancestor is included once, ancestors of the first element
+    /// come first starting from its parent.
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector ancestors have to match, `None` keeps all of them
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<div class='card'><p><b>one</b></p><p><b>two</b></p></div>");
+    /// let bold = doc.select("b");
+    ///
+    /// assert_eq!(bold.parents(Some("p")).len(), 2);
+    /// assert_eq!(bold.parents(Some(".card")).len(), 1);
+    /// assert_eq!(bold.parents(None).map(|el| el.tag().unwrap()), vec!["p", "div", "body", "html", "p"]);
+    /// ```
+    pub fn parents(&self, selector: Option<&str>) -> Selection {
+        let mut seen = HashSet::new();
+        let mut acc = vec![];
+
+        for el in self.elements.iter() {
+            let mut node = parent_handle(&el.handle);
+            while let Some(n) = node.filter(is_element) {
+                node = parent_handle(&n);
+                let parent = el.element(&n);
+                if seen.insert(parent.clone()) {
+                    acc.push(parent);
+                }
+            }
+        }
+
+        Selection::from(acc).refine(selector)
+    }
+
+    /// Select child elements of all elements, optionally only ones matching given css selector
+    ///
+    /// # Arguments
+    /// * `selector` - CSS selector children have to match, `None` keeps all of them
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>1</li><li class='on'>2</li></ul><ul><li class='on'>3</li></ul>");
+    /// let lists = doc.select("ul");
+    ///
+    /// assert_eq!(lists.children(None).texts(), vec!["1", "2", "3"]);
+    /// assert_eq!(lists.children(Some(".on")).texts(), vec!["2", "3"]);
+    /// ```
+    pub fn children(&self, selector: Option<&str>) -> Selection {
+        let mut seen = HashSet::new();
+        self.elements
+            .iter()
+            .flat_map(|el| el.children())
+            .filter(|el| seen.insert(el.clone()))
+            .collect::<Selection>()
+            .refine(selector)
+    }
+
+    /// Apply function to every element, collecting the results
+    ///
+    /// # Arguments
+    /// * `f` - function called with every element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a href='/one'>1</a><a href='/two'>2</a>");
+    ///
+    /// assert_eq!(doc.select("a").map(|a| a.attr("href")), vec![Some("/one".to_string()), Some("/two".to_string())]);
+    /// ```
+    pub fn map<T, F>(&self, f: F) -> Vec<T>
+    where
+        F: FnMut(&Element) -> T,
+    {
+        self.elements.iter().map(f).collect()
+    }
+
+    /// Get text of every element, same as `Element::text`
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<ul><li>one</li><li>two</li></ul>");
+    ///
+    /// assert_eq!(doc.select("li").texts(), vec!["one", "two"]);
+    /// ```
+    pub fn texts(&self) -> Vec<String> {
+        self.elements
+            .iter()
+            .map(|el| el.text().unwrap_or_default())
+            .collect()
+    }
+
+    /// Get values of an attribute, elements without the attribute are skipped
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<a href='/one'>1</a><a name='top'>2</a><a href='/three'>3</a>");
+    ///
+    /// assert_eq!(doc.select("a").attrs("href"), vec!["/one", "/three"]);
+    /// ```
+    pub fn attrs(&self, name: &str) -> Vec<String> {
+        self.elements
+            .iter()
+            .filter_map(|el| el.attr(name))
+            .collect()
+    }
+
+    /// Convert into vector of elements
+    pub fn into_vec(self) -> Vec<Element> {
+        self.elements
+    }
+
+    fn refine(self, selector: Option<&str>) -> Selection {
+        match selector {
+            Some(selector) => self.filter(selector),
+            None => self,
+        }
+    }
+
+    fn retain<F>(&self, mut f: F) -> Selection
+    where
+        F: FnMut(&Element) -> bool,
+    {
+        self.elements.iter().filter(|el| f(el)).cloned().collect()
+    }
+}
+
+impl Deref for Selection {
+    type Target = [Element];
+
+    fn deref(&self) -> &Self::Target {
+        &self.elements
+    }
+}
+
+impl Index<usize> for Selection {
+    type Output = Element;
+
+    /// Get element at given position, panics when there is none like indexing a `Vec` does
+    fn index(&self, n: usize) -> &Element {
+        &self.elements[n]
+    }
+}
+
+impl From<Vec<Element>> for Selection {
+    fn from(elements: Vec<Element>) -> Self {
+        Selection { elements }
+    }
+}
+
+impl From<Selection> for Vec<Element> {
+    fn from(selection: Selection) -> Self {
+        selection.elements
+    }
+}
+
+impl FromIterator<Element> for Selection {
+    fn from_iter<I: IntoIterator<Item = Element>>(iter: I) -> Self {
+        Selection {
+            elements: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl IntoIterator for Selection {
+    type Item = Element;
+    type IntoIter = std::vec::IntoIter<Element>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Selection {
+    type Item = &'a Element;
+    type IntoIter = slice::Iter<'a, Element>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.iter()
+    }
+} //}}}
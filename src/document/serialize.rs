@@ -0,0 +1,326 @@
+//! Serialization of nodes back to html
+//!
+use html5ever::serialize::{serialize, Serialize, SerializeOpts, Serializer, TraversalScope};
+use markup5ever_arcdom::{Handle, NodeData};
+use std::io;
+
+/// Node serializable with html5ever serializer, unlike `SerializableHandle` of arcdom
+/// it includes content of `<template>` elements and walks the tree without recursion
+pub(crate) struct SerializableNode<'a>(pub(crate) &'a Handle);
+
+enum Op {
+    Open(Handle),
+    Close(Handle),
+}
+
+impl Serialize for SerializableNode<'_> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: &mut S,
+        traversal_scope: TraversalScope,
+    ) -> io::Result<()> {
+        let mut stack = match traversal_scope {
+            TraversalScope::IncludeNode => vec![Op::Open(self.0.clone())],
+            TraversalScope::ChildrenOnly(_) => {
+                children(self.0).into_iter().rev().map(Op::Open).collect()
+            }
+        };
+
+        while let Some(op) = stack.pop() {
+            let handle = match op {
+                Op::Open(handle) => handle,
+                Op::Close(handle) => {
+                    if let NodeData::Element { ref name, .. } = handle.data {
+                        serializer.end_elem(name.clone())?;
+                    }
+                    continue;
+                }
+            };
+
+            match handle.data {
+                NodeData::Element {
+                    ref name,
+                    ref attrs,
+                    ..
+                } => {
+                    serializer.start_elem(
+                        name.clone(),
+                        attrs
+                            .borrow()
+                            .iter()
+                            .map(|attr| (&attr.name, &attr.value[..])),
+                    )?;
+                    stack.push(Op::Close(handle.clone()));
+                    stack.extend(children(&handle).into_iter().rev().map(Op::Open));
+                }
+                NodeData::Doctype { ref name, .. } => serializer.write_doctype(name)?,
+                NodeData::Text { ref contents } => serializer.write_text(&contents.borrow())?,
+                NodeData::Comment { ref contents } => serializer.write_comment(contents)?,
+                NodeData::ProcessingInstruction {
+                    ref target,
+                    ref contents,
+                } => serializer.write_processing_instruction(target, contents)?,
+                NodeData::Document => {
+                    stack.extend(children(&handle).into_iter().rev().map(Op::Open));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Child nodes, for templates the nodes of their content
+pub(crate) fn children(handle: &Handle) -> Vec<Handle> {
+    match handle.data {
+        NodeData::Element {
+            template_contents: Some(ref contents),
+            ..
+        } => contents.children.borrow().clone(),
+        _ => handle.children.borrow().clone(),
+    }
+}
+
+/// Serialize node or only its children into html string
+pub(crate) fn to_html(handle: &Handle, traversal_scope: TraversalScope) -> String {
+    let mut out = vec![];
+    let opts = SerializeOpts {
+        traversal_scope,
+        ..Default::default()
+    };
+
+    // writing into a vector can't fail
+    let _ = serialize(&mut out, &SerializableNode(handle), opts);
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Elements rendered as blocks or put on their own lines by pretty printing
+pub(crate) const BLOCK: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "blockquote",
+    "body",
+    "caption",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "meta",
+    "nav",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "pre",
+    "script",
+    "section",
+    "select",
+    "style",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "template",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "ul",
+];
+
+/// Elements written exactly as they are, whitespace in them is significant
+/// or their content is not html
+pub(crate) const VERBATIM: &[&str] = &[
+    "iframe",
+    "listing",
+    "noembed",
+    "noframes",
+    "noscript",
+    "plaintext",
+    "pre",
+    "script",
+    "style",
+    "textarea",
+    "xmp",
+];
+
+/// Elements serialized without end tag
+pub(crate) const VOID: &[&str] = &[
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+/// How text and attribute values are escaped when serializing
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Escaping {
+    /// Only characters with special meaning in html, the same as `Document::html` does
+    #[default]
+    Minimal,
+    /// Also every non-ascii character as numeric character reference, e.g. `&#xE9;`,
+    /// so the output is plain ascii. Content of `<script>`, `<style>` and other raw text
+    /// elements, comments and names are left as they are since references are not
+    /// decoded there.
+    Ascii,
+}
+
+/// Elements which content is not escaped on serialization
+const RAW_TEXT: &[&str] = &[
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "plaintext",
+    "noscript",
+];
+
+/// Serialize node or only its children with given escaping of text and attribute values
+pub(crate) fn to_html_with(
+    handle: &Handle,
+    traversal_scope: TraversalScope,
+    escaping: Escaping,
+) -> String {
+    if escaping == Escaping::Minimal {
+        return to_html(handle, traversal_scope);
+    }
+
+    let mut out = String::new();
+    let mut stack = match traversal_scope {
+        TraversalScope::IncludeNode => vec![Op::Open(handle.clone())],
+        TraversalScope::ChildrenOnly(_) => {
+            children(handle).into_iter().rev().map(Op::Open).collect()
+        }
+    };
+
+    while let Some(op) = stack.pop() {
+        let handle = match op {
+            Op::Open(handle) => handle,
+            Op::Close(handle) => {
+                end_tag(&handle, &mut out);
+                continue;
+            }
+        };
+
+        match handle.data {
+            NodeData::Element { ref name, .. } => {
+                start_tag_with(&handle, escaping, &mut out);
+                if !VOID.contains(&&*name.local) {
+                    stack.push(Op::Close(handle.clone()));
+                    stack.extend(children(&handle).into_iter().rev().map(Op::Open));
+                }
+            }
+            NodeData::Text { ref contents } => {
+                let raw = match super::parent_handle(&handle) {
+                    Some(parent) => match parent.data {
+                        NodeData::Element { ref name, .. } => RAW_TEXT.contains(&&*name.local),
+                        _ => false,
+                    },
+                    None => false,
+                };
+                if raw {
+                    out.push_str(&contents.borrow());
+                } else {
+                    escape(&contents.borrow(), false, escaping, &mut out);
+                }
+            }
+            NodeData::Document => {
+                stack.extend(children(&handle).into_iter().rev().map(Op::Open));
+            }
+            _ => out.push_str(&to_html(&handle, TraversalScope::IncludeNode)),
+        }
+    }
+
+    out
+}
+
+/// Write text escaped the same way html5ever serializer does
+pub(crate) fn escape_text(text: &str, out: &mut String) {
+    escape(text, false, Escaping::Minimal, out);
+}
+
+fn escape(text: &str, attr: bool, escaping: Escaping, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '\u{a0}' => out.push_str("&nbsp;"),
+            '"' if attr => out.push_str("&quot;"),
+            '<' if !attr => out.push_str("&lt;"),
+            '>' if !attr => out.push_str("&gt;"),
+            c if !c.is_ascii() && escaping == Escaping::Ascii => {
+                out.push_str(&format!("&#x{:X};", c as u32))
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+/// Write start tag of the element with its attributes
+pub(crate) fn start_tag(handle: &Handle, out: &mut String) {
+    start_tag_with(handle, Escaping::Minimal, out);
+}
+
+fn start_tag_with(handle: &Handle, escaping: Escaping, out: &mut String) {
+    if let NodeData::Element {
+        ref name,
+        ref attrs,
+        ..
+    } = handle.data
+    {
+        out.push('<');
+        out.push_str(&name.local);
+        for attr in attrs.borrow().iter() {
+            out.push(' ');
+            if let Some(ref prefix) = attr.name.prefix {
+                out.push_str(prefix);
+                out.push(':');
+            }
+            out.push_str(&attr.name.local);
+            out.push_str("=\"");
+            escape(&attr.value, true, escaping, out);
+            out.push('"');
+        }
+        out.push('>');
+    }
+}
+
+/// Write end tag of the element, nothing for void elements
+pub(crate) fn end_tag(handle: &Handle, out: &mut String) {
+    if let NodeData::Element { ref name, .. } = handle.data {
+        if !VOID.contains(&&*name.local) {
+            out.push_str("</");
+            out.push_str(&name.local);
+            out.push('>');
+        }
+    }
+}
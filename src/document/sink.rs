@@ -0,0 +1,938 @@
+//! Tree sink wrapping `ArcDom` to collect extra information during parsing
+//!
+use html5ever::driver::ParseOpts;
+use html5ever::tendril::{StrTendril, TendrilSink};
+use html5ever::tokenizer::{BufferQueue, TagToken, Token, TokenSink, TokenSinkResult, Tokenizer};
+use html5ever::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
+use html5ever::{parse_document, parse_fragment, Parser};
+use markup5ever::{namespace_url, ns, Attribute, ExpandedName, LocalName, QualName};
+use markup5ever_arcdom::{ArcDom, Handle, NodeData};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{
+    descendants, is_element, parent_handle, Context, Limit, ParseError, Selector, RAW_TEXT,
+};
+
+/// Limits guarding against adversarial input, none are set by default
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Limits {
+    pub(crate) input_size: Option<usize>,
+    pub(crate) depth: Option<usize>,
+    pub(crate) nodes: Option<usize>,
+}
+
+/// Incremental parser feeding input to html5ever in chunks of text and whole tags,
+/// that way the tag currently processed by the tree builder is always known.
+/// Input is scanned only once, text is passed on as soon as it arrives.
+/// Offsets are counted from the start of the whole input.
+pub(crate) struct Feeder {
+    parser: Parser<Sink>,
+    end_tags: HashMap<usize, (String, usize)>,
+    /// Start of input which is not fed to the parser yet
+    chunk_start: usize,
+    /// End of input already scanned for chunk boundaries
+    scanned: usize,
+    /// Column of `chunk_start` in characters, starting from 1
+    column: u64,
+    scan: Scan,
+    /// Noscript content is raw text only with scripting enabled
+    scripting: bool,
+    /// Trailing bytes of incomplete utf-8 sequence
+    pending: Vec<u8>,
+    /// Number of bytes fed so far
+    fed: usize,
+}
+
+/// Part of markup the scanner is in, follows the tokenizer closely enough
+/// to know where tags end
+enum Scan {
+    Data,
+    /// After `<` which may start a tag
+    Open,
+    /// After `</`
+    EndOpen,
+    /// After `<!`
+    Declaration,
+    Tag(Attr),
+    /// Comment which content starts at given offset
+    Comment(usize),
+    /// Doctype or bogus comment ending with the first `>`
+    Bogus,
+    /// Content of element which ends only with its end tag, plaintext never ends
+    RawText(Option<String>),
+}
+
+/// Position inside of a tag, attribute values can contain `>` when quoted
+#[derive(Clone, Copy)]
+enum Attr {
+    Name,
+    Equals,
+    Unquoted,
+    Quoted(u8),
+}
+
+enum Chunk {
+    /// Text ending at given offset
+    Text(usize),
+    /// Tag, comment or doctype ending at given offset
+    Markup(usize),
+}
+
+impl Feeder {
+    pub(crate) fn new(opts: ParseOpts, limits: Limits) -> Self {
+        let scripting = opts.tree_builder.scripting_enabled;
+        Self::with_parser(parse_document(Sink::new(limits), opts), scripting)
+    }
+
+    /// Parser of html fragment as if it was the content of given context element
+    pub(crate) fn fragment(opts: ParseOpts, limits: Limits, context: QualName) -> Self {
+        let scripting = opts.tree_builder.scripting_enabled;
+        let parser = parse_fragment(Sink::new(limits), opts, context, vec![]);
+        Self::with_parser(parser, scripting)
+    }
+
+    fn with_parser(parser: Parser<Sink>, scripting: bool) -> Self {
+        Self {
+            parser,
+            end_tags: HashMap::new(),
+            chunk_start: 0,
+            scanned: 0,
+            column: 1,
+            scan: Scan::Data,
+            scripting,
+            pending: vec![],
+            fed: 0,
+        }
+    }
+
+    /// Build only subtrees of elements matching any of the selectors and their ancestors
+    pub(crate) fn keep_only(&mut self, selectors: &[String]) {
+        self.parser.tokenizer.sink.sink.keep = selectors
+            .iter()
+            .map(|sel| Selector::from(sel.as_str()))
+            .collect();
+    }
+
+    /// Limit which made the parser stop, no more input is parsed after that
+    pub(crate) fn exceeded(&self) -> Option<Limit> {
+        self.parser.tokenizer.sink.sink.context.exceeded
+    }
+
+    pub(crate) fn feed(&mut self, input: &str) {
+        if self.exceeded().is_some() {
+            return;
+        }
+
+        let sink = &mut self.parser.tokenizer.sink.sink;
+        self.fed += input.len();
+        if let Some(max) = sink.limits.input_size {
+            if self.fed > max {
+                sink.exceed(Limit::InputSize(max));
+                return;
+            }
+        }
+        sink.input.push_str(input);
+
+        while let Some(chunk) = self.next_chunk() {
+            let sink = &mut self.parser.tokenizer.sink.sink;
+            let offset = sink.offset;
+            let (end, start) = match chunk {
+                Chunk::Text(end) => (end, end),
+                Chunk::Markup(end) => {
+                    let markup = &sink.input[self.chunk_start - offset..end - offset];
+                    if let Some(name) = end_tag_name(markup) {
+                        self.end_tags.insert(self.chunk_start, (name, end));
+                    }
+                    (end, self.chunk_start)
+                }
+            };
+
+            let text = &sink.input[self.chunk_start - offset..end - offset];
+            let column = column_after(self.column, text);
+            sink.column = match chunk {
+                Chunk::Text(_) => column,
+                Chunk::Markup(_) => self.column,
+            };
+            let text = StrTendril::from_slice(text);
+            sink.cursor = Cursor { start, end };
+            sink.raw_text = None;
+            self.parser.process(text);
+            self.chunk_start = end;
+            self.column = column;
+
+            if let Chunk::Markup(_) = chunk {
+                match self.parser.tokenizer.sink.sink.raw_text.take() {
+                    Some(name) if &*name == "plaintext" => self.scan = Scan::RawText(None),
+                    Some(name) if &*name != "noscript" || self.scripting => {
+                        self.scan = Scan::RawText(Some(name.to_string()))
+                    }
+                    _ => {}
+                }
+            }
+            if self.exceeded().is_some() {
+                break;
+            }
+        }
+
+        // keep only input which is not processed yet
+        let sink = &mut self.parser.tokenizer.sink.sink;
+        let processed = self.chunk_start - sink.offset;
+        sink.input.drain(..processed);
+        sink.offset += processed;
+    }
+
+    /// Scan input for the end of the next chunk to feed, `None` when more input is needed
+    fn next_chunk(&mut self) -> Option<Chunk> {
+        let sink = &self.parser.tokenizer.sink.sink;
+        let offset = sink.offset;
+        let len = offset + sink.input.len();
+
+        loop {
+            let i = self.scanned;
+            let rest = &sink.input.as_bytes()[i - offset..];
+            match self.scan {
+                Scan::Data => match rest.iter().position(|&b| b == b'<') {
+                    Some(pos) => {
+                        self.scanned = i + pos + 1;
+                        self.scan = Scan::Open;
+                        if i + pos > self.chunk_start {
+                            return Some(Chunk::Text(i + pos));
+                        }
+                    }
+                    None => {
+                        self.scanned = len;
+                        return Some(Chunk::Text(len)).filter(|_| len > self.chunk_start);
+                    }
+                },
+                Scan::Open => {
+                    self.scan = match rest.first()? {
+                        b'/' => Scan::EndOpen,
+                        b'!' => Scan::Declaration,
+                        b'?' => Scan::Bogus,
+                        b if b.is_ascii_alphabetic() => Scan::Tag(Attr::Name),
+                        // `<` is text
+                        _ => Scan::Data,
+                    };
+                    if matches!(self.scan, Scan::EndOpen | Scan::Declaration) {
+                        self.scanned += 1;
+                    }
+                }
+                Scan::EndOpen => {
+                    self.scan = match rest.first()? {
+                        b if *b == b'>' || b.is_ascii_alphabetic() => Scan::Tag(Attr::Name),
+                        _ => Scan::Bogus,
+                    };
+                }
+                Scan::Declaration => {
+                    if rest.starts_with(b"--") {
+                        self.scanned += 2;
+                        self.scan = Scan::Comment(i + 2);
+                    } else if b"--".starts_with(rest) {
+                        return None;
+                    } else {
+                        self.scan = Scan::Bogus;
+                    }
+                }
+                Scan::Tag(mut attr) => {
+                    for (pos, &b) in rest.iter().enumerate() {
+                        attr = match (attr, b) {
+                            (Attr::Quoted(q), b) if b == q => Attr::Name,
+                            (Attr::Quoted(q), _) => Attr::Quoted(q),
+                            (_, b'>') => {
+                                self.scanned = i + pos + 1;
+                                self.scan = Scan::Data;
+                                return Some(Chunk::Markup(i + pos + 1));
+                            }
+                            (Attr::Name, b'=') => Attr::Equals,
+                            (Attr::Equals, b'"') | (Attr::Equals, b'\'') => Attr::Quoted(b),
+                            (Attr::Equals, b) if b.is_ascii_whitespace() => Attr::Equals,
+                            (Attr::Equals, _) => Attr::Unquoted,
+                            (Attr::Unquoted, b) if b.is_ascii_whitespace() => Attr::Name,
+                            (attr, _) => attr,
+                        };
+                    }
+                    self.scanned = len;
+                    self.scan = Scan::Tag(attr);
+                    return None;
+                }
+                Scan::Comment(content) => {
+                    let input = sink.input.as_bytes();
+                    for (pos, _) in rest.iter().enumerate().filter(|(_, b)| **b == b'>') {
+                        // `<!-->` and `<!--->` are empty comments
+                        let text = &input[content - offset..i + pos - offset];
+                        if text.is_empty()
+                            || text == b"-"
+                            || text.ends_with(b"--")
+                            || text.ends_with(b"--!")
+                        {
+                            self.scanned = i + pos + 1;
+                            self.scan = Scan::Data;
+                            return Some(Chunk::Markup(i + pos + 1));
+                        }
+                    }
+                    self.scanned = len;
+                    return None;
+                }
+                Scan::Bogus => match rest.iter().position(|&b| b == b'>') {
+                    Some(pos) => {
+                        self.scanned = i + pos + 1;
+                        self.scan = Scan::Data;
+                        return Some(Chunk::Markup(i + pos + 1));
+                    }
+                    None => {
+                        self.scanned = len;
+                        return None;
+                    }
+                },
+                Scan::RawText(None) => {
+                    self.scanned = len;
+                    return Some(Chunk::Text(len)).filter(|_| len > self.chunk_start);
+                }
+                Scan::RawText(Some(ref name)) => {
+                    // text ends where the end tag of the element may start
+                    let (end, scanned) = match rest.windows(2).position(|w| w == b"</") {
+                        Some(pos) => match rest[pos + 2..].get(..=name.len()) {
+                            Some(tag) if !is_end_tag(tag, name) => {
+                                self.scanned = i + pos + 2;
+                                continue;
+                            }
+                            Some(_) => {
+                                self.scan = Scan::Tag(Attr::Name);
+                                (i + pos, i + pos + 2)
+                            }
+                            None => (i + pos, i + pos),
+                        },
+                        None if rest.ends_with(b"<") => (len - 1, len - 1),
+                        None => (len, len),
+                    };
+                    self.scanned = scanned;
+                    if end > self.chunk_start {
+                        return Some(Chunk::Text(end));
+                    }
+                    if end == scanned {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Feed raw bytes decoding them as utf-8
+    pub(crate) fn feed_utf8(&mut self, bytes: &[u8]) {
+        let decoded = decode_utf8(&mut self.pending, bytes);
+        self.feed(&decoded);
+    }
+
+    pub(crate) fn finish(mut self) -> (ArcDom, Context) {
+        if !self.pending.is_empty() {
+            self.feed(&char::REPLACEMENT_CHARACTER.to_string());
+        }
+
+        let sink = &mut self.parser.tokenizer.sink.sink;
+        let len = sink.offset + sink.input.len();
+        let rest = &sink.input[self.chunk_start - sink.offset..];
+        sink.column = column_after(self.column, rest);
+        let rest = StrTendril::from_slice(rest);
+        sink.cursor = Cursor {
+            start: len,
+            end: len,
+        };
+        if !rest.is_empty() && self.exceeded().is_none() {
+            self.parser.process(rest);
+        }
+
+        let (dom, mut context) = self.parser.finish();
+        close_ranges(&dom.document, &mut context, &self.end_tags);
+
+        (dom, context)
+    }
+}
+
+/// Get column following the text which starts at given column
+fn column_after(column: u64, text: &str) -> u64 {
+    match text.rfind('\n') {
+        Some(i) => text[i + 1..].chars().count() as u64 + 1,
+        None => column + text.chars().count() as u64,
+    }
+}
+
+/// Check if markup after `</` is the end tag with given name
+fn is_end_tag(markup: &[u8], name: &str) -> bool {
+    let (tag, next) = markup.split_at(name.len());
+    tag.eq_ignore_ascii_case(name.as_bytes())
+        && (next[0].is_ascii_whitespace() || next[0] == b'/' || next[0] == b'>')
+}
+
+fn end_tag_name(tag: &str) -> Option<String> {
+    let name: String = tag
+        .strip_prefix("</")?
+        .chars()
+        .take_while(|c| !c.is_whitespace() && *c != '/' && *c != '>')
+        .collect();
+
+    Some(name.to_ascii_lowercase())
+}
+
+/// Extend element ranges to cover their content and matching end tag,
+/// returns end of the node content. Tree is walked without recursion.
+fn close_ranges(
+    handle: &Handle,
+    context: &mut Context,
+    end_tags: &HashMap<usize, (String, usize)>,
+) -> usize {
+    // nodes being visited with index of the next child to visit and end of content seen so far
+    let start = context.source(handle).map_or(0, |s| s.range.end);
+    let mut stack = vec![(Arc::clone(handle), 0, start)];
+
+    loop {
+        let next = match stack.last_mut() {
+            Some((node, i, _)) => {
+                *i += 1;
+                child_or_template_contents(node, *i - 1)
+            }
+            None => return 0,
+        };
+        if let Some(child) = next {
+            let end = context.source(&child).map_or(0, |s| s.range.end);
+            stack.push((child, 0, end));
+            continue;
+        }
+
+        let (node, _, mut end) = match stack.pop() {
+            Some(frame) => frame,
+            None => return 0,
+        };
+        if let NodeData::Element { ref name, .. } = node.data {
+            match end_tags.get(&end) {
+                Some((tag, tag_end)) if *tag == *name.local => end = *tag_end,
+                _ => {}
+            }
+            context.set_source_end(&node, end);
+        }
+
+        match stack.last_mut() {
+            Some((_, _, parent_end)) => *parent_end = (*parent_end).max(end),
+            None => return end,
+        }
+    }
+}
+
+/// Get child of the node at given index, content of `<template>` follows its children
+fn child_or_template_contents(handle: &Handle, i: usize) -> Option<Handle> {
+    let children = handle.children.borrow();
+    if let Some(child) = children.get(i) {
+        return Some(Arc::clone(child));
+    }
+
+    match handle.data {
+        NodeData::Element {
+            template_contents: Some(ref contents),
+            ..
+        } if i == children.len() => Some(Arc::clone(contents)),
+        _ => None,
+    }
+}
+
+/// Get attributes of start tag markup including duplicates dropped by the parser,
+/// returns None when there are no duplicates or markup is not a start tag for given name
+fn raw_attributes(markup: &str, name: &str, parsed: usize) -> Option<Vec<(String, String)>> {
+    let rest = markup.strip_prefix('<')?;
+    let name_end = rest
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(rest.len());
+    if !rest[..name_end].eq_ignore_ascii_case(name) {
+        return None;
+    }
+
+    let attrs = scan_attributes(&rest[name_end..]);
+    if attrs.len() <= parsed {
+        return None;
+    }
+
+    // Let the tokenizer decode values, names are replaced so nothing is dropped as duplicate
+    let mut tag = "<x".to_string();
+    for (i, (_, value)) in attrs.iter().enumerate() {
+        tag.push_str(&format!(" a{}", i));
+        if let Some(value) = value {
+            tag.push('=');
+            tag.push_str(value);
+        }
+    }
+    tag.push('>');
+
+    let values = tokenize_attributes(&tag);
+    if values.len() != attrs.len() {
+        return None;
+    }
+
+    Some(
+        attrs
+            .into_iter()
+            .zip(values)
+            .map(|((name, _), value)| (name, value))
+            .collect(),
+    )
+}
+
+/// Split attributes part of a start tag into lowercased names and raw value markup
+fn scan_attributes(input: &str) -> Vec<(String, Option<&str>)> {
+    let bytes = input.as_bytes();
+    let mut res = vec![];
+    let mut i = 0;
+
+    let skip_ws = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    };
+
+    loop {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            break;
+        }
+
+        let start = i;
+        i += 1;
+        while i < bytes.len() && !matches!(bytes[i], b'>' | b'/' | b'=') {
+            if bytes[i].is_ascii_whitespace() {
+                break;
+            }
+            i += 1;
+        }
+        let name = input[start..i].to_ascii_lowercase();
+
+        let j = skip_ws(i);
+        if j >= bytes.len() || bytes[j] != b'=' {
+            res.push((name, None));
+            continue;
+        }
+
+        let value_start = skip_ws(j + 1);
+        i = value_start;
+        match bytes.get(i) {
+            Some(&q) if q == b'"' || q == b'\'' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != q {
+                    i += 1;
+                }
+                i = (i + 1).min(bytes.len());
+            }
+            _ => {
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+            }
+        }
+        res.push((name, Some(&input[value_start..i])));
+    }
+
+    res
+}
+
+/// Collect decoded attribute values of the first tag in the input
+fn tokenize_attributes(input: &str) -> Vec<String> {
+    struct AttrSink(Vec<String>);
+
+    impl TokenSink for AttrSink {
+        type Handle = ();
+
+        fn process_token(&mut self, token: Token, _line: u64) -> TokenSinkResult<()> {
+            if let TagToken(tag) = token {
+                if self.0.is_empty() {
+                    self.0 = tag.attrs.iter().map(|a| a.value.to_string()).collect();
+                }
+            }
+            TokenSinkResult::Continue
+        }
+    }
+
+    let mut queue = BufferQueue::new();
+    queue.push_back(StrTendril::from_slice(input));
+    let mut tokenizer = Tokenizer::new(AttrSink(vec![]), Default::default());
+    let _ = tokenizer.feed(&mut queue);
+    tokenizer.end();
+
+    tokenizer.sink.0
+}
+
+/// Byte offsets of the tag currently processed by the parser
+#[derive(Default)]
+struct Cursor {
+    start: usize,
+    end: usize,
+}
+
+pub(crate) struct Sink {
+    /// Input starting at `offset` which is still needed for the current tag
+    input: String,
+    offset: usize,
+    dom: ArcDom,
+    context: Context,
+    line: u64,
+    /// Column of the cursor start
+    column: u64,
+    cursor: Cursor,
+    /// Name of the last created element which content is raw text
+    raw_text: Option<LocalName>,
+    limits: Limits,
+    /// Number of nodes created so far
+    nodes: usize,
+    /// Selectors of subtrees to build, everything is built when empty
+    keep: Vec<Selector>,
+    /// Nodes kept when pruning keyed by address, flag tells if the whole subtree is kept
+    /// or the node is only an ancestor of kept subtree
+    kept: HashMap<usize, (Handle, bool)>,
+}
+
+impl Sink {
+    fn new(limits: Limits) -> Self {
+        Self {
+            input: String::new(),
+            offset: 0,
+            dom: ArcDom::default(),
+            context: Context::default(),
+            // html5ever starts counting lines from 1 and reports only changes
+            line: 1,
+            column: 1,
+            cursor: Cursor::default(),
+            raw_text: None,
+            limits,
+            nodes: 0,
+            keep: vec![],
+            kept: HashMap::new(),
+        }
+    }
+
+    fn in_kept_subtree(&self, handle: &Handle) -> bool {
+        matches!(
+            self.kept.get(&(Arc::as_ptr(handle) as usize)),
+            Some((_, true))
+        )
+    }
+
+    /// Check if child should be added to the parent when pruning, only elements are added
+    /// outside of kept subtrees as they can still contain matching elements.
+    /// Previous element child of the parent is closed at this point and is dropped
+    /// unless it is kept.
+    fn keep_child(&mut self, parent: &Handle, child: &NodeOrText<Handle>) -> bool {
+        if self.keep.is_empty() || self.in_kept_subtree(parent) {
+            return true;
+        }
+
+        match child {
+            NodeOrText::AppendNode(node) if is_element(node) => {
+                let last = parent.children.borrow().last().cloned();
+                if let Some(last) = last.filter(is_element) {
+                    if !self.kept.contains_key(&(Arc::as_ptr(&last) as usize)) {
+                        parent.children.borrow_mut().pop();
+                        self.prune(&last);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remember appended element when it matches or is inside of kept subtree
+    fn track(&mut self, parent: &Handle, child: &Handle) {
+        if self.keep.is_empty() || !is_element(child) {
+            return;
+        }
+        if !self.in_kept_subtree(parent) && !self.keep.iter().any(|sel| sel.matches(child)) {
+            return;
+        }
+
+        self.kept
+            .insert(Arc::as_ptr(child) as usize, (Arc::clone(child), true));
+        let mut node = parent_handle(child);
+        while let Some(ancestor) = node {
+            let key = Arc::as_ptr(&ancestor) as usize;
+            if self.kept.contains_key(&key) {
+                break;
+            }
+            node = parent_handle(&ancestor);
+            self.kept.insert(key, (ancestor, false));
+        }
+    }
+
+    /// Forget detached subtree
+    fn prune(&mut self, handle: &Handle) {
+        handle.parent.set(None);
+        self.context.remove_source(handle);
+        for node in descendants(handle) {
+            self.context.remove_source(&node);
+        }
+    }
+
+    /// Drop everything which is not kept once parsing is done
+    fn prune_unclosed(&mut self) {
+        let mut stack = vec![self.dom.document.clone()];
+
+        while let Some(node) = stack.pop() {
+            if self.in_kept_subtree(&node) {
+                continue;
+            }
+
+            let children = std::mem::take(&mut *node.children.borrow_mut());
+            for child in children {
+                let key = Arc::as_ptr(&child) as usize;
+                if self.kept.contains_key(&key) || matches!(child.data, NodeData::Doctype { .. }) {
+                    stack.push(Arc::clone(&child));
+                    node.children.borrow_mut().push(child);
+                } else {
+                    self.prune(&child);
+                }
+            }
+        }
+    }
+
+    fn exceed(&mut self, limit: Limit) {
+        if self.context.exceeded.is_none() {
+            self.context.exceeded = Some(limit);
+        }
+    }
+
+    fn count_node(&mut self) {
+        self.nodes += 1;
+        if let Some(max) = self.limits.nodes {
+            if self.nodes > max {
+                self.exceed(Limit::Nodes(max));
+            }
+        }
+    }
+
+    /// Check if child can be added to the parent, nothing is added once a limit is exceeded
+    fn allowed(&mut self, parent: &Handle, child: &NodeOrText<Handle>) -> bool {
+        if self.context.exceeded.is_some() {
+            return false;
+        }
+
+        if let (Some(max), NodeOrText::AppendNode(_)) = (self.limits.depth, child) {
+            let mut depth = 1;
+            let mut node = Arc::clone(parent);
+            while let Some(parent) = parent_handle(&node) {
+                depth += 1;
+                if depth > max {
+                    self.exceed(Limit::Depth(max));
+                    return false;
+                }
+                node = parent;
+            }
+        }
+
+        true
+    }
+
+    /// Content of the parent reaches at least the token currently processed
+    fn extend_parent(&mut self, parent: &Handle, child: &NodeOrText<Handle>) {
+        let end = match child {
+            NodeOrText::AppendText(_) => self.cursor.start,
+            NodeOrText::AppendNode(_) => self.cursor.end,
+        };
+        self.context.set_source_end(parent, end);
+    }
+}
+
+impl TreeSink for Sink {
+    type Handle = Handle;
+    type Output = (ArcDom, Context);
+
+    fn finish(mut self) -> Self::Output {
+        if !self.keep.is_empty() {
+            self.prune_unclosed();
+        }
+        (self.dom, self.context)
+    }
+
+    fn parse_error(&mut self, msg: Cow<'static, str>) {
+        self.context.errors.push(ParseError {
+            line: self.line,
+            message: msg.into_owned(),
+        });
+    }
+
+    fn get_document(&mut self) -> Handle {
+        self.dom.get_document()
+    }
+
+    fn elem_name<'a>(&'a self, target: &'a Handle) -> ExpandedName<'a> {
+        self.dom.elem_name(target)
+    }
+
+    fn create_element(
+        &mut self,
+        name: QualName,
+        attrs: Vec<Attribute>,
+        flags: ElementFlags,
+    ) -> Handle {
+        let markup = &self.input[self.cursor.start - self.offset..self.cursor.end - self.offset];
+        let raw_attrs = raw_attributes(markup, &name.local, attrs.len());
+
+        self.count_node();
+        self.raw_text = Some(name.local.clone())
+            .filter(|local| name.ns == ns!(html) && RAW_TEXT.contains(&&**local));
+        let handle = self.dom.create_element(name, attrs, flags);
+        let range = self.cursor.start..self.cursor.end;
+        self.context
+            .set_source(&handle, self.line, self.column, range);
+        if let Some(raw_attrs) = raw_attrs {
+            self.context.set_raw_attrs(&handle, raw_attrs);
+        }
+        handle
+    }
+
+    fn create_comment(&mut self, text: StrTendril) -> Handle {
+        self.count_node();
+        self.dom.create_comment(text)
+    }
+
+    fn create_pi(&mut self, target: StrTendril, data: StrTendril) -> Handle {
+        self.count_node();
+        self.dom.create_pi(target, data)
+    }
+
+    fn append(&mut self, parent: &Handle, child: NodeOrText<Handle>) {
+        if !self.allowed(parent, &child) || !self.keep_child(parent, &child) {
+            return;
+        }
+        self.extend_parent(parent, &child);
+
+        let node = match child {
+            NodeOrText::AppendNode(ref node) => Some(Arc::clone(node)),
+            NodeOrText::AppendText(_) => None,
+        };
+        self.dom.append(parent, child);
+        if let Some(node) = node {
+            self.track(parent, &node);
+        }
+    }
+
+    fn append_based_on_parent_node(
+        &mut self,
+        element: &Handle,
+        prev_element: &Handle,
+        child: NodeOrText<Handle>,
+    ) {
+        if parent_handle(element).is_some() {
+            self.append_before_sibling(element, child)
+        } else {
+            self.append(prev_element, child)
+        }
+    }
+
+    fn append_doctype_to_document(
+        &mut self,
+        name: StrTendril,
+        public_id: StrTendril,
+        system_id: StrTendril,
+    ) {
+        self.dom
+            .append_doctype_to_document(name, public_id, system_id)
+    }
+
+    fn get_template_contents(&mut self, target: &Handle) -> Handle {
+        let contents = self.dom.get_template_contents(target);
+        if self.in_kept_subtree(target) {
+            self.kept.insert(
+                Arc::as_ptr(&contents) as usize,
+                (Arc::clone(&contents), true),
+            );
+        }
+        contents
+    }
+
+    fn same_node(&self, x: &Handle, y: &Handle) -> bool {
+        Arc::ptr_eq(x, y)
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        self.dom.set_quirks_mode(mode)
+    }
+
+    fn append_before_sibling(&mut self, sibling: &Handle, new_node: NodeOrText<Handle>) {
+        let parent = parent_handle(sibling);
+        if let Some(ref parent) = parent {
+            if !self.allowed(parent, &new_node) {
+                return;
+            }
+            let keep = match new_node {
+                NodeOrText::AppendNode(ref node) if is_element(node) => true,
+                _ => self.keep.is_empty() || self.in_kept_subtree(parent),
+            };
+            if !keep {
+                return;
+            }
+            self.extend_parent(parent, &new_node);
+        }
+
+        let node = match new_node {
+            NodeOrText::AppendNode(ref node) => Some(Arc::clone(node)),
+            NodeOrText::AppendText(_) => None,
+        };
+        self.dom.append_before_sibling(sibling, new_node);
+        if let (Some(parent), Some(node)) = (parent, node) {
+            self.track(&parent, &node);
+        }
+    }
+
+    fn add_attrs_if_missing(&mut self, target: &Handle, attrs: Vec<Attribute>) {
+        self.dom.add_attrs_if_missing(target, attrs)
+    }
+
+    fn remove_from_parent(&mut self, target: &Handle) {
+        self.dom.remove_from_parent(target)
+    }
+
+    fn reparent_children(&mut self, node: &Handle, new_parent: &Handle) {
+        self.dom.reparent_children(node, new_parent)
+    }
+
+    fn is_mathml_annotation_xml_integration_point(&self, handle: &Handle) -> bool {
+        self.dom.is_mathml_annotation_xml_integration_point(handle)
+    }
+
+    fn set_current_line(&mut self, line_number: u64) {
+        self.line = line_number;
+    }
+}
+
+/// Decode bytes as utf-8 replacing invalid sequences,
+/// incomplete sequence at the end is kept in `pending` until more bytes arrive
+pub(crate) fn decode_utf8(pending: &mut Vec<u8>, bytes: &[u8]) -> String {
+    let mut input = std::mem::take(pending);
+    input.extend_from_slice(bytes);
+
+    let mut rest = &input[..];
+    let mut decoded = String::with_capacity(rest.len());
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                decoded.push_str(valid);
+                break;
+            }
+            Err(err) => {
+                let (valid, invalid) = rest.split_at(err.valid_up_to());
+                decoded.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                match err.error_len() {
+                    Some(len) => {
+                        decoded.push(char::REPLACEMENT_CHARACTER);
+                        rest = &invalid[len..];
+                    }
+                    None => {
+                        *pending = invalid.to_vec();
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    decoded
+}
@@ -0,0 +1,411 @@
+//! Selector matching over a stream of tokens without building the document tree
+//!
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::states::RawKind;
+use html5ever::tokenizer::{
+    BufferQueue, EndTag, StartTag, Tag, TagToken, Token, TokenSink, TokenSinkResult, Tokenizer,
+};
+use markup5ever::{Attribute, LocalName};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+
+use super::sink::decode_utf8;
+use super::{Matcher, Selector, SourceLocation};
+
+const VOID: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "keygen", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+/// Start tags of these elements close currently open `<p>`
+const CLOSES_P: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "details",
+    "dd",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Iterator over elements matching a selector in html read from any reader,
+/// input is tokenized chunk by chunk and only currently open elements are kept in memory.
+///
+/// Unlike `Document` no tree is built, so elements implied by the parser
+/// (like missing `<html>` or `<tbody>`) do not exist and misnested markup is not fixed up,
+/// only simple implied end tags (`<p>`, `<li>`, `<td>` and similar) are taken into account.
+///
+/// # Example
+/// ```
+/// use crabquery::SelectStream;
+///
+/// let html = "<ul><li><a href='/one'>one</a><li><a href='/two'>two</a></ul>";
+/// let hrefs: Vec<_> = SelectStream::new(html.as_bytes(), "li > a[href]")
+///     .map(|el| el.unwrap().attr("href").unwrap())
+///     .collect();
+///
+/// assert_eq!(hrefs, vec!["/one", "/two"]);
+/// ```
+pub struct SelectStream<R> {
+    //{{{
+    reader: Option<R>,
+    tokenizer: Tokenizer<StreamSink>,
+    queue: BufferQueue,
+    /// Trailing bytes of incomplete utf-8 sequence
+    pending: Vec<u8>,
+}
+
+impl<R: Read> SelectStream<R> {
+    /// Create iterator of elements matching selector
+    ///
+    /// # Arguments
+    /// * `reader` - source of utf-8 encoded html, invalid sequences are replaced
+    /// * `selector` - CSS selector
+    pub fn new(reader: R, selector: &str) -> Self {
+        let sink = StreamSink {
+            selector: Compound::parse(selector),
+            open: vec![],
+            root_children: 0,
+            matched: VecDeque::new(),
+        };
+
+        Self {
+            reader: Some(reader),
+            tokenizer: Tokenizer::new(sink, Default::default()),
+            queue: BufferQueue::new(),
+            pending: vec![],
+        }
+    }
+
+    fn feed(&mut self, input: String) {
+        self.queue.push_back(StrTendril::from(input));
+        let _ = self.tokenizer.feed(&mut self.queue);
+    }
+}
+
+impl<R: Read> Iterator for SelectStream<R> {
+    type Item = io::Result<StreamElement>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = [0; 8192];
+
+        loop {
+            if let Some(el) = self.tokenizer.sink.matched.pop_front() {
+                return Some(Ok(el));
+            }
+
+            let reader = self.reader.as_mut()?;
+            match reader.read(&mut buf) {
+                Ok(0) => {
+                    self.reader = None;
+                    if !self.pending.is_empty() {
+                        self.pending.clear();
+                        self.feed(char::REPLACEMENT_CHARACTER.to_string());
+                    }
+                    self.tokenizer.end();
+                }
+                Ok(n) => {
+                    let decoded = decode_utf8(&mut self.pending, &buf[..n]);
+                    self.feed(decoded);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    self.reader = None;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+} //}}}
+
+/// Element matched by `SelectStream`, only its tag and attributes are available
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamElement {
+    //{{{
+    name: String,
+    attrs: Vec<(String, String)>,
+    line: u64,
+}
+
+impl StreamElement {
+    /// Get tag name of the element
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::SelectStream;
+    ///
+    /// let html = "<div class='a'><span class='a'></span></div>";
+    /// let tags: Vec<_> = SelectStream::new(html.as_bytes(), ".a")
+    ///     .map(|el| el.unwrap().tag())
+    ///     .collect();
+    ///
+    /// assert_eq!(tags, vec!["div", "span"]);
+    /// ```
+    pub fn tag(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Get value of the attribute
+    ///
+    /// # Arguments
+    /// * `name` - attribute name
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::SelectStream;
+    ///
+    /// let html = "<a href='/about'>about</a>";
+    /// let el = SelectStream::new(html.as_bytes(), "a").next().unwrap().unwrap();
+    ///
+    /// assert_eq!(el.attr("href"), Some("/about".to_string()));
+    /// assert_eq!(el.attr("title"), None);
+    /// ```
+    pub fn attr(&self, name: &str) -> Option<String> {
+        self.attrs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    /// Get all attributes of the element in source order
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::SelectStream;
+    ///
+    /// let html = "<input type='text' name='q'>";
+    /// let el = SelectStream::new(html.as_bytes(), "input").next().unwrap().unwrap();
+    ///
+    /// assert_eq!(
+    ///     el.attrs(),
+    ///     vec![
+    ///         ("type".to_string(), "text".to_string()),
+    ///         ("name".to_string(), "q".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn attrs(&self) -> Vec<(String, String)> {
+        self.attrs.clone()
+    }
+
+    /// Get location of the element in the html input
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::SelectStream;
+    ///
+    /// let html = "<div>\n  <span>hi there</span>\n</div>";
+    /// let el = SelectStream::new(html.as_bytes(), "span").next().unwrap().unwrap();
+    ///
+    /// assert_eq!(el.source_location().line, 2);
+    /// ```
+    pub fn source_location(&self) -> SourceLocation {
+        SourceLocation {
+            line: self.line,
+            column: None,
+        }
+    }
+} //}}}
+
+/// Selector split into compound parts, each with a flag telling
+/// if it has to match parent of the element matched by the previous part
+struct Compound(Vec<(Matcher, bool)>);
+
+impl Compound {
+    fn parse(selector: &str) -> Self {
+        let mut parts = vec![];
+        let mut direct_match = false;
+
+        for matcher in Selector::from(selector).matchers {
+            if matcher.direct_match {
+                direct_match = true;
+                continue;
+            }
+            parts.push((matcher, direct_match));
+            direct_match = false;
+        }
+
+        Compound(parts)
+    }
+
+    fn matches(&self, open: &[Open]) -> bool {
+        Self::matches_parts(&self.0, open)
+    }
+
+    fn matches_parts(parts: &[(Matcher, bool)], open: &[Open]) -> bool {
+        let ((matcher, direct_match), parts) = match parts.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+        let (el, ancestors) = match open.split_last() {
+            Some(split) => split,
+            None => return false,
+        };
+
+        if !matcher.matches_parts(&el.name, &el.attrs, || Some(el.index)) {
+            return false;
+        }
+        if parts.is_empty() {
+            return true;
+        }
+        if *direct_match {
+            return Self::matches_parts(parts, ancestors);
+        }
+
+        (1..=ancestors.len())
+            .rev()
+            .any(|i| Self::matches_parts(parts, &ancestors[..i]))
+    }
+}
+
+/// Currently open element
+struct Open {
+    name: LocalName,
+    attrs: Vec<Attribute>,
+    /// Position among sibling elements
+    index: usize,
+    /// Number of child elements seen so far
+    children: usize,
+    /// Element is inside of `<svg>` or `<math>`
+    foreign: bool,
+}
+
+struct StreamSink {
+    selector: Compound,
+    open: Vec<Open>,
+    root_children: usize,
+    matched: VecDeque<StreamElement>,
+}
+
+impl StreamSink {
+    fn start_tag(&mut self, tag: Tag, line: u64) -> TokenSinkResult<()> {
+        while let Some(current) = self.open.last() {
+            if current.foreign || !closes(&tag.name, &current.name) {
+                break;
+            }
+            self.open.pop();
+        }
+
+        let (index, foreign) = match self.open.last_mut() {
+            Some(parent) => {
+                parent.children += 1;
+                (parent.children - 1, parent.foreign)
+            }
+            None => {
+                self.root_children += 1;
+                (self.root_children - 1, false)
+            }
+        };
+        let name = tag.name.clone();
+        let foreign = foreign || &*name == "svg" || &*name == "math";
+
+        self.open.push(Open {
+            name: tag.name,
+            attrs: tag.attrs,
+            index,
+            children: 0,
+            foreign,
+        });
+
+        if self.selector.matches(&self.open) {
+            if let Some(el) = self.open.last() {
+                self.matched.push_back(StreamElement {
+                    name: el.name.to_string(),
+                    attrs: el
+                        .attrs
+                        .iter()
+                        .map(|a| (a.name.local.to_string(), a.value.to_string()))
+                        .collect(),
+                    line,
+                });
+            }
+        }
+
+        if foreign {
+            if tag.self_closing {
+                self.open.pop();
+            }
+            return TokenSinkResult::Continue;
+        }
+        if VOID.contains(&&*name) {
+            self.open.pop();
+            return TokenSinkResult::Continue;
+        }
+
+        match &*name {
+            "script" => TokenSinkResult::RawData(RawKind::ScriptData),
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => {
+                TokenSinkResult::RawData(RawKind::Rawtext)
+            }
+            "title" | "textarea" => TokenSinkResult::RawData(RawKind::Rcdata),
+            "plaintext" => TokenSinkResult::Plaintext,
+            _ => TokenSinkResult::Continue,
+        }
+    }
+
+    fn end_tag(&mut self, tag: Tag) {
+        if let Some(i) = self.open.iter().rposition(|el| el.name == tag.name) {
+            self.open.truncate(i);
+        }
+    }
+}
+
+impl TokenSink for StreamSink {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, line: u64) -> TokenSinkResult<()> {
+        match token {
+            TagToken(tag) if tag.kind == StartTag => self.start_tag(tag, line),
+            TagToken(tag) if tag.kind == EndTag => {
+                self.end_tag(tag);
+                TokenSinkResult::Continue
+            }
+            _ => TokenSinkResult::Continue,
+        }
+    }
+}
+
+/// Check if start tag closes currently open element
+fn closes(tag: &str, open: &str) -> bool {
+    match open {
+        "p" => CLOSES_P.contains(&tag),
+        "li" => tag == "li",
+        "dt" | "dd" => tag == "dt" || tag == "dd",
+        "option" => tag == "option" || tag == "optgroup",
+        "optgroup" => tag == "optgroup",
+        "td" | "th" => matches!(tag, "td" | "th" | "tr" | "tbody" | "thead" | "tfoot"),
+        "tr" => matches!(tag, "tr" | "tbody" | "thead" | "tfoot"),
+        "tbody" | "thead" | "tfoot" => matches!(tag, "tbody" | "thead" | "tfoot"),
+        _ => false,
+    }
+}
@@ -0,0 +1,188 @@
+//! Undo journal of document mutations
+//!
+use markup5ever::Attribute;
+use markup5ever_arcdom::{Handle, NodeData};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::{index, parent_handle, Context};
+
+/// State of a node before it was changed
+enum Undo {
+    Children(Handle, Vec<Handle>),
+    Attrs(Handle, Vec<Attribute>),
+}
+
+/// Mutations recorded while any transaction is open
+#[derive(Default)]
+pub(crate) struct Journal {
+    /// Ids of open transactions, innermost last
+    open: Vec<u64>,
+    next_id: u64,
+    entries: Vec<Undo>,
+}
+
+impl Context {
+    /// Remember children of the node before they are changed
+    pub(crate) fn record_children(&self, handle: &Handle) {
+        index::touch();
+        let mut journal = self.journal.borrow_mut();
+        if !journal.open.is_empty() {
+            let children = handle.children.borrow().clone();
+            journal
+                .entries
+                .push(Undo::Children(Arc::clone(handle), children));
+        }
+    }
+
+    /// Remember children of the parent of the node before the node is moved
+    pub(crate) fn record_parent(&self, handle: &Handle) {
+        if let Some(parent) = parent_handle(handle) {
+            self.record_children(&parent);
+        }
+    }
+
+    /// Remember attributes of the element before they are changed
+    pub(crate) fn record_attrs(&self, handle: &Handle) {
+        index::touch();
+        let mut journal = self.journal.borrow_mut();
+        if !journal.open.is_empty() {
+            if let NodeData::Element { ref attrs, .. } = handle.data {
+                let attrs = attrs.borrow().clone();
+                journal.entries.push(Undo::Attrs(Arc::clone(handle), attrs));
+            }
+        }
+    }
+}
+
+/// Transaction recording changes of a document so they can be undone, started with
+/// `Document::begin`. Changes made through the document and its elements are recorded,
+/// including insertion of elements moved in from other documents, their removal from the
+/// other document is recorded by transactions of that document. Transaction which is dropped without
+/// `commit` is rolled back. Transactions can be nested, rolling back the outer one undoes
+/// changes committed by the inner ones too. Finishing a transaction ends the ones started
+/// inside of it which are still open, they do nothing when finished afterwards.
+///
+/// # Example
+/// ```
+/// use crabquery::Document;
+///
+/// let doc = Document::from("<ul><li>one</li><li class='ad'>buy</li></ul>");
+///
+/// let tx = doc.begin();
+/// doc.select_first(".ad").unwrap().remove();
+/// doc.select_first("li").unwrap().set_text("changed");
+/// tx.rollback();
+///
+/// assert_eq!(doc.select("li").len(), 2);
+/// assert_eq!(doc.select_first("li").unwrap().text().unwrap(), "one");
+/// ```
+#[must_use = "transaction is rolled back when dropped"]
+pub struct Transaction {
+    //{{{
+    context: Rc<Context>,
+    id: u64,
+    /// Journal entries made before the transaction started
+    start: usize,
+    finished: bool,
+}
+
+impl Transaction {
+    pub(crate) fn new(context: &Rc<Context>) -> Self {
+        let mut journal = context.journal.borrow_mut();
+        let id = journal.next_id;
+        journal.next_id += 1;
+        journal.open.push(id);
+
+        Transaction {
+            context: Rc::clone(context),
+            id,
+            start: journal.entries.len(),
+            finished: false,
+        }
+    }
+
+    /// Keep changes made during the transaction
+    ///
+    /// # Example
+    /// ```
+    /// use crabquery::Document;
+    ///
+    /// let doc = Document::from("<p>text</p>");
+    /// let tx = doc.begin();
+    /// doc.select_first("p").unwrap().set_attr("class", "note");
+    /// tx.commit();
+    ///
+    /// assert_eq!(doc.select(".note").len(), 1);
+    /// ```
+    pub fn commit(mut self) {
+        self.finish(false);
+    }
+
+    /// Undo changes made during the transaction, elements taken from the document
+    /// stay valid and point to restored nodes
+    pub fn rollback(mut self) {
+        self.finish(true);
+    }
+
+    fn finish(&mut self, undo: bool) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        let mut journal = self.context.journal.borrow_mut();
+        // transaction was ended together with an enclosing one
+        let level = match journal.open.iter().position(|id| *id == self.id) {
+            Some(level) => level,
+            None => return,
+        };
+        journal.open.truncate(level);
+        let entries = if undo {
+            journal.entries.split_off(self.start)
+        } else {
+            vec![]
+        };
+        if journal.open.is_empty() {
+            journal.entries.clear();
+        }
+        drop(journal);
+        if !entries.is_empty() {
+            index::touch();
+        }
+
+        for entry in entries.into_iter().rev() {
+            match entry {
+                Undo::Children(parent, children) => restore_children(&parent, children),
+                Undo::Attrs(handle, old) => {
+                    if let NodeData::Element { ref attrs, .. } = handle.data {
+                        *attrs.borrow_mut() = old;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        self.finish(true);
+    }
+} //}}}
+
+/// Put back children of the node, nodes which are not among them anymore
+/// are detached unless they were moved somewhere else already
+fn restore_children(parent: &Handle, children: Vec<Handle>) {
+    let removed = std::mem::replace(&mut *parent.children.borrow_mut(), children);
+
+    for node in removed.iter() {
+        if let Some(current) = parent_handle(node) {
+            if Arc::ptr_eq(&current, parent) {
+                node.parent.set(None);
+            }
+        }
+    }
+    for node in parent.children.borrow().iter() {
+        node.parent.set(Some(Arc::downgrade(parent)));
+    }
+}
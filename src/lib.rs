@@ -6,10 +6,20 @@
 //! * id based `#mainbutton`
 //! * direct child `>`
 //! * attribute selectors `[href]`, `[href="specific-value"]`, `[href*="contains-str"]`,
-//! `[href^="begins-with"]`,, `[href$="ends-with"]`
+//!   `[href^="begins-with"]`, `[href$="ends-with"]`
+//! * position based `:nth-child(3)`
 //! * all combinations of above like `div.container > form#feedback input.button`
 #![crate_name = "crabquery"]
 
 mod document;
 
 pub use document::*;
+
+#[cfg(feature = "encoding_rs")]
+pub use encoding_rs;
+
+#[cfg(feature = "serde_json")]
+pub use serde_json;
+
+#[cfg(feature = "regex")]
+pub use regex;
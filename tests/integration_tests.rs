@@ -21,3 +21,47 @@ fn test_docs_rs_index() {
     let els = document.select("a[href]");
     assert!(els.len() > 20);
 }
+
+#[test]
+fn test_docs_rs_source_ranges() {
+    let html = include_str!("fixtures/docs_rs.html");
+    let document = Document::from(html);
+
+    for el in document.select("a") {
+        let markup = &html[el.source_range().unwrap()];
+        assert!(markup.starts_with("<a"), "{}", markup);
+        assert!(markup.ends_with("</a>"), "{}", markup);
+    }
+}
+
+#[test]
+fn test_docs_rs_from_reader() {
+    let html = include_str!("fixtures/docs_rs.html");
+    let document = Document::from(html);
+    let streamed = Document::from_reader(html.as_bytes()).unwrap();
+
+    let els = document.select("a[href]");
+    let streamed_els = streamed.select("a[href]");
+    assert_eq!(els.len(), streamed_els.len());
+
+    for (el, streamed_el) in els.iter().zip(streamed_els.iter()) {
+        assert_eq!(el.attr("href"), streamed_el.attr("href"));
+        assert_eq!(el.source_range(), streamed_el.source_range());
+    }
+}
+
+#[test]
+fn test_docs_rs_select_stream() {
+    let html = include_str!("fixtures/docs_rs.html");
+    let document = Document::from(html);
+
+    let hrefs: Vec<_> = document
+        .select("a[href]")
+        .iter()
+        .map(|el| el.attr("href"))
+        .collect();
+    let streamed: Vec<_> = SelectStream::new(html.as_bytes(), "a[href]")
+        .map(|el| el.unwrap().attr("href"))
+        .collect();
+    assert_eq!(hrefs, streamed);
+}